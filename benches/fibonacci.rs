@@ -0,0 +1,40 @@
+#![feature(test)]
+
+extern crate test;
+
+use lynxlang::compiler::Compiler;
+use lynxlang::env::Env;
+use lynxlang::evaluator::Evaluator;
+use lynxlang::object::Object;
+use lynxlang::parser::Parser;
+use lynxlang::vm::Vm;
+use std::cell::RefCell;
+use std::rc::Rc;
+use test::Bencher;
+
+const FIBONACCI: &str = r#"
+    fn fibonacci(x) {
+        if (x < 2) {
+            return x;
+        }
+        return fibonacci(x - 1) + fibonacci(x - 2);
+    }
+    fibonacci(20);
+"#;
+
+#[bench]
+fn evaluator_fibonacci(b: &mut Bencher) {
+    let program = Parser::get(FIBONACCI).parse_program();
+    let mut evaluator = Evaluator::new(Rc::new(RefCell::new(Env::new())));
+
+    b.iter(|| assert_eq!(evaluator.eval_program(program.clone()), Some(Object::Integer(6765))));
+}
+
+#[bench]
+fn vm_fibonacci(b: &mut Bencher) {
+    let program = Parser::get(FIBONACCI).parse_program();
+    let bytecode = Compiler::new().compile(program).unwrap();
+    let mut vm = Vm::new();
+
+    b.iter(|| assert_eq!(vm.run(&bytecode), Some(Object::Integer(6765))));
+}
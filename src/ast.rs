@@ -9,8 +9,13 @@ pub type HashValue = Expression;
 pub type FnName = Box<Expression>;
 pub type FnParameter = Vec<Expression>;
 pub type FnBody = Statement;
+pub type MethodReceiver = Box<Expression>;
 pub type IfCondition = Box<Expression>;
 pub type WhileCondition = Box<Expression>;
+pub type AssignTarget = Box<Expression>;
+pub type AssignValue = Box<Expression>;
+pub type ScopeDepth = usize;
+pub type SlotIndex = usize;
 
 #[derive(Debug, Clone)]
 pub enum AstNode {
@@ -30,8 +35,14 @@ pub enum Expression {
     // foo
     Identifier(String),
 
+    // foo, resolved to a slot of the scope `ScopeDepth` levels up
+    Local(String, ScopeDepth, SlotIndex),
+
     // 1234
-    Integer(usize),
+    Integer(i64),
+
+    // 12.5
+    Float(f64),
 
     // true | false
     Boolean(bool),
@@ -68,6 +79,18 @@ pub enum Expression {
     // a(1 + 1, 2, b(1))
     FnCall(FnName, FnParameter),
 
+    // <expression>.<identifier>(<expression>, <expression>, ...)
+    // file.read(10)
+    MethodCall(MethodReceiver, String, FnParameter),
+
+    // <expression>.<identifier>
+    // math.PI, the value of a string key of a hash
+    Property(MethodReceiver, String),
+
+    // <identifier> = <expression>
+    // updates the nearest existing binding instead of declaring a new one
+    Assign(AssignTarget, AssignValue),
+
     // (token_position, token_type)
     NON_PARSED_EXPR((usize, TokenType)),
 }
@@ -90,6 +113,7 @@ pub enum Statement {
 #[derive(Debug, Eq, PartialEq, PartialOrd)]
 pub enum Precedence {
     Lowest,
+    Assign,   // =
     Equals,   // ==
     Compare,  // < or >
     Addition, // + or  -
@@ -105,10 +129,13 @@ impl Precedence {
             TokenType::ADD | TokenType::MINUS => Precedence::Addition,
             TokenType::MULTIPLY | TokenType::DIVIDE => Precedence::Multiply,
             TokenType::BANG => Precedence::Prefix, // | TokenType::MINUS
-            TokenType::LEFT_PAREN => Precedence::Group,
+            TokenType::LEFT_PAREN | TokenType::DOT => Precedence::Group,
             TokenType::LEFT_BRACE => Precedence::Index,
-            TokenType::EQUAL_EQUAL | TokenType::LESS_EQUAL | TokenType::GREATER_EQUAL => Precedence::Equals,
-            TokenType::LESS | TokenType::GREATER => Precedence::Compare,
+            TokenType::EQUAL_EQUAL | TokenType::BANG_EQUAL => Precedence::Equals,
+            TokenType::LESS | TokenType::GREATER | TokenType::LESS_EQUAL | TokenType::GREATER_EQUAL => {
+                Precedence::Compare
+            }
+            TokenType::ASSIGN => Precedence::Assign,
             _ => Precedence::Lowest,
         }
     }
@@ -1,53 +1,132 @@
-use crate::object::Object;
+use crate::evaluator::Evaluator;
+use crate::object::{BuiltinFunction, Object, RuntimeError};
 use std::collections::HashMap;
 
+mod array;
+mod hash;
+mod io;
+mod json;
+mod math;
+mod random;
+mod string;
+
+type BuiltinResult = Result<Object, RuntimeError>;
+type BuiltinFn = fn(&mut Evaluator, &[Object]) -> BuiltinResult;
+
 pub fn make_builtin() -> HashMap<String, Object> {
     let mut builtin_map = HashMap::new();
 
-    builtin_map.insert("len".to_string(), Object::Builtin(len));
-    builtin_map.insert("first".to_string(), Object::Builtin(first));
-    builtin_map.insert("last".to_string(), Object::Builtin(last));
-    builtin_map.insert("rest".to_string(), Object::Builtin(rest));
-    builtin_map.insert("push".to_string(), Object::Builtin(push));
-    builtin_map.insert("unshift".to_string(), Object::Builtin(unshift));
-    builtin_map.insert("print".to_string(), Object::Builtin(print));
+    let mut insert = |name: &str, arity: Option<usize>, func: BuiltinFn| {
+        builtin_map.insert(
+            name.to_string(),
+            Object::Builtin(BuiltinFunction::new(name, arity, func)),
+        );
+    };
+
+    insert("len", Some(1), len);
+    insert("first", Some(1), first);
+    insert("last", Some(1), last);
+    insert("rest", Some(1), rest);
+    insert("push", Some(2), push);
+    insert("unshift", Some(2), unshift);
+    insert("str", Some(1), str);
+    insert("repr", Some(1), repr);
+
+    for &(name, arity, func) in array::FUNCTIONS
+        .iter()
+        .chain(hash::FUNCTIONS)
+        .chain(string::FUNCTIONS)
+        .chain(random::FUNCTIONS)
+        .chain(json::FUNCTIONS)
+        .chain(io::FUNCTIONS)
+    {
+        insert(name, arity, func);
+    }
+
+    builtin_map.insert(String::from("math"), math::namespace());
 
     builtin_map
 }
 
-fn len(params: Vec<Object>) -> Object {
-    match params.first() {
-        Some(Object::Array(arr)) => Object::Integer(arr.len()),
-        _ => Object::Null,
+// the error of a builtin called with an argument of the wrong type, `position` counts from 1
+fn argument_error(name: &str, position: usize, expected: &str, got: &Object) -> RuntimeError {
+    RuntimeError::new(format!(
+        "argument {} of {}: expected {}, got {}",
+        position,
+        name,
+        expected,
+        got.type_name()
+    ))
+}
+
+fn integer_argument(name: &str, position: usize, params: &[Object]) -> Result<i64, RuntimeError> {
+    match &params[position - 1] {
+        Object::Integer(int) => Ok(*int),
+        other => Err(argument_error(name, position, "integer", other)),
     }
 }
 
-fn first(params: Vec<Object>) -> Object {
-    match params.first() {
+// an integer that counts or indexes something, a negative one is an error
+fn count_argument(name: &str, position: usize, params: &[Object]) -> Result<usize, RuntimeError> {
+    let int = integer_argument(name, position, params)?;
+
+    usize::try_from(int).map_err(|_| {
+        RuntimeError::new(format!(
+            "argument {} of {}: expected a non-negative integer, got {}",
+            position, name, int
+        ))
+    })
+}
+
+// for builtins with optional arguments, the arity of the others is checked on the call
+fn check_arguments(name: &str, params: &[Object], min: usize, max: usize) -> Result<(), RuntimeError> {
+    match (min..=max).contains(&params.len()) {
+        true => Ok(()),
+        false => Err(RuntimeError::new(format!(
+            "wrong number of arguments for {}: expected {} to {}, got {}",
+            name,
+            min,
+            max,
+            params.len()
+        ))),
+    }
+}
+
+fn len(_: &mut Evaluator, params: &[Object]) -> BuiltinResult {
+    Ok(match params.first() {
+        Some(Object::Array(arr)) => Object::Integer(arr.len() as i64),
+        Some(Object::Hash(pairs)) => Object::Integer(pairs.len() as i64),
+        Some(Object::String(string)) => Object::Integer(string.chars().count() as i64),
+        _ => Object::Null,
+    })
+}
+
+fn first(_: &mut Evaluator, params: &[Object]) -> BuiltinResult {
+    Ok(match params.first() {
         Some(Object::Array(arr)) => arr.first().unwrap_or(&Object::Null).clone(),
         _ => Object::Null,
-    }
+    })
 }
 
-fn last(params: Vec<Object>) -> Object {
-    match params.first() {
+fn last(_: &mut Evaluator, params: &[Object]) -> BuiltinResult {
+    Ok(match params.first() {
         Some(Object::Array(arr)) => arr.last().unwrap_or(&Object::Null).clone(),
         _ => Object::Null,
-    }
+    })
 }
 
-fn rest(params: Vec<Object>) -> Object {
-    match params.first() {
+fn rest(_: &mut Evaluator, params: &[Object]) -> BuiltinResult {
+    Ok(match params.first() {
         Some(Object::Array(arr)) => match arr.split_first() {
             Some((_, elements)) => Object::Array(elements.to_vec()),
             None => Object::Null,
         },
         _ => Object::Null,
-    }
+    })
 }
 
-fn push(params: Vec<Object>) -> Object {
-    match params.first() {
+fn push(_: &mut Evaluator, params: &[Object]) -> BuiltinResult {
+    Ok(match params.first() {
         Some(Object::Array(arr)) => {
             let mut vec = vec![];
             match params.get(1) {
@@ -61,11 +140,11 @@ fn push(params: Vec<Object>) -> Object {
             }
         }
         _ => Object::Null,
-    }
+    })
 }
 
-fn unshift(params: Vec<Object>) -> Object {
-    match params.first() {
+fn unshift(_: &mut Evaluator, params: &[Object]) -> BuiltinResult {
+    Ok(match params.first() {
         Some(Object::Array(arr)) => {
             let mut vec = vec![];
             match params.get(1) {
@@ -79,18 +158,13 @@ fn unshift(params: Vec<Object>) -> Object {
             }
         }
         _ => Object::Null,
-    }
+    })
 }
 
-fn print(params: Vec<Object>) -> Object {
-    match params.first() {
-        Some(obj) => {
-            println!("{:?}", obj);
-        }
-        None => {
-            println!("nothing for print");
-        }
-    }
+fn str(_: &mut Evaluator, params: &[Object]) -> BuiltinResult {
+    Ok(Object::String(params[0].to_string()))
+}
 
-    Object::Null
+fn repr(_: &mut Evaluator, params: &[Object]) -> BuiltinResult {
+    Ok(Object::String(params[0].repr()))
 }
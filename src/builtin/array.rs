@@ -0,0 +1,421 @@
+use std::cmp::Ordering;
+use std::collections::HashSet;
+
+use super::{argument_error, check_arguments, count_argument, integer_argument, BuiltinFn, BuiltinResult};
+use crate::evaluator::Evaluator;
+use crate::object::{Object, RuntimeError};
+
+// Array helpers, the ones taking a callback call it through the evaluator so lynx functions
+// and builtins both work and an error inside the callback is the error of the whole call.
+pub(super) const FUNCTIONS: &[(&str, Option<usize>, BuiltinFn)] = &[
+    ("map", Some(2), map),
+    ("filter", Some(2), filter),
+    ("reduce", None, reduce),
+    ("any", Some(2), any),
+    ("all", Some(2), all),
+    ("find", Some(2), find),
+    ("find_index", Some(2), find_index),
+    ("sort", None, sort),
+    ("sort_by", Some(2), sort_by),
+    ("reverse", Some(1), reverse),
+    ("zip", Some(2), zip),
+    ("flatten", Some(1), flatten),
+    ("unique", Some(1), unique),
+    ("slice", None, slice),
+    ("concat", None, concat),
+    ("join", Some(2), join),
+    ("range", None, range),
+];
+
+fn array_argument<'a>(name: &str, position: usize, params: &'a [Object]) -> Result<&'a [Object], RuntimeError> {
+    match &params[position - 1] {
+        Object::Array(items) => Ok(items),
+        other => Err(argument_error(name, position, "array", other)),
+    }
+}
+
+fn function_argument<'a>(name: &str, position: usize, params: &'a [Object]) -> Result<&'a Object, RuntimeError> {
+    match &params[position - 1] {
+        function if function.type_name() == "function" => Ok(function),
+        other => Err(argument_error(name, position, "function", other)),
+    }
+}
+
+fn map(evaluator: &mut Evaluator, params: &[Object]) -> BuiltinResult {
+    let items = array_argument("map", 1, params)?;
+    let function = function_argument("map", 2, params)?;
+
+    call_each(evaluator, function, items).map(Object::Array)
+}
+
+fn filter(evaluator: &mut Evaluator, params: &[Object]) -> BuiltinResult {
+    let items = array_argument("filter", 1, params)?;
+    let function = function_argument("filter", 2, params)?;
+    let kept = items
+        .iter()
+        .zip(call_each(evaluator, function, items)?)
+        .filter(|(_, keep)| keep.is_truthy())
+        .map(|(item, _)| item)
+        .collect::<Vec<_>>();
+
+    evaluator.reserve(kept.iter().map(|item| item.allocated_size()).sum())?;
+
+    Ok(Object::Array(kept.into_iter().cloned().collect()))
+}
+
+// the result of `function` for each item, held against the memory limit while they are collected
+fn call_each(evaluator: &mut Evaluator, function: &Object, items: &[Object]) -> Result<Vec<Object>, RuntimeError> {
+    let mut results = vec![];
+    let mut held = 0;
+
+    let outcome = items.iter().try_for_each(|item| {
+        let result = evaluator.call_function(function, vec![item.clone()])?;
+        held += evaluator.hold(&result)?;
+        results.push(result);
+
+        Ok(())
+    });
+
+    evaluator.release(held);
+
+    outcome.map(|_| results)
+}
+
+// `reduce(array, fn(total, item) {...}, initial)`, without `initial` the first item starts
+fn reduce(evaluator: &mut Evaluator, params: &[Object]) -> BuiltinResult {
+    check_arguments("reduce", params, 2, 3)?;
+
+    let items = array_argument("reduce", 1, params)?;
+    let function = function_argument("reduce", 2, params)?;
+
+    let (mut total, rest) = match (params.get(2), items.split_first()) {
+        (Some(initial), _) => (initial.clone(), items),
+        (None, Some((first, rest))) => (first.clone(), rest),
+        (None, None) => return Err(RuntimeError::new("reduce of an empty array without an initial value")),
+    };
+
+    // only the running total is held, the ones before it are dropped
+    let mut held = 0;
+
+    let outcome = rest.iter().try_for_each(|item| {
+        let running = std::mem::replace(&mut total, Object::Null);
+        total = evaluator.call_function(function, vec![running, item.clone()])?;
+        evaluator.release(std::mem::take(&mut held));
+        held = evaluator.hold(&total)?;
+
+        Ok(())
+    });
+
+    evaluator.release(held);
+
+    outcome.map(|_| total)
+}
+
+fn any(evaluator: &mut Evaluator, params: &[Object]) -> BuiltinResult {
+    let items = array_argument("any", 1, params)?;
+    let function = function_argument("any", 2, params)?;
+
+    for item in items {
+        if evaluator.call_function(function, vec![item.clone()])?.is_truthy() {
+            return Ok(Object::Boolean(true));
+        }
+    }
+
+    Ok(Object::Boolean(false))
+}
+
+fn all(evaluator: &mut Evaluator, params: &[Object]) -> BuiltinResult {
+    let items = array_argument("all", 1, params)?;
+    let function = function_argument("all", 2, params)?;
+
+    for item in items {
+        if !evaluator.call_function(function, vec![item.clone()])?.is_truthy() {
+            return Ok(Object::Boolean(false));
+        }
+    }
+
+    Ok(Object::Boolean(true))
+}
+
+fn position(evaluator: &mut Evaluator, name: &str, params: &[Object]) -> Result<Option<usize>, RuntimeError> {
+    let items = array_argument(name, 1, params)?;
+    let function = function_argument(name, 2, params)?;
+
+    for (index, item) in items.iter().enumerate() {
+        if evaluator.call_function(function, vec![item.clone()])?.is_truthy() {
+            return Ok(Some(index));
+        }
+    }
+
+    Ok(None)
+}
+
+// the first item the callback is truthy for, null if there is none
+fn find(evaluator: &mut Evaluator, params: &[Object]) -> BuiltinResult {
+    Ok(match position(evaluator, "find", params)? {
+        Some(index) => array_argument("find", 1, params)?[index].clone(),
+        None => Object::Null,
+    })
+}
+
+fn find_index(evaluator: &mut Evaluator, params: &[Object]) -> BuiltinResult {
+    Ok(match position(evaluator, "find_index", params)? {
+        Some(index) => Object::Integer(index as i64),
+        None => Object::Null,
+    })
+}
+
+// numbers, strings and booleans have an order of their own, the other values need a comparator
+fn compare(left: &Object, right: &Object) -> Result<Ordering, RuntimeError> {
+    match (left, right) {
+        (Object::Integer(left), Object::Integer(right)) => Ok(left.cmp(right)),
+        (Object::String(left), Object::String(right)) => Ok(left.cmp(right)),
+        (Object::Boolean(left), Object::Boolean(right)) => Ok(left.cmp(right)),
+        (Object::Integer(_) | Object::Float(_), Object::Integer(_) | Object::Float(_)) => {
+            let number = |object: &Object| match object {
+                Object::Integer(int) => *int as f64,
+                Object::Float(float) => *float,
+                _ => unreachable!(),
+            };
+
+            number(left)
+                .partial_cmp(&number(right))
+                .ok_or_else(|| RuntimeError::new("can not compare a float that is not a number"))
+        }
+        _ => Err(RuntimeError::new(format!(
+            "can not compare {} and {}",
+            left.type_name(),
+            right.type_name()
+        ))),
+    }
+}
+
+// a stable sort that stops comparing after the first error, `Vec::sort_by` can not fail
+fn sort_with<T, F>(items: &mut [T], mut compare: F) -> Result<(), RuntimeError>
+where
+    F: FnMut(&T, &T) -> Result<Ordering, RuntimeError>,
+{
+    let mut error = None;
+
+    items.sort_by(|left, right| {
+        if error.is_some() {
+            return Ordering::Equal;
+        }
+
+        compare(left, right).unwrap_or_else(|failure| {
+            error = Some(failure);
+            Ordering::Equal
+        })
+    });
+
+    match error {
+        Some(error) => Err(error),
+        None => Ok(()),
+    }
+}
+
+fn comes_before(evaluator: &mut Evaluator, function: &Object, a: &Object, b: &Object) -> Result<bool, RuntimeError> {
+    Ok(evaluator
+        .call_function(function, vec![a.clone(), b.clone()])?
+        .is_truthy())
+}
+
+// `sort(array)` in the natural order, `sort(array, fn(a, b) { a < b })` with a comparator that
+// is true when `a` goes first
+fn sort(evaluator: &mut Evaluator, params: &[Object]) -> BuiltinResult {
+    check_arguments("sort", params, 1, 2)?;
+
+    let mut items = array_argument("sort", 1, params)?.to_vec();
+
+    match params.get(1) {
+        Some(_) => {
+            let function = function_argument("sort", 2, params)?;
+
+            sort_with(&mut items, |left, right| {
+                Ok(if comes_before(evaluator, function, left, right)? {
+                    Ordering::Less
+                } else if comes_before(evaluator, function, right, left)? {
+                    Ordering::Greater
+                } else {
+                    Ordering::Equal
+                })
+            })?;
+        }
+        None => sort_with(&mut items, compare)?,
+    }
+
+    Ok(Object::Array(items))
+}
+
+// sorted by the key the callback returns for each item, it runs once per item
+fn sort_by(evaluator: &mut Evaluator, params: &[Object]) -> BuiltinResult {
+    let items = array_argument("sort_by", 1, params)?;
+    let function = function_argument("sort_by", 2, params)?;
+
+    let keys = call_each(evaluator, function, items)?;
+    evaluator.reserve(items.iter().map(Object::allocated_size).sum())?;
+
+    let mut keyed = keys.into_iter().zip(items.iter().cloned()).collect::<Vec<_>>();
+
+    sort_with(&mut keyed, |(left, _), (right, _)| compare(left, right))?;
+
+    Ok(Object::Array(keyed.into_iter().map(|(_, item)| item).collect()))
+}
+
+fn reverse(_: &mut Evaluator, params: &[Object]) -> BuiltinResult {
+    let items = array_argument("reverse", 1, params)?;
+
+    Ok(Object::Array(items.iter().rev().cloned().collect()))
+}
+
+// `[[a, b], ...]` as long as the shorter array
+fn zip(evaluator: &mut Evaluator, params: &[Object]) -> BuiltinResult {
+    let first = array_argument("zip", 1, params)?;
+    let second = array_argument("zip", 2, params)?;
+
+    evaluator.reserve(
+        first
+            .iter()
+            .zip(second)
+            .map(|(a, b)| Object::Array(vec![]).allocated_size() + a.allocated_size() + b.allocated_size())
+            .sum(),
+    )?;
+
+    Ok(Object::Array(
+        first
+            .iter()
+            .zip(second)
+            .map(|(a, b)| Object::Array(vec![a.clone(), b.clone()]))
+            .collect(),
+    ))
+}
+
+// one level deep, items that are not arrays stay as they are
+fn flatten(evaluator: &mut Evaluator, params: &[Object]) -> BuiltinResult {
+    let items = array_argument("flatten", 1, params)?;
+    evaluator.reserve(items.iter().map(Object::allocated_size).sum())?;
+
+    let mut flat = vec![];
+
+    for item in items {
+        match item {
+            Object::Array(inner) => flat.extend(inner.iter().cloned()),
+            other => flat.push(other.clone()),
+        }
+    }
+
+    Ok(Object::Array(flat))
+}
+
+// the first of equal items stays in its place
+fn unique(_: &mut Evaluator, params: &[Object]) -> BuiltinResult {
+    let items = array_argument("unique", 1, params)?;
+    let mut seen = HashSet::new();
+    let mut kept: Vec<Object> = vec![];
+
+    for item in items {
+        let first = match item.is_hashable() {
+            true => seen.insert(item.clone()),
+            // functions can only be compared one by one
+            false => !kept.contains(item),
+        };
+
+        if first {
+            kept.push(item.clone());
+        }
+    }
+
+    Ok(Object::Array(kept))
+}
+
+// `slice(array, start)` or `slice(array, start, end)`, both are clamped to the array
+fn slice(_: &mut Evaluator, params: &[Object]) -> BuiltinResult {
+    check_arguments("slice", params, 2, 3)?;
+
+    let items = array_argument("slice", 1, params)?;
+    let start = count_argument("slice", 2, params)?.min(items.len());
+    let end = match params.get(2) {
+        Some(_) => count_argument("slice", 3, params)?.clamp(start, items.len()),
+        None => items.len(),
+    };
+
+    Ok(Object::Array(items[start..end].to_vec()))
+}
+
+fn concat(evaluator: &mut Evaluator, params: &[Object]) -> BuiltinResult {
+    let mut arrays = vec![];
+
+    for position in 1..=params.len() {
+        arrays.push(array_argument("concat", position, params)?);
+    }
+
+    evaluator.reserve(
+        arrays
+            .iter()
+            .flat_map(|items| items.iter())
+            .map(Object::allocated_size)
+            .sum(),
+    )?;
+
+    let mut joined = vec![];
+
+    for items in arrays {
+        joined.extend(items.iter().cloned());
+    }
+
+    Ok(Object::Array(joined))
+}
+
+// the items as `str` formats them with the separator in between
+fn join(_: &mut Evaluator, params: &[Object]) -> BuiltinResult {
+    let items = array_argument("join", 1, params)?;
+    let separator = match &params[1] {
+        Object::String(separator) => separator,
+        other => return Err(argument_error("join", 2, "string", other)),
+    };
+
+    let texts = items.iter().map(Object::to_string).collect::<Vec<_>>();
+
+    Ok(Object::String(texts.join(separator)))
+}
+
+// `range(end)`, `range(start, end)` or `range(start, end, step)`, the end is excluded and a
+// negative step counts down
+fn range(evaluator: &mut Evaluator, params: &[Object]) -> BuiltinResult {
+    check_arguments("range", params, 1, 3)?;
+
+    let (start, end) = match params.len() {
+        1 => (0, integer_argument("range", 1, params)?),
+        _ => (
+            integer_argument("range", 1, params)?,
+            integer_argument("range", 2, params)?,
+        ),
+    };
+    let step = match params.get(2) {
+        Some(_) => integer_argument("range", 3, params)?,
+        None => 1,
+    };
+
+    if step == 0 {
+        return Err(RuntimeError::new("range step must not be zero"));
+    }
+
+    // in i128 the distance between any two integers fits
+    let (start, end, step) = (start as i128, end as i128, step as i128);
+    let count = match (end - start).signum() == step.signum() {
+        true => ((end - start).abs() - 1) / step.abs() + 1,
+        false => 0,
+    };
+
+    // the size is checked before anything is collected, a range too large for the memory
+    // limit or the machine is an error and not an abort
+    let too_large = || RuntimeError::new(format!("range of {} items is too large", count));
+    let length = usize::try_from(count).map_err(|_| too_large())?;
+    evaluator.reserve(length.saturating_mul(Object::Integer(0).allocated_size()))?;
+
+    let mut items = vec![];
+    items.try_reserve_exact(length).map_err(|_| too_large())?;
+    items.extend((0..count).map(|index| Object::Integer((start + index * step) as i64)));
+
+    Ok(Object::Array(items))
+}
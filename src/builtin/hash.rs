@@ -0,0 +1,124 @@
+use super::{argument_error, check_arguments, BuiltinFn, BuiltinResult};
+use crate::evaluator::Evaluator;
+use crate::object::{Object, RuntimeError};
+use crate::ordered_hash::OrderedHash;
+
+// Hashes are values like arrays, `set`, `delete` and `merge` return a new hash and leave the
+// one they got alone.
+pub(super) const FUNCTIONS: &[(&str, Option<usize>, BuiltinFn)] = &[
+    ("keys", Some(1), keys),
+    ("values", Some(1), values),
+    ("entries", Some(1), entries),
+    ("has", Some(2), has),
+    ("get", None, get),
+    ("set", Some(3), set),
+    ("delete", Some(2), delete),
+    ("merge", Some(2), merge),
+    ("deep_merge", Some(2), deep_merge),
+];
+
+fn hash_argument<'a>(name: &str, position: usize, params: &'a [Object]) -> Result<&'a OrderedHash, RuntimeError> {
+    match &params[position - 1] {
+        Object::Hash(pairs) => Ok(pairs),
+        other => Err(argument_error(name, position, "hash", other)),
+    }
+}
+
+fn hashable_key(key: &Object) -> Result<Object, RuntimeError> {
+    match key.is_hashable() {
+        true => Ok(key.clone()),
+        false => Err(RuntimeError::new(key.unhashable_error())),
+    }
+}
+
+fn keys(_: &mut Evaluator, params: &[Object]) -> BuiltinResult {
+    let pairs = hash_argument("keys", 1, params)?;
+
+    Ok(Object::Array(pairs.keys().cloned().collect()))
+}
+
+fn values(_: &mut Evaluator, params: &[Object]) -> BuiltinResult {
+    let pairs = hash_argument("values", 1, params)?;
+
+    Ok(Object::Array(pairs.values().cloned().collect()))
+}
+
+// `[[key, value], ...]` in insertion order
+fn entries(_: &mut Evaluator, params: &[Object]) -> BuiltinResult {
+    let pairs = hash_argument("entries", 1, params)?;
+
+    Ok(Object::Array(
+        pairs
+            .iter()
+            .map(|(key, value)| Object::Array(vec![key.clone(), value.clone()]))
+            .collect(),
+    ))
+}
+
+fn has(_: &mut Evaluator, params: &[Object]) -> BuiltinResult {
+    let pairs = hash_argument("has", 1, params)?;
+
+    Ok(Object::Boolean(pairs.contains_key(&params[1])))
+}
+
+// `get(hash, key)` is null for a missing key, `get(hash, key, default)` the default
+fn get(_: &mut Evaluator, params: &[Object]) -> BuiltinResult {
+    check_arguments("get", params, 2, 3)?;
+
+    let pairs = hash_argument("get", 1, params)?;
+
+    Ok(match pairs.get(&params[1]) {
+        Some(value) => value.clone(),
+        None => params.get(2).cloned().unwrap_or(Object::Null),
+    })
+}
+
+fn set(_: &mut Evaluator, params: &[Object]) -> BuiltinResult {
+    let mut pairs = hash_argument("set", 1, params)?.clone();
+
+    pairs.insert(hashable_key(&params[1])?, params[2].clone());
+
+    Ok(Object::Hash(pairs))
+}
+
+fn delete(_: &mut Evaluator, params: &[Object]) -> BuiltinResult {
+    let mut pairs = hash_argument("delete", 1, params)?.clone();
+
+    pairs.remove(&params[1]);
+
+    Ok(Object::Hash(pairs))
+}
+
+// the pairs of the second hash win, keys only the second one has come last
+fn merge(_: &mut Evaluator, params: &[Object]) -> BuiltinResult {
+    let mut pairs = hash_argument("merge", 1, params)?.clone();
+
+    for (key, value) in hash_argument("merge", 2, params)?.iter() {
+        pairs.insert(key.clone(), value.clone());
+    }
+
+    Ok(Object::Hash(pairs))
+}
+
+// like `merge`, but a key holding a hash on both sides gets the two hashes merged
+fn deep_merge(_: &mut Evaluator, params: &[Object]) -> BuiltinResult {
+    let first = hash_argument("deep_merge", 1, params)?;
+    let second = hash_argument("deep_merge", 2, params)?;
+
+    Ok(Object::Hash(deep_merge_pairs(first, second)))
+}
+
+fn deep_merge_pairs(first: &OrderedHash, second: &OrderedHash) -> OrderedHash {
+    let mut pairs = first.clone();
+
+    for (key, value) in second.iter() {
+        let merged = match (pairs.get(key), value) {
+            (Some(Object::Hash(inner)), Object::Hash(other)) => Object::Hash(deep_merge_pairs(inner, other)),
+            _ => value.clone(),
+        };
+
+        pairs.insert(key.clone(), merged);
+    }
+
+    pairs
+}
@@ -0,0 +1,73 @@
+use std::io::Write;
+
+use super::{argument_error, check_arguments, BuiltinFn, BuiltinResult};
+use crate::evaluator::Evaluator;
+use crate::object::{Object, RuntimeError};
+
+// Output and input through the streams of the evaluator, `Evaluator::set_stdout` and friends
+// point them somewhere else. The values are written as `str` formats them, separated by spaces and
+// followed by a line break, `println` and `eprintln` are the same as `print` and `eprint`.
+pub(super) const FUNCTIONS: &[(&str, Option<usize>, BuiltinFn)] = &[
+    ("print", None, print),
+    ("println", None, println),
+    ("eprint", None, eprint),
+    ("eprintln", None, eprintln),
+    ("input", None, input),
+];
+
+fn io_error(name: &str, error: std::io::Error) -> RuntimeError {
+    RuntimeError::new(format!("{}: {}", name, error))
+}
+
+fn write_line(name: &str, output: &mut dyn Write, params: &[Object]) -> BuiltinResult {
+    let texts = params.iter().map(Object::to_string).collect::<Vec<_>>();
+
+    writeln!(output, "{}", texts.join(" ")).map_err(|error| io_error(name, error))?;
+
+    Ok(Object::Null)
+}
+
+fn print(evaluator: &mut Evaluator, params: &[Object]) -> BuiltinResult {
+    write_line("print", evaluator.stdout(), params)
+}
+
+fn println(evaluator: &mut Evaluator, params: &[Object]) -> BuiltinResult {
+    write_line("println", evaluator.stdout(), params)
+}
+
+fn eprint(evaluator: &mut Evaluator, params: &[Object]) -> BuiltinResult {
+    write_line("eprint", evaluator.stderr(), params)
+}
+
+fn eprintln(evaluator: &mut Evaluator, params: &[Object]) -> BuiltinResult {
+    write_line("eprintln", evaluator.stderr(), params)
+}
+
+// `input()` or `input(prompt)`, a line without its line break, null at the end of the input
+fn input(evaluator: &mut Evaluator, params: &[Object]) -> BuiltinResult {
+    check_arguments("input", params, 0, 1)?;
+
+    if let Some(prompt) = params.first() {
+        let prompt = match prompt {
+            Object::String(prompt) => prompt,
+            other => return Err(argument_error("input", 1, "string", other)),
+        };
+
+        let stdout = evaluator.stdout();
+        write!(stdout, "{}", prompt)
+            .and_then(|_| stdout.flush())
+            .map_err(|error| io_error("input", error))?;
+    }
+
+    let mut line = String::new();
+
+    match evaluator.stdin().read_line(&mut line) {
+        Ok(0) => Ok(Object::Null),
+        Ok(_) => {
+            let line = line.strip_suffix('\n').unwrap_or(&line);
+
+            Ok(Object::String(line.strip_suffix('\r').unwrap_or(line).to_string()))
+        }
+        Err(error) => Err(io_error("input", error)),
+    }
+}
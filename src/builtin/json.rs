@@ -0,0 +1,29 @@
+use super::{argument_error, check_arguments, count_argument, BuiltinFn, BuiltinResult};
+use crate::evaluator::Evaluator;
+use crate::json;
+use crate::object::Object;
+
+pub(super) const FUNCTIONS: &[(&str, Option<usize>, BuiltinFn)] = &[
+    ("json_parse", Some(1), json_parse),
+    ("json_stringify", None, json_stringify),
+];
+
+fn json_parse(_: &mut Evaluator, params: &[Object]) -> BuiltinResult {
+    match &params[0] {
+        Object::String(text) => json::parse(text),
+        other => Err(argument_error("json_parse", 1, "string", other)),
+    }
+}
+
+// `json_stringify(value)` on one line, `json_stringify(value, indent)` with `indent` spaces per
+// level
+fn json_stringify(_: &mut Evaluator, params: &[Object]) -> BuiltinResult {
+    check_arguments("json_stringify", params, 1, 2)?;
+
+    let indent = match params.get(1) {
+        Some(_) => count_argument("json_stringify", 2, params)?,
+        None => 0,
+    };
+
+    json::stringify(&params[0], indent).map(Object::String)
+}
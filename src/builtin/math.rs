@@ -0,0 +1,257 @@
+use super::{argument_error, check_arguments, integer_argument, BuiltinFn, BuiltinResult};
+use crate::evaluator::Evaluator;
+use crate::object::{BuiltinFunction, Object, RuntimeError};
+use crate::ordered_hash::OrderedHash;
+
+// The `math` namespace, a hash of these functions and the constants that programs call as
+// `math.sqrt(2)` and read as `math.PI`. Integers stay integers where the result is whole,
+// everything else is a float. A result that is not a number is an error.
+const FUNCTIONS: &[(&str, Option<usize>, BuiltinFn)] = &[
+    ("abs", Some(1), abs),
+    ("min", None, min),
+    ("max", None, max),
+    ("clamp", Some(3), clamp),
+    ("pow", Some(2), pow),
+    ("sqrt", Some(1), sqrt),
+    ("floor", Some(1), floor),
+    ("ceil", Some(1), ceil),
+    ("round", Some(1), round),
+    ("trunc", Some(1), trunc),
+    ("sin", Some(1), sin),
+    ("cos", Some(1), cos),
+    ("tan", Some(1), tan),
+    ("asin", Some(1), asin),
+    ("acos", Some(1), acos),
+    ("atan", Some(1), atan),
+    ("atan2", Some(2), atan2),
+    ("log", None, log),
+    ("exp", Some(1), exp),
+    ("gcd", Some(2), gcd),
+    ("lcm", Some(2), lcm),
+];
+
+pub(super) fn namespace() -> Object {
+    let mut pairs = OrderedHash::new();
+
+    for &(name, arity, func) in FUNCTIONS {
+        let builtin = BuiltinFunction::new(&format!("math.{}", name), arity, func);
+
+        pairs.insert(Object::String(name.to_string()), Object::Builtin(builtin));
+    }
+
+    pairs.insert(Object::String(String::from("PI")), Object::Float(std::f64::consts::PI));
+    pairs.insert(Object::String(String::from("E")), Object::Float(std::f64::consts::E));
+
+    Object::Hash(pairs)
+}
+
+fn float_argument(name: &str, position: usize, params: &[Object]) -> Result<f64, RuntimeError> {
+    match &params[position - 1] {
+        Object::Float(float) => Ok(*float),
+        Object::Integer(int) => Ok(*int as f64),
+        other => Err(argument_error(name, position, "number", other)),
+    }
+}
+
+fn float_result(name: &str, value: f64) -> BuiltinResult {
+    match value.is_nan() {
+        true => Err(RuntimeError::new(format!("{}: the result is not a number", name))),
+        false => Ok(Object::Float(value)),
+    }
+}
+
+// integers are passed through, floats go through `round`
+fn whole(name: &str, params: &[Object], round: fn(f64) -> f64) -> BuiltinResult {
+    match &params[0] {
+        Object::Integer(int) => Ok(Object::Integer(*int)),
+        Object::Float(float) => Ok(Object::Float(round(*float))),
+        other => Err(argument_error(name, 1, "number", other)),
+    }
+}
+
+fn unary(name: &str, params: &[Object], function: fn(f64) -> f64) -> BuiltinResult {
+    float_result(name, function(float_argument(name, 1, params)?))
+}
+
+fn abs(_: &mut Evaluator, params: &[Object]) -> BuiltinResult {
+    match &params[0] {
+        Object::Integer(int) => int
+            .checked_abs()
+            .map(Object::Integer)
+            .ok_or_else(|| RuntimeError::new(format!("integer overflow: abs of {}", int))),
+        _ => whole("math.abs", params, f64::abs),
+    }
+}
+
+// the argument that is smallest or largest, compared as numbers and returned as it is
+fn extreme(name: &str, params: &[Object], replaces: fn(f64, f64) -> bool) -> BuiltinResult {
+    if params.is_empty() {
+        return Err(RuntimeError::new(format!(
+            "wrong number of arguments for {}: expected 1 or more, got 0",
+            name
+        )));
+    }
+
+    let mut best = (&params[0], float_argument(name, 1, params)?);
+
+    for position in 2..=params.len() {
+        let value = float_argument(name, position, params)?;
+
+        if replaces(value, best.1) {
+            best = (&params[position - 1], value);
+        }
+    }
+
+    Ok(best.0.clone())
+}
+
+fn min(_: &mut Evaluator, params: &[Object]) -> BuiltinResult {
+    extreme("math.min", params, |value, best| value < best)
+}
+
+fn max(_: &mut Evaluator, params: &[Object]) -> BuiltinResult {
+    extreme("math.max", params, |value, best| value > best)
+}
+
+// `clamp(value, low, high)`
+fn clamp(_: &mut Evaluator, params: &[Object]) -> BuiltinResult {
+    let value = float_argument("math.clamp", 1, params)?;
+    let low = float_argument("math.clamp", 2, params)?;
+    let high = float_argument("math.clamp", 3, params)?;
+
+    if low > high {
+        return Err(RuntimeError::new("math.clamp: the low bound is above the high bound"));
+    }
+
+    Ok(if value < low {
+        params[1].clone()
+    } else if value > high {
+        params[2].clone()
+    } else {
+        params[0].clone()
+    })
+}
+
+// an integer for two integers, it fails on overflow like the arithmetic operators. A negative
+// exponent gives a float.
+fn pow(_: &mut Evaluator, params: &[Object]) -> BuiltinResult {
+    match (&params[0], &params[1]) {
+        (Object::Integer(base), Object::Integer(exponent)) if *exponent >= 0 => u32::try_from(*exponent)
+            .ok()
+            .and_then(|exponent| base.checked_pow(exponent))
+            .map(Object::Integer)
+            .ok_or_else(|| RuntimeError::new(format!("integer overflow: {} pow {}", base, exponent))),
+        _ => float_result(
+            "math.pow",
+            float_argument("math.pow", 1, params)?.powf(float_argument("math.pow", 2, params)?),
+        ),
+    }
+}
+
+fn sqrt(_: &mut Evaluator, params: &[Object]) -> BuiltinResult {
+    unary("math.sqrt", params, f64::sqrt)
+}
+
+fn floor(_: &mut Evaluator, params: &[Object]) -> BuiltinResult {
+    whole("math.floor", params, f64::floor)
+}
+
+fn ceil(_: &mut Evaluator, params: &[Object]) -> BuiltinResult {
+    whole("math.ceil", params, f64::ceil)
+}
+
+// halves away from zero
+fn round(_: &mut Evaluator, params: &[Object]) -> BuiltinResult {
+    whole("math.round", params, f64::round)
+}
+
+fn trunc(_: &mut Evaluator, params: &[Object]) -> BuiltinResult {
+    whole("math.trunc", params, f64::trunc)
+}
+
+fn sin(_: &mut Evaluator, params: &[Object]) -> BuiltinResult {
+    unary("math.sin", params, f64::sin)
+}
+
+fn cos(_: &mut Evaluator, params: &[Object]) -> BuiltinResult {
+    unary("math.cos", params, f64::cos)
+}
+
+fn tan(_: &mut Evaluator, params: &[Object]) -> BuiltinResult {
+    unary("math.tan", params, f64::tan)
+}
+
+fn asin(_: &mut Evaluator, params: &[Object]) -> BuiltinResult {
+    unary("math.asin", params, f64::asin)
+}
+
+fn acos(_: &mut Evaluator, params: &[Object]) -> BuiltinResult {
+    unary("math.acos", params, f64::acos)
+}
+
+fn atan(_: &mut Evaluator, params: &[Object]) -> BuiltinResult {
+    unary("math.atan", params, f64::atan)
+}
+
+// `atan2(y, x)`
+fn atan2(_: &mut Evaluator, params: &[Object]) -> BuiltinResult {
+    let y = float_argument("math.atan2", 1, params)?;
+    let x = float_argument("math.atan2", 2, params)?;
+
+    float_result("math.atan2", y.atan2(x))
+}
+
+// `log(value)` is the natural logarithm, `log(value, base)` the one of `base`
+fn log(_: &mut Evaluator, params: &[Object]) -> BuiltinResult {
+    check_arguments("math.log", params, 1, 2)?;
+
+    let value = float_argument("math.log", 1, params)?;
+
+    if value <= 0.0 {
+        return Err(RuntimeError::new("math.log of a number that is not positive"));
+    }
+
+    match params.get(1) {
+        Some(_) => float_result("math.log", value.log(float_argument("math.log", 2, params)?)),
+        None => float_result("math.log", value.ln()),
+    }
+}
+
+fn exp(_: &mut Evaluator, params: &[Object]) -> BuiltinResult {
+    unary("math.exp", params, f64::exp)
+}
+
+fn greatest_common_divisor(mut a: u64, mut b: u64) -> u64 {
+    while b != 0 {
+        (a, b) = (b, a % b);
+    }
+
+    a
+}
+
+fn gcd(_: &mut Evaluator, params: &[Object]) -> BuiltinResult {
+    let a = integer_argument("math.gcd", 1, params)?;
+    let b = integer_argument("math.gcd", 2, params)?;
+
+    // never negative, only the one of -2^63 and 0 does not fit
+    i64::try_from(greatest_common_divisor(a.unsigned_abs(), b.unsigned_abs()))
+        .map(Object::Integer)
+        .map_err(|_| RuntimeError::new(format!("integer overflow: gcd of {} and {}", a, b)))
+}
+
+fn lcm(_: &mut Evaluator, params: &[Object]) -> BuiltinResult {
+    let a = integer_argument("math.lcm", 1, params)?;
+    let b = integer_argument("math.lcm", 2, params)?;
+
+    if a == 0 || b == 0 {
+        return Ok(Object::Integer(0));
+    }
+
+    let (a_abs, b_abs) = (a.unsigned_abs(), b.unsigned_abs());
+
+    (a_abs / greatest_common_divisor(a_abs, b_abs))
+        .checked_mul(b_abs)
+        .and_then(|lcm| i64::try_from(lcm).ok())
+        .map(Object::Integer)
+        .ok_or_else(|| RuntimeError::new(format!("integer overflow: lcm of {} and {}", a, b)))
+}
@@ -0,0 +1,69 @@
+use super::{argument_error, integer_argument, BuiltinFn, BuiltinResult};
+use crate::evaluator::Evaluator;
+use crate::object::{Object, RuntimeError};
+
+// Randomness from the generator of the evaluator, `Evaluator::set_seed` makes a run repeatable.
+pub(super) const FUNCTIONS: &[(&str, Option<usize>, BuiltinFn)] = &[
+    ("random", Some(0), random),
+    ("random_int", Some(2), random_int),
+    ("shuffle", Some(1), shuffle),
+    ("choice", Some(1), choice),
+];
+
+fn array_argument<'a>(name: &str, params: &'a [Object]) -> Result<&'a [Object], RuntimeError> {
+    match &params[0] {
+        Object::Array(items) => Ok(items),
+        other => Err(argument_error(name, 1, "array", other)),
+    }
+}
+
+// a float in [0, 1)
+fn random(evaluator: &mut Evaluator, _: &[Object]) -> BuiltinResult {
+    Ok(Object::Float(evaluator.rng().next_float()))
+}
+
+// `random_int(low, high)`, both ends included
+fn random_int(evaluator: &mut Evaluator, params: &[Object]) -> BuiltinResult {
+    let low = integer_argument("random_int", 1, params)?;
+    let high = integer_argument("random_int", 2, params)?;
+
+    if low > high {
+        return Err(RuntimeError::new(format!(
+            "random_int: the low bound {} is above the high bound {}",
+            low, high
+        )));
+    }
+
+    // the distance as unsigned, it does not fit an i64 from a negative low bound on
+    let offset = match (high.wrapping_sub(low) as u64).checked_add(1) {
+        Some(span) => evaluator.rng().below(span),
+        // every integer is possible
+        None => evaluator.rng().next_u64(),
+    };
+
+    Ok(Object::Integer(low.wrapping_add(offset as i64)))
+}
+
+// a new array with the items in random order
+fn shuffle(evaluator: &mut Evaluator, params: &[Object]) -> BuiltinResult {
+    let mut items = array_argument("shuffle", params)?.to_vec();
+
+    // Fisher-Yates from the back
+    for index in (1..items.len()).rev() {
+        let other = evaluator.rng().below(index as u64 + 1) as usize;
+
+        items.swap(index, other);
+    }
+
+    Ok(Object::Array(items))
+}
+
+fn choice(evaluator: &mut Evaluator, params: &[Object]) -> BuiltinResult {
+    let items = array_argument("choice", params)?;
+
+    if items.is_empty() {
+        return Err(RuntimeError::new("choice from an empty array"));
+    }
+
+    Ok(items[evaluator.rng().below(items.len() as u64) as usize].clone())
+}
@@ -0,0 +1,285 @@
+use super::{argument_error, check_arguments, count_argument, BuiltinFn, BuiltinResult};
+use crate::evaluator::Evaluator;
+use crate::object::{Object, RuntimeError};
+
+// String helpers, positions and lengths count chars and not bytes so text outside of ascii
+// can not be cut in half.
+pub(super) const FUNCTIONS: &[(&str, Option<usize>, BuiltinFn)] = &[
+    ("split", Some(2), split),
+    ("trim", Some(1), trim),
+    ("trim_start", Some(1), trim_start),
+    ("trim_end", Some(1), trim_end),
+    ("upper", Some(1), upper),
+    ("lower", Some(1), lower),
+    ("contains", Some(2), contains),
+    ("starts_with", Some(2), starts_with),
+    ("ends_with", Some(2), ends_with),
+    ("replace", Some(3), replace),
+    ("index_of", Some(2), index_of),
+    ("substring", None, substring),
+    ("chars", Some(1), chars),
+    ("repeat", Some(2), repeat),
+    ("pad_start", None, pad_start),
+    ("pad_end", None, pad_end),
+    ("to_int", Some(1), to_int),
+    ("to_float", Some(1), to_float),
+    ("format", None, format),
+];
+
+fn string_argument<'a>(name: &str, position: usize, params: &'a [Object]) -> Result<&'a str, RuntimeError> {
+    match &params[position - 1] {
+        Object::String(string) => Ok(string),
+        other => Err(argument_error(name, position, "string", other)),
+    }
+}
+
+fn string(value: &str) -> Object {
+    Object::String(value.to_string())
+}
+
+// an empty separator splits between every char
+fn split(_: &mut Evaluator, params: &[Object]) -> BuiltinResult {
+    let text = string_argument("split", 1, params)?;
+    let separator = string_argument("split", 2, params)?;
+
+    Ok(Object::Array(match separator {
+        "" => text.chars().map(|char| Object::String(char.to_string())).collect(),
+        separator => text.split(separator).map(string).collect(),
+    }))
+}
+
+fn trim(_: &mut Evaluator, params: &[Object]) -> BuiltinResult {
+    Ok(string(string_argument("trim", 1, params)?.trim()))
+}
+
+fn trim_start(_: &mut Evaluator, params: &[Object]) -> BuiltinResult {
+    Ok(string(string_argument("trim_start", 1, params)?.trim_start()))
+}
+
+fn trim_end(_: &mut Evaluator, params: &[Object]) -> BuiltinResult {
+    Ok(string(string_argument("trim_end", 1, params)?.trim_end()))
+}
+
+fn upper(_: &mut Evaluator, params: &[Object]) -> BuiltinResult {
+    Ok(Object::String(string_argument("upper", 1, params)?.to_uppercase()))
+}
+
+fn lower(_: &mut Evaluator, params: &[Object]) -> BuiltinResult {
+    Ok(Object::String(string_argument("lower", 1, params)?.to_lowercase()))
+}
+
+fn contains(_: &mut Evaluator, params: &[Object]) -> BuiltinResult {
+    let text = string_argument("contains", 1, params)?;
+
+    Ok(Object::Boolean(text.contains(string_argument("contains", 2, params)?)))
+}
+
+fn starts_with(_: &mut Evaluator, params: &[Object]) -> BuiltinResult {
+    let text = string_argument("starts_with", 1, params)?;
+
+    Ok(Object::Boolean(text.starts_with(string_argument(
+        "starts_with",
+        2,
+        params,
+    )?)))
+}
+
+fn ends_with(_: &mut Evaluator, params: &[Object]) -> BuiltinResult {
+    let text = string_argument("ends_with", 1, params)?;
+
+    Ok(Object::Boolean(text.ends_with(string_argument(
+        "ends_with",
+        2,
+        params,
+    )?)))
+}
+
+// every occurrence
+fn replace(_: &mut Evaluator, params: &[Object]) -> BuiltinResult {
+    let text = string_argument("replace", 1, params)?;
+    let from = string_argument("replace", 2, params)?;
+    let to = string_argument("replace", 3, params)?;
+
+    Ok(Object::String(text.replace(from, to)))
+}
+
+// the char position of the first occurrence, null if there is none
+fn index_of(_: &mut Evaluator, params: &[Object]) -> BuiltinResult {
+    let text = string_argument("index_of", 1, params)?;
+    let part = string_argument("index_of", 2, params)?;
+
+    Ok(match text.find(part) {
+        Some(byte) => Object::Integer(text[..byte].chars().count() as i64),
+        None => Object::Null,
+    })
+}
+
+// `substring(text, start)` or `substring(text, start, end)` in chars, clamped like `slice`
+fn substring(_: &mut Evaluator, params: &[Object]) -> BuiltinResult {
+    check_arguments("substring", params, 2, 3)?;
+
+    let text = string_argument("substring", 1, params)?;
+    let start = count_argument("substring", 2, params)?;
+    let end = match params.get(2) {
+        Some(_) => count_argument("substring", 3, params)?,
+        None => usize::MAX,
+    };
+
+    Ok(Object::String(
+        text.chars().skip(start).take(end.saturating_sub(start)).collect(),
+    ))
+}
+
+fn chars(_: &mut Evaluator, params: &[Object]) -> BuiltinResult {
+    let text = string_argument("chars", 1, params)?;
+
+    Ok(Object::Array(
+        text.chars().map(|char| Object::String(char.to_string())).collect(),
+    ))
+}
+
+// an empty string with room for `bytes` bytes, `None` is a size that overflowed, a string too
+// large for the memory limit or the machine is an error and not an abort
+fn allocate(evaluator: &mut Evaluator, name: &str, bytes: Option<usize>) -> Result<String, RuntimeError> {
+    let too_large = || RuntimeError::new(format!("the result of {} is too large", name));
+    let bytes = bytes.ok_or_else(too_large)?;
+    evaluator.reserve(bytes)?;
+
+    let mut string = String::new();
+    string.try_reserve_exact(bytes).map_err(|_| too_large())?;
+
+    Ok(string)
+}
+
+fn repeat(evaluator: &mut Evaluator, params: &[Object]) -> BuiltinResult {
+    let text = string_argument("repeat", 1, params)?;
+    let count = count_argument("repeat", 2, params)?;
+    let mut repeated = allocate(evaluator, "repeat", text.len().checked_mul(count))?;
+
+    for _ in 0..count {
+        repeated.push_str(text);
+    }
+
+    Ok(Object::String(repeated))
+}
+
+// the chars to put in front or after the text so it is `width` chars long, `pad` repeats,
+// with room for the text
+fn padding(evaluator: &mut Evaluator, name: &str, params: &[Object]) -> Result<(String, String), RuntimeError> {
+    check_arguments(name, params, 2, 3)?;
+
+    let text = string_argument(name, 1, params)?;
+    let width = count_argument(name, 2, params)?;
+    let pad = match params.get(2) {
+        Some(_) => string_argument(name, 3, params)?,
+        None => " ",
+    };
+
+    if pad.is_empty() {
+        return Err(RuntimeError::new(format!("the padding of {} must not be empty", name)));
+    }
+
+    let missing = width.saturating_sub(text.chars().count());
+    let widest = pad.chars().map(char::len_utf8).max().unwrap_or(1);
+    let bytes = missing
+        .checked_mul(widest)
+        .and_then(|bytes| bytes.checked_add(text.len()));
+    let mut padding = allocate(evaluator, name, bytes)?;
+    padding.extend(pad.chars().cycle().take(missing));
+
+    Ok((text.to_string(), padding))
+}
+
+// `pad_start(text, width)` fills with spaces, `pad_start(text, width, pad)` with `pad`
+fn pad_start(evaluator: &mut Evaluator, params: &[Object]) -> BuiltinResult {
+    let (text, padding) = padding(evaluator, "pad_start", params)?;
+
+    Ok(Object::String(padding + &text))
+}
+
+fn pad_end(evaluator: &mut Evaluator, params: &[Object]) -> BuiltinResult {
+    let (text, mut padding) = padding(evaluator, "pad_end", params)?;
+    padding.insert_str(0, &text);
+
+    Ok(Object::String(padding))
+}
+
+// parses a string, a float loses its fraction
+fn to_int(_: &mut Evaluator, params: &[Object]) -> BuiltinResult {
+    match &params[0] {
+        Object::String(text) => match text.parse() {
+            Ok(int) => Ok(Object::Integer(int)),
+            Err(_) => Err(RuntimeError::new(format!("can not parse {:?} as integer", text))),
+        },
+        Object::Integer(int) => Ok(Object::Integer(*int)),
+        // from -2^63 up to 2^63 excluded
+        Object::Float(float) if (i64::MIN as f64..-(i64::MIN as f64)).contains(&float.trunc()) => {
+            Ok(Object::Integer(*float as i64))
+        }
+        Object::Float(float) => Err(RuntimeError::new(format!("can not convert {:?} to integer", float))),
+        other => Err(argument_error("to_int", 1, "string", other)),
+    }
+}
+
+// parses a string, an integer becomes the same float
+fn to_float(_: &mut Evaluator, params: &[Object]) -> BuiltinResult {
+    match &params[0] {
+        Object::String(text) => match text.parse() {
+            Ok(float) => Ok(Object::Float(float)),
+            Err(_) => Err(RuntimeError::new(format!("can not parse {:?} as float", text))),
+        },
+        Object::Integer(int) => Ok(Object::Float(*int as f64)),
+        Object::Float(float) => Ok(Object::Float(*float)),
+        other => Err(argument_error("to_float", 1, "string", other)),
+    }
+}
+
+// `format("{} of {}", 1, 2)` puts the arguments in place of `{}` as `str` formats them, `{{`
+// and `}}` are literal braces
+fn format(_: &mut Evaluator, params: &[Object]) -> BuiltinResult {
+    if params.is_empty() {
+        return Err(RuntimeError::new(
+            "wrong number of arguments for format: expected 1 or more, got 0",
+        ));
+    }
+
+    let template = string_argument("format", 1, params)?;
+    let mut arguments = params[1..].iter();
+    let mut placeholders = 0;
+    let mut formatted = String::new();
+    let mut chars = template.chars().peekable();
+
+    while let Some(char) = chars.next() {
+        match (char, chars.peek()) {
+            ('{', Some('{')) | ('}', Some('}')) => {
+                formatted.push(char);
+                chars.next();
+            }
+            ('{', Some('}')) => {
+                chars.next();
+                placeholders += 1;
+
+                if let Some(argument) = arguments.next() {
+                    formatted.push_str(&argument.to_string());
+                }
+            }
+            ('{', _) | ('}', _) => {
+                return Err(RuntimeError::new(format!(
+                    "unmatched brace in format string {:?}",
+                    template
+                )));
+            }
+            (char, _) => formatted.push(char),
+        }
+    }
+
+    if placeholders != params.len() - 1 {
+        return Err(RuntimeError::new(format!(
+            "format string has {} placeholders, got {} arguments",
+            placeholders,
+            params.len() - 1
+        )));
+    }
+
+    Ok(Object::String(formatted))
+}
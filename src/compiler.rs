@@ -0,0 +1,1187 @@
+use std::fmt::Write;
+use std::rc::Rc;
+
+use crate::{ast::*, builtin::make_builtin, object::Object, resolver::Resolver, token::TokenType};
+
+// one byte per opcode, operands follow big endian and are u16 unless noted
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Opcode {
+    Constant, // constant index
+    Null,
+    True,
+    False,
+    Pop,
+    GetGlobal,    // global index
+    SetGlobal,    // global index, keeps the value on the stack
+    DefineGlobal, // global index, pops the value
+    GetLocal,     // slot
+    SetLocal,     // slot, keeps the value on the stack
+    GetUpvalue,   // upvalue index
+    SetUpvalue,   // upvalue index, keeps the value on the stack
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Equal,
+    NotEqual,
+    Less,
+    LessEqual,
+    Greater,
+    GreaterEqual,
+    Not,
+    Negate,
+    Jump,          // absolute target
+    JumpIfFalse,   // absolute target, pops the condition
+    Array,         // element count
+    Hash,          // pair count
+    Call,          // u8 argument count
+    TailCall,      // u8 argument count, a closure takes over the frame of the caller
+    Invoke,        // constant index of the method name, then an u8 argument count
+    GetProperty,   // constant index of the key
+    Closure,       // constant index, then an u8 is_local flag and an u16 index per upvalue
+    CloseUpvalues, // first slot to close
+    Return,
+    ReturnNone,
+}
+
+const OPCODES: [Opcode; 36] = [
+    Opcode::Constant,
+    Opcode::Null,
+    Opcode::True,
+    Opcode::False,
+    Opcode::Pop,
+    Opcode::GetGlobal,
+    Opcode::SetGlobal,
+    Opcode::DefineGlobal,
+    Opcode::GetLocal,
+    Opcode::SetLocal,
+    Opcode::GetUpvalue,
+    Opcode::SetUpvalue,
+    Opcode::Add,
+    Opcode::Sub,
+    Opcode::Mul,
+    Opcode::Div,
+    Opcode::Equal,
+    Opcode::NotEqual,
+    Opcode::Less,
+    Opcode::LessEqual,
+    Opcode::Greater,
+    Opcode::GreaterEqual,
+    Opcode::Not,
+    Opcode::Negate,
+    Opcode::Jump,
+    Opcode::JumpIfFalse,
+    Opcode::Array,
+    Opcode::Hash,
+    Opcode::Call,
+    Opcode::TailCall,
+    Opcode::Invoke,
+    Opcode::GetProperty,
+    Opcode::Closure,
+    Opcode::CloseUpvalues,
+    Opcode::Return,
+    Opcode::ReturnNone,
+];
+
+impl Opcode {
+    pub fn from_byte(byte: u8) -> Option<Opcode> {
+        OPCODES.get(byte as usize).copied()
+    }
+
+    // the infix operator an arithmetic or comparison opcode stands for
+    pub fn operator(&self) -> Option<TokenType> {
+        match self {
+            Opcode::Add => Some(TokenType::ADD),
+            Opcode::Sub => Some(TokenType::MINUS),
+            Opcode::Mul => Some(TokenType::MULTIPLY),
+            Opcode::Div => Some(TokenType::DIVIDE),
+            Opcode::Equal => Some(TokenType::EQUAL_EQUAL),
+            Opcode::NotEqual => Some(TokenType::BANG_EQUAL),
+            Opcode::Less => Some(TokenType::LESS),
+            Opcode::LessEqual => Some(TokenType::LESS_EQUAL),
+            Opcode::Greater => Some(TokenType::GREATER),
+            Opcode::GreaterEqual => Some(TokenType::GREATER_EQUAL),
+            _ => None,
+        }
+    }
+
+    fn from_operator(operator: &TokenType) -> Option<Opcode> {
+        OPCODES
+            .iter()
+            .copied()
+            .find(|op| op.operator().as_ref() == Some(operator))
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Default)]
+pub struct CompiledFunction {
+    pub name: String,
+    pub parameters: Vec<String>,
+    // parameters come first, then every local declared in the body
+    pub num_slots: usize,
+    pub upvalue_count: usize,
+    pub code: Vec<u8>,
+    pub constants: Vec<Object>,
+}
+
+impl CompiledFunction {
+    // human readable listing of the code, nested functions are listed after their parent
+    pub fn disassemble(&self) -> String {
+        let mut output = String::new();
+        let mut ip = 0;
+
+        writeln!(output, "== {} ==", self.display_name());
+
+        while ip < self.code.len() {
+            let op = match Opcode::from_byte(self.code[ip]) {
+                Some(op) => op,
+                None => {
+                    writeln!(output, "{:04} <unknown {}>", ip, self.code[ip]);
+                    ip += 1;
+                    continue;
+                }
+            };
+
+            write!(output, "{:04} {:?}", ip, op);
+            ip += 1;
+
+            match op {
+                Opcode::Call | Opcode::TailCall => {
+                    write!(output, " {}", self.code[ip]);
+                    ip += 1;
+                }
+                Opcode::Invoke => {
+                    let index = self.read_u16(ip);
+                    write!(output, " {} ({:?}) {}", index, self.constants[index], self.code[ip + 2]);
+                    ip += 3;
+                }
+                Opcode::Constant | Opcode::GetProperty => {
+                    let index = self.read_u16(ip);
+                    write!(output, " {} ({:?})", index, self.constants[index]);
+                    ip += 2;
+                }
+                Opcode::Closure => {
+                    let index = self.read_u16(ip);
+                    ip += 2;
+                    write!(output, " {}", index);
+
+                    if let Object::CompiledFunction(function) = &self.constants[index] {
+                        for _ in 0..function.upvalue_count {
+                            let kind = if self.code[ip] == 1 { "local" } else { "upvalue" };
+                            write!(output, " {} {}", kind, self.read_u16(ip + 1));
+                            ip += 3;
+                        }
+                    }
+                }
+                Opcode::GetGlobal
+                | Opcode::SetGlobal
+                | Opcode::DefineGlobal
+                | Opcode::GetLocal
+                | Opcode::SetLocal
+                | Opcode::GetUpvalue
+                | Opcode::SetUpvalue
+                | Opcode::Jump
+                | Opcode::JumpIfFalse
+                | Opcode::Array
+                | Opcode::Hash
+                | Opcode::CloseUpvalues => {
+                    write!(output, " {}", self.read_u16(ip));
+                    ip += 2;
+                }
+                _ => {}
+            }
+
+            output.push('\n');
+        }
+
+        for constant in &self.constants {
+            if let Object::CompiledFunction(function) = constant {
+                output.push_str(&function.disassemble());
+            }
+        }
+
+        output
+    }
+
+    pub fn display_name(&self) -> &str {
+        if self.name.is_empty() {
+            "<anonymous>"
+        } else {
+            &self.name
+        }
+    }
+
+    fn read_u16(&self, ip: usize) -> usize {
+        u16::from_be_bytes([self.code[ip], self.code[ip + 1]]) as usize
+    }
+}
+
+pub struct Bytecode {
+    pub main: Rc<CompiledFunction>,
+    // names of the global slots, shared by every program compiled with the same compiler
+    pub global_names: Vec<String>,
+}
+
+struct Loop {
+    exit_jumps: Vec<usize>,
+    stack_depth: usize,
+    first_slot: usize,
+}
+
+struct FunctionState {
+    function: CompiledFunction,
+    // for every scope of the function from the outermost, the frame slots of the slots the
+    // resolver gave out in it
+    scopes: Vec<Vec<usize>>,
+    upvalues: Vec<(bool, u16)>,
+    // temporaries on top of the slots, `break` pops them before leaving the loop
+    stack_depth: usize,
+    loops: Vec<Loop>,
+}
+
+impl FunctionState {
+    fn new(name: String, parameters: Vec<String>, scopes: Vec<Vec<usize>>) -> Self {
+        FunctionState {
+            function: CompiledFunction {
+                name,
+                parameters,
+                ..CompiledFunction::default()
+            },
+            scopes,
+            upvalues: vec![],
+            stack_depth: 0,
+            loops: vec![],
+        }
+    }
+}
+
+// what the code of a statement does with its value
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Value {
+    Drop,
+    Keep,
+    // the result of the function, a call there is a tail call like in the evaluator
+    Tail,
+}
+
+// Lowers the ast into bytecode for the vm.
+//
+// Compiles the output of the resolver, so names are scoped exactly like in the evaluator. Top
+// level names are globals and late bound. Every slot the resolver gives out inside functions and
+// blocks gets a fixed slot in the call frame, the ones of a block's declarations when the block
+// starts, so closures created before a declaration already capture its binding.
+//
+// The compiler keeps the global names between programs, the bytecode it returns has to run on a
+// vm that only ran programs of the same compiler.
+pub struct Compiler {
+    resolver: Resolver,
+    globals: Vec<String>,
+    states: Vec<FunctionState>,
+}
+
+impl Default for Compiler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Compiler {
+    pub fn new() -> Self {
+        Compiler {
+            resolver: Resolver::new(make_builtin().into_keys()),
+            globals: vec![],
+            states: vec![],
+        }
+    }
+
+    pub fn compile(&mut self, program: AstNode) -> Result<Bytecode, String> {
+        let AstNode::Program(statements) = self.resolver.resolve(program)?;
+
+        self.states = vec![FunctionState::new(String::from("<script>"), vec![], vec![])];
+
+        let compiled = self.compile_program(statements);
+        let state = self.states.pop().unwrap();
+
+        compiled?;
+
+        Ok(Bytecode {
+            main: Rc::new(state.function),
+            global_names: self.globals.clone(),
+        })
+    }
+
+    // like `Evaluator::eval_program` the last statement is the result, `let`, `while` and `fn`
+    // declarations have none
+    fn compile_program(&mut self, statements: Vec<Statement>) -> Result<(), String> {
+        if statements.is_empty() {
+            self.emit(Opcode::Null);
+            self.emit(Opcode::Return);
+
+            return Ok(());
+        }
+
+        let count = statements.len();
+
+        for (index, statement) in statements.into_iter().enumerate() {
+            if index + 1 < count {
+                self.compile_statement(statement, Value::Drop)?;
+            } else if has_value(&statement) {
+                self.compile_statement(statement, Value::Keep)?;
+                self.emit(Opcode::Return);
+            } else {
+                self.compile_statement(statement, Value::Drop)?;
+                self.emit(Opcode::ReturnNone);
+            }
+        }
+
+        Ok(())
+    }
+
+    fn compile_statement(&mut self, statement: Statement, value: Value) -> Result<(), String> {
+        match statement {
+            Statement::Let(identifier, expr) => {
+                self.compile_let_statement(*identifier, *expr)?;
+
+                if value != Value::Drop {
+                    self.emit(Opcode::Null);
+                }
+            }
+            Statement::Return(expr) => {
+                match *expr {
+                    // like in the evaluator only inside a function
+                    Expression::FnCall(function, arguments) if self.states.len() > 1 => {
+                        self.compile_call(*function, arguments, Opcode::TailCall)?
+                    }
+                    expr => self.compile_expression(expr)?,
+                }
+
+                self.emit(Opcode::Return);
+
+                if value == Value::Drop {
+                    self.emit(Opcode::Pop);
+                }
+            }
+            Statement::Expr(expr) => {
+                match (*expr, value) {
+                    (Expression::FnCall(function, arguments), Value::Tail) => {
+                        self.compile_call(*function, arguments, Opcode::TailCall)?
+                    }
+                    (Expression::If(condition, statements, else_statements), Value::Tail) => {
+                        self.compile_if(*condition, statements, else_statements, Value::Tail)?
+                    }
+                    (expr, _) => self.compile_expression(expr)?,
+                }
+
+                if value == Value::Drop {
+                    self.emit(Opcode::Pop);
+                }
+            }
+            // only the last statement of a function or of an `if` branch in tail position is one
+            Statement::BlockStatement(statements) => match value {
+                Value::Tail => self.compile_block(statements, Value::Keep)?,
+                value => self.compile_block(statements, value)?,
+            },
+        }
+
+        Ok(())
+    }
+
+    fn compile_let_statement(&mut self, identifier: Expression, expr: Expression) -> Result<(), String> {
+        match identifier {
+            Expression::Identifier(name) if !name.is_empty() => {
+                self.compile_bound_expression(&name, expr)?;
+
+                let index = self.global_index(&name)?;
+                self.emit_u16(Opcode::DefineGlobal, index);
+            }
+            Expression::Local(name, _, slot) => {
+                let slot = self.declare_local(slot)?;
+
+                self.compile_bound_expression(&name, expr)?;
+                self.emit_u16(Opcode::SetLocal, slot);
+                self.emit(Opcode::Pop);
+            }
+            _ => {}
+        }
+
+        Ok(())
+    }
+
+    // a function literal is named after the `let` that binds it, like a declaration
+    fn compile_bound_expression(&mut self, name: &str, expr: Expression) -> Result<(), String> {
+        match expr {
+            Expression::Fn(fn_name, parameters, body) if is_anonymous(&fn_name) => {
+                self.compile_function(name.to_string(), parameters, body)
+            }
+            expr => self.compile_expression(expr),
+        }
+    }
+
+    // compiles the statements into the current scope, `value` is what happens to the value of the
+    // last one
+    fn compile_block(&mut self, statements: Vec<Statement>, value: Value) -> Result<(), String> {
+        // closures see the declarations after them, their bindings have to exist before
+        for statement in &statements {
+            if let Some(slot) = declared_slot(statement) {
+                self.declare_local(slot)?;
+            }
+        }
+
+        if statements.is_empty() {
+            if value != Value::Drop {
+                self.emit(Opcode::Null);
+            }
+
+            return Ok(());
+        }
+
+        let count = statements.len();
+
+        for (index, statement) in statements.into_iter().enumerate() {
+            match index + 1 == count {
+                true => self.compile_statement(statement, value)?,
+                false => self.compile_statement(statement, Value::Drop)?,
+            }
+        }
+
+        Ok(())
+    }
+
+    fn compile_scoped_block(&mut self, block_stmt: Statement, value: Value) -> Result<(), String> {
+        self.begin_scope();
+
+        let compiled = match block_stmt {
+            Statement::BlockStatement(statements) => self.compile_block(statements, value),
+            statement => self.compile_statement(statement, value),
+        };
+
+        self.end_scope();
+
+        compiled
+    }
+
+    fn compile_expression(&mut self, expr: Expression) -> Result<(), String> {
+        match expr {
+            Expression::Integer(int) => self.emit_constant(Object::Integer(int))?,
+            Expression::Float(float) => self.emit_constant(Object::Float(float))?,
+            Expression::String(string) => self.emit_constant(Object::String(string))?,
+            Expression::Boolean(true) => self.emit(Opcode::True),
+            Expression::Boolean(false) => self.emit(Opcode::False),
+            Expression::Identifier(_) | Expression::Local(..) => self.compile_identifier(expr)?,
+            Expression::Array(exprs) => {
+                let count = exprs.len();
+
+                for expr in exprs {
+                    self.compile_expression(expr)?;
+                }
+
+                self.emit_u16(Opcode::Array, operand(count, "array elements")?);
+            }
+            Expression::Hash(hashes) => {
+                let count = hashes.len();
+
+                for (key, value) in hashes {
+                    self.compile_expression(key)?;
+                    self.compile_expression(value)?;
+                }
+
+                self.emit_u16(Opcode::Hash, operand(count, "hash pairs")?);
+            }
+            Expression::Prefix(TokenType::BANG, expr) => {
+                self.compile_expression(*expr)?;
+                self.emit(Opcode::Not);
+            }
+            Expression::Prefix(TokenType::MINUS, expr) => {
+                self.compile_expression(*expr)?;
+                self.emit(Opcode::Negate);
+            }
+            Expression::Prefix(operator, _) => return Err(format!("unsupported prefix operator: {}", operator)),
+            Expression::Infix(left, operator, right) => {
+                let op = Opcode::from_operator(&operator)
+                    .ok_or_else(|| format!("unsupported infix operator: {}", operator))?;
+
+                self.compile_expression(*left)?;
+                self.compile_expression(*right)?;
+                self.emit(op);
+            }
+            Expression::If(condition, statements, else_statements) => {
+                self.compile_if(*condition, statements, else_statements, Value::Keep)?
+            }
+            Expression::While(condition, block_stmt) => self.compile_while_expression(*condition, block_stmt)?,
+            Expression::Break => self.compile_break()?,
+            Expression::Fn(fn_name, parameters, body) => match is_anonymous(&fn_name) {
+                true => self.compile_function(String::new(), parameters, body)?,
+                false => self.compile_fn_declaration(*fn_name, parameters, body)?,
+            },
+            Expression::FnCall(function, arguments) => self.compile_call(*function, arguments, Opcode::Call)?,
+            Expression::MethodCall(receiver, method, arguments) => {
+                let count = arguments.len();
+
+                if count > u8::MAX as usize {
+                    return Err(format!("too many arguments: {}", count));
+                }
+
+                self.compile_expression(*receiver)?;
+
+                for argument in arguments {
+                    self.compile_expression(argument)?;
+                }
+
+                let index = self.add_constant(Object::String(method))?;
+                self.emit_u16(Opcode::Invoke, index);
+
+                let state = self.state_mut();
+                state.function.code.push(count as u8);
+                state.stack_depth -= count;
+            }
+            Expression::Property(receiver, key) => {
+                self.compile_expression(*receiver)?;
+
+                let index = self.add_constant(Object::String(key))?;
+                self.emit_u16(Opcode::GetProperty, index);
+            }
+            Expression::Assign(target, value) => {
+                if !matches!(*target, Expression::Identifier(_) | Expression::Local(..)) {
+                    return Err(String::from("invalid assignment target"));
+                }
+
+                self.compile_expression(*value)?;
+
+                match self.resolve(*target)? {
+                    Resolved::Local(slot) => self.emit_u16(Opcode::SetLocal, slot),
+                    Resolved::Upvalue(index) => self.emit_u16(Opcode::SetUpvalue, index),
+                    Resolved::Global(index) => self.emit_u16(Opcode::SetGlobal, index),
+                }
+            }
+            Expression::NON_PARSED_EXPR(_) => self.emit(Opcode::Null),
+        }
+
+        Ok(())
+    }
+
+    // `value` is `Keep`, or `Tail` when the `if` is in tail position
+    fn compile_if(
+        &mut self,
+        condition: Expression,
+        statements: Statement,
+        else_statements: Option<Statement>,
+        value: Value,
+    ) -> Result<(), String> {
+        self.compile_expression(condition)?;
+        let else_jump = self.emit_jump(Opcode::JumpIfFalse);
+
+        self.compile_scoped_block(statements, value)?;
+        let end_jump = self.emit_jump(Opcode::Jump);
+
+        // only one branch runs, the else branch starts from the same depth
+        self.state_mut().stack_depth -= 1;
+        self.patch_jump(else_jump)?;
+
+        match else_statements {
+            Some(else_statements) => self.compile_scoped_block(else_statements, value)?,
+            None => self.emit(Opcode::Null),
+        }
+
+        self.patch_jump(end_jump)
+    }
+
+    // `op` is `Call` or `TailCall`, a tail call of a builtin runs like any other call
+    fn compile_call(&mut self, function: Expression, arguments: Vec<Expression>, op: Opcode) -> Result<(), String> {
+        let count = arguments.len();
+
+        if count > u8::MAX as usize {
+            return Err(format!("too many arguments: {}", count));
+        }
+
+        self.compile_expression(function)?;
+
+        for argument in arguments {
+            self.compile_expression(argument)?;
+        }
+
+        self.emit_u8(op, count as u8);
+
+        Ok(())
+    }
+
+    fn compile_identifier(&mut self, identifier: Expression) -> Result<(), String> {
+        match self.resolve(identifier)? {
+            Resolved::Local(slot) => self.emit_u16(Opcode::GetLocal, slot),
+            Resolved::Upvalue(index) => self.emit_u16(Opcode::GetUpvalue, index),
+            Resolved::Global(index) => self.emit_u16(Opcode::GetGlobal, index),
+        }
+
+        Ok(())
+    }
+
+    fn compile_while_expression(&mut self, condition: Expression, block_stmt: Statement) -> Result<(), String> {
+        let loop_start = self.state().function.code.len();
+
+        self.compile_expression(condition)?;
+        let exit_jump = self.emit_jump(Opcode::JumpIfFalse);
+
+        let first_slot = self.state().function.num_slots;
+        let stack_depth = self.state().stack_depth;
+
+        self.state_mut().loops.push(Loop {
+            exit_jumps: vec![exit_jump],
+            stack_depth,
+            first_slot,
+        });
+
+        let compiled = self.compile_scoped_block(block_stmt, Value::Drop);
+        let exit_jumps = self.state_mut().loops.pop().unwrap().exit_jumps;
+
+        compiled?;
+
+        // every iteration gets fresh bindings, closures of this one keep theirs
+        if self.state().function.num_slots > first_slot {
+            self.emit_u16(Opcode::CloseUpvalues, first_slot);
+        }
+
+        self.emit_u16(Opcode::Jump, operand(loop_start, "code size")?);
+
+        for jump in exit_jumps {
+            self.patch_jump(jump)?;
+        }
+
+        // like the evaluator the loop itself has no value
+        self.emit(Opcode::Null);
+
+        Ok(())
+    }
+
+    fn compile_break(&mut self) -> Result<(), String> {
+        let (stack_depth, first_slot) = match self.state().loops.last() {
+            Some(current) => (current.stack_depth, current.first_slot),
+            None if self.states.len() > 1 => {
+                // a stray `break` leaves the function with null
+                self.emit(Opcode::Null);
+                self.emit(Opcode::Return);
+
+                return Ok(());
+            }
+            None => return Err(String::from("break outside of a loop")),
+        };
+
+        let current_depth = self.state().stack_depth;
+
+        for _ in stack_depth..current_depth {
+            self.emit(Opcode::Pop);
+        }
+
+        self.emit_u16(Opcode::CloseUpvalues, first_slot);
+
+        let jump = self.emit_jump(Opcode::Jump);
+        self.state_mut().loops.last_mut().unwrap().exit_jumps.push(jump);
+
+        // the code after is unreachable, it still expects `break` to have pushed a value
+        self.state_mut().stack_depth = current_depth;
+        self.emit(Opcode::Null);
+
+        Ok(())
+    }
+
+    fn compile_fn_declaration(
+        &mut self,
+        fn_name: Expression,
+        parameters: FnParameter,
+        body: FnBody,
+    ) -> Result<(), String> {
+        match fn_name {
+            Expression::Local(name, _, slot) => {
+                let slot = self.declare_local(slot)?;
+
+                self.compile_function(name, parameters, body)?;
+                self.emit_u16(Opcode::SetLocal, slot);
+                self.emit(Opcode::Pop);
+            }
+            Expression::Identifier(name) => {
+                let index = self.global_index(&name)?;
+
+                self.compile_function(name, parameters, body)?;
+                self.emit_u16(Opcode::DefineGlobal, index);
+            }
+            _ => return Err(String::from("invalid function name")),
+        }
+
+        // a declaration has no value
+        self.emit(Opcode::Null);
+
+        Ok(())
+    }
+
+    fn compile_function(&mut self, name: String, parameters: FnParameter, body: FnBody) -> Result<(), String> {
+        let names = parameters
+            .iter()
+            .map(|parameter| binding_name(parameter).cloned().unwrap_or_default())
+            .collect();
+        self.states.push(FunctionState::new(name, names, vec![vec![]]));
+
+        let compiled = self.compile_function_body(parameters, body);
+        let state = self.states.pop().unwrap();
+
+        compiled?;
+
+        let upvalues = state.upvalues;
+        let mut function = state.function;
+        function.upvalue_count = upvalues.len();
+
+        let index = self.add_constant(Object::CompiledFunction(Rc::new(function)))?;
+        self.emit_u16(Opcode::Closure, index);
+
+        for (is_local, index) in upvalues {
+            let code = &mut self.state_mut().function.code;
+
+            code.push(is_local as u8);
+            code.extend_from_slice(&index.to_be_bytes());
+        }
+
+        Ok(())
+    }
+
+    fn compile_function_body(&mut self, parameters: FnParameter, body: FnBody) -> Result<(), String> {
+        // the arguments fill the first frame slots, the resolver numbers only the named ones
+        for parameter in parameters {
+            let slot = self.frame_slot()?;
+
+            if let Expression::Local(..) = parameter {
+                self.state_mut().scopes[0].push(slot);
+            }
+        }
+
+        match body {
+            Statement::BlockStatement(statements) => self.compile_block(statements, Value::Tail)?,
+            _ => self.emit(Opcode::Null),
+        }
+
+        self.emit(Opcode::Return);
+
+        Ok(())
+    }
+
+    fn begin_scope(&mut self) {
+        self.state_mut().scopes.push(vec![]);
+    }
+
+    fn end_scope(&mut self) {
+        self.state_mut().scopes.pop();
+    }
+
+    // a new slot in the frame of the current function
+    fn frame_slot(&mut self) -> Result<usize, String> {
+        let function = &mut self.state_mut().function;
+        let slot = function.num_slots;
+
+        if slot > u16::MAX as usize {
+            return Err(format!("too many local variables in {}", function.name));
+        }
+
+        function.num_slots += 1;
+
+        Ok(slot)
+    }
+
+    // the frame slot of a slot the resolver gave out in the innermost scope, the slots before it
+    // get theirs too so they stay in the order of the resolver
+    fn declare_local(&mut self, slot: usize) -> Result<usize, String> {
+        while self.state().scopes.last().unwrap().len() <= slot {
+            let frame_slot = self.frame_slot()?;
+            self.state_mut().scopes.last_mut().unwrap().push(frame_slot);
+        }
+
+        Ok(self.state().scopes.last().unwrap()[slot])
+    }
+
+    // where the value of an identifier the resolver looked at lives
+    fn resolve(&mut self, identifier: Expression) -> Result<Resolved, String> {
+        let (mut depth, slot) = match identifier {
+            Expression::Local(_, depth, slot) => (depth, slot),
+            Expression::Identifier(name) => return Ok(Resolved::Global(self.global_index(&name)?)),
+            identifier => return Err(format!("not an identifier: {:?}", identifier)),
+        };
+
+        let current = self.states.len() - 1;
+
+        // the scopes of the resolver are the ones of every function from the innermost
+        for state_index in (0..=current).rev() {
+            let scopes = &self.states[state_index].scopes;
+
+            if depth >= scopes.len() {
+                depth -= scopes.len();
+                continue;
+            }
+
+            let frame_slot = match scopes[scopes.len() - 1 - depth].get(slot) {
+                Some(&frame_slot) => frame_slot,
+                None => break,
+            };
+
+            return match state_index == current {
+                true => Ok(Resolved::Local(frame_slot)),
+                false => self.capture(current, state_index, frame_slot).map(Resolved::Upvalue),
+            };
+        }
+
+        Err(format!("unresolved local variable: {}", slot))
+    }
+
+    // the upvalue of the function at `state_index` for a frame slot of an enclosing function
+    fn capture(&mut self, state_index: usize, owner: usize, frame_slot: usize) -> Result<usize, String> {
+        if state_index - 1 == owner {
+            return self.add_upvalue(state_index, true, frame_slot);
+        }
+
+        let index = self.capture(state_index - 1, owner, frame_slot)?;
+
+        self.add_upvalue(state_index, false, index)
+    }
+
+    fn add_upvalue(&mut self, state_index: usize, is_local: bool, index: usize) -> Result<usize, String> {
+        let upvalue = (is_local, operand(index, "captured variables")? as u16);
+        let upvalues = &mut self.states[state_index].upvalues;
+
+        if let Some(position) = upvalues.iter().position(|captured| *captured == upvalue) {
+            return Ok(position);
+        }
+
+        upvalues.push(upvalue);
+
+        Ok(upvalues.len() - 1)
+    }
+
+    fn global_index(&mut self, name: &str) -> Result<usize, String> {
+        let index = match self.globals.iter().position(|global| global == name) {
+            Some(index) => index,
+            None => {
+                self.globals.push(name.to_string());
+                self.globals.len() - 1
+            }
+        };
+
+        operand(index, "global variables")
+    }
+
+    fn add_constant(&mut self, constant: Object) -> Result<usize, String> {
+        let constants = &mut self.state_mut().function.constants;
+
+        constants.push(constant);
+
+        operand(constants.len() - 1, "constants")
+    }
+
+    fn emit_constant(&mut self, constant: Object) -> Result<(), String> {
+        let index = self.add_constant(constant)?;
+        self.emit_u16(Opcode::Constant, index);
+
+        Ok(())
+    }
+
+    fn emit(&mut self, op: Opcode) {
+        let effect = match op {
+            Opcode::Constant
+            | Opcode::Null
+            | Opcode::True
+            | Opcode::False
+            | Opcode::GetGlobal
+            | Opcode::GetLocal
+            | Opcode::GetUpvalue
+            | Opcode::Closure
+            | Opcode::Array
+            | Opcode::Hash => 1,
+            Opcode::Pop | Opcode::DefineGlobal | Opcode::JumpIfFalse => -1,
+            op if op.operator().is_some() => -1,
+            // the remaining ones are balanced, or like `Return` never continue
+            _ => 0,
+        };
+
+        let state = self.state_mut();
+
+        state.function.code.push(op as u8);
+        state.stack_depth = (state.stack_depth as isize + effect) as usize;
+    }
+
+    fn emit_u8(&mut self, op: Opcode, operand: u8) {
+        self.emit(op);
+
+        let state = self.state_mut();
+
+        state.function.code.push(operand);
+
+        if matches!(op, Opcode::Call | Opcode::TailCall) {
+            state.stack_depth -= operand as usize;
+        }
+    }
+
+    fn emit_u16(&mut self, op: Opcode, operand: usize) {
+        self.emit(op);
+
+        let state = self.state_mut();
+
+        state.function.code.extend_from_slice(&(operand as u16).to_be_bytes());
+
+        match op {
+            Opcode::Array => state.stack_depth -= operand,
+            Opcode::Hash => state.stack_depth -= operand * 2,
+            _ => {}
+        }
+    }
+
+    fn emit_jump(&mut self, op: Opcode) -> usize {
+        self.emit_u16(op, u16::MAX as usize);
+
+        self.state().function.code.len() - 2
+    }
+
+    fn patch_jump(&mut self, position: usize) -> Result<(), String> {
+        let code = &mut self.state_mut().function.code;
+        let target = operand(code.len(), "code size")? as u16;
+
+        code[position..position + 2].copy_from_slice(&target.to_be_bytes());
+
+        Ok(())
+    }
+
+    fn state(&self) -> &FunctionState {
+        self.states.last().unwrap()
+    }
+
+    fn state_mut(&mut self) -> &mut FunctionState {
+        self.states.last_mut().unwrap()
+    }
+}
+
+enum Resolved {
+    Local(usize),
+    Upvalue(usize),
+    Global(usize),
+}
+
+fn operand(value: usize, what: &str) -> Result<usize, String> {
+    if value > u16::MAX as usize {
+        Err(format!("too many {}: {}", what, value))
+    } else {
+        Ok(value)
+    }
+}
+
+// the name a declaration or parameter binds
+fn binding_name(identifier: &Expression) -> Option<&String> {
+    match identifier {
+        Expression::Identifier(name) | Expression::Local(name, _, _) if !name.is_empty() => Some(name),
+        _ => None,
+    }
+}
+
+fn is_anonymous(fn_name: &Expression) -> bool {
+    binding_name(fn_name).is_none()
+}
+
+// the slot the resolver gave a `let` or `fn` declaration of a block
+fn declared_slot(statement: &Statement) -> Option<usize> {
+    let identifier = match statement {
+        Statement::Let(identifier, _) => identifier.as_ref(),
+        Statement::Expr(expr) => match expr.as_ref() {
+            Expression::Fn(fn_name, _, _) => fn_name.as_ref(),
+            _ => return None,
+        },
+        _ => return None,
+    };
+
+    match identifier {
+        Expression::Local(_, 0, slot) => Some(*slot),
+        _ => None,
+    }
+}
+
+fn has_value(statement: &Statement) -> bool {
+    match statement {
+        Statement::Let(_, _) => false,
+        Statement::Expr(expr) => match expr.as_ref() {
+            Expression::While(_, _) => false,
+            Expression::Fn(fn_name, _, _) => is_anonymous(fn_name),
+            _ => true,
+        },
+        _ => true,
+    }
+}
+
+#[cfg(test)]
+mod unit_test {
+    use crate::compiler::{Compiler, Opcode, OPCODES};
+    use crate::parser::Parser;
+
+    fn disassemble(input: &str) -> String {
+        let program = Parser::get(input).parse_program();
+
+        Compiler::new().compile(program).unwrap().main.disassemble()
+    }
+
+    #[test]
+    fn opcode_from_byte() {
+        for (byte, op) in OPCODES.iter().enumerate() {
+            assert_eq!(Opcode::from_byte(byte as u8), Some(*op));
+        }
+
+        assert_eq!(Opcode::from_byte(OPCODES.len() as u8), None);
+    }
+
+    #[test]
+    fn compile_infix_expression() {
+        assert_eq!(
+            disassemble("1 + 2 * 3"),
+            r#"== <script> ==
+0000 Constant 0 (Integer(1))
+0003 Constant 1 (Integer(2))
+0006 Constant 2 (Integer(3))
+0009 Mul
+0010 Add
+0011 Return
+"#
+        );
+    }
+
+    #[test]
+    fn compile_method_call() {
+        assert_eq!(
+            disassemble(r#""lynx".pad(1, 2)"#),
+            r#"== <script> ==
+0000 Constant 0 (String("lynx"))
+0003 Constant 1 (Integer(1))
+0006 Constant 2 (Integer(2))
+0009 Invoke 3 (String("pad")) 2
+0013 Return
+"#
+        );
+    }
+
+    #[test]
+    fn compile_global_let_statement() {
+        assert_eq!(
+            disassemble("let foo = 1; foo;"),
+            r#"== <script> ==
+0000 Constant 0 (Integer(1))
+0003 DefineGlobal 0
+0006 GetGlobal 0
+0009 Return
+"#
+        );
+    }
+
+    #[test]
+    fn compile_closure() {
+        assert_eq!(
+            disassemble("fn counter() { let count = 0; return fn() { count = count + 1; }; }"),
+            r#"== <script> ==
+0000 Closure 0
+0003 DefineGlobal 0
+0006 Null
+0007 Pop
+0008 ReturnNone
+== counter ==
+0000 Constant 0 (Integer(0))
+0003 SetLocal 0
+0006 Pop
+0007 Closure 1 local 0
+0013 Return
+0014 Return
+== <anonymous> ==
+0000 GetUpvalue 0
+0003 Constant 0 (Integer(1))
+0006 Add
+0007 SetUpvalue 0
+0010 Return
+"#
+        );
+    }
+
+    #[test]
+    fn compile_closure_before_declaration() {
+        assert_eq!(
+            disassemble("fn f() { let g = fn() { h }; let h = 1; g() }"),
+            r#"== <script> ==
+0000 Closure 0
+0003 DefineGlobal 0
+0006 Null
+0007 Pop
+0008 ReturnNone
+== f ==
+0000 Closure 0 local 1
+0006 SetLocal 0
+0009 Pop
+0010 Constant 1 (Integer(1))
+0013 SetLocal 1
+0016 Pop
+0017 GetLocal 0
+0020 TailCall 0
+0022 Return
+== g ==
+0000 GetUpvalue 0
+0003 Return
+"#
+        );
+    }
+
+    #[test]
+    fn compile_tail_call() {
+        assert_eq!(
+            disassemble("fn f(n) { if (n) { f(n - 1) } else { f(n) + 1 } }"),
+            r#"== <script> ==
+0000 Closure 0
+0003 DefineGlobal 0
+0006 Null
+0007 Pop
+0008 ReturnNone
+== f ==
+0000 GetLocal 0
+0003 JumpIfFalse 21
+0006 GetGlobal 0
+0009 GetLocal 0
+0012 Constant 0 (Integer(1))
+0015 Sub
+0016 TailCall 1
+0018 Jump 33
+0021 GetGlobal 0
+0024 GetLocal 0
+0027 Call 1
+0029 Constant 1 (Integer(1))
+0032 Add
+0033 Return
+"#
+        );
+    }
+
+    #[test]
+    fn compile_while_break() {
+        assert_eq!(
+            disassemble("while (true) { let x = 1; break; }"),
+            r#"== <script> ==
+0000 True
+0001 JumpIfFalse 25
+0004 Constant 0 (Integer(1))
+0007 SetLocal 0
+0010 Pop
+0011 CloseUpvalues 0
+0014 Jump 25
+0017 Null
+0018 Pop
+0019 CloseUpvalues 0
+0022 Jump 0
+0025 Null
+0026 Pop
+0027 ReturnNone
+"#
+        );
+    }
+
+    #[test]
+    fn compile_break_outside_loop() {
+        let program = Parser::get("break;").parse_program();
+
+        assert_eq!(
+            Compiler::new().compile(program).err(),
+            Some(String::from("break outside of a loop"))
+        );
+    }
+}
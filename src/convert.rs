@@ -0,0 +1,393 @@
+use std::collections::HashMap;
+use std::hash::Hash;
+
+use crate::evaluator::Evaluator;
+use crate::object::{BuiltinFunction, NativeObject, Object, RuntimeError};
+use crate::ordered_hash::OrderedHash;
+
+// Conversions between rust values and `Object`, used by host functions to take and return
+// plain rust types.
+//
+// Integers convert to and from every rust integer type, a value out of the target's range is an
+// error. `Option` maps `None` to null, tuples are arrays of a fixed length.
+pub trait IntoLynx {
+    fn into_lynx(self) -> Result<Object, RuntimeError>;
+}
+
+pub trait FromLynx: Sized {
+    fn from_lynx(object: &Object) -> Result<Self, RuntimeError>;
+}
+
+fn expected(type_name: &str, object: &Object) -> RuntimeError {
+    RuntimeError::new(format!("expected {}, got {}", type_name, object.type_name()))
+}
+
+impl IntoLynx for Object {
+    fn into_lynx(self) -> Result<Object, RuntimeError> {
+        Ok(self)
+    }
+}
+
+impl FromLynx for Object {
+    fn from_lynx(object: &Object) -> Result<Self, RuntimeError> {
+        Ok(object.clone())
+    }
+}
+
+impl IntoLynx for NativeObject {
+    fn into_lynx(self) -> Result<Object, RuntimeError> {
+        Ok(Object::Native(self))
+    }
+}
+
+impl FromLynx for NativeObject {
+    fn from_lynx(object: &Object) -> Result<Self, RuntimeError> {
+        match object {
+            Object::Native(native) => Ok(native.clone()),
+            other => Err(expected("native object", other)),
+        }
+    }
+}
+
+macro_rules! integer_conversions {
+    ($($int:ty),*) => {
+        $(
+            impl IntoLynx for $int {
+                fn into_lynx(self) -> Result<Object, RuntimeError> {
+                    match i64::try_from(self) {
+                        Ok(int) => Ok(Object::Integer(int)),
+                        Err(_) => Err(RuntimeError::new(format!("integer out of range: {}", self))),
+                    }
+                }
+            }
+
+            impl FromLynx for $int {
+                fn from_lynx(object: &Object) -> Result<Self, RuntimeError> {
+                    match object {
+                        Object::Integer(int) => <$int>::try_from(*int).map_err(|_| {
+                            RuntimeError::new(format!("integer out of range for {}: {}", stringify!($int), int))
+                        }),
+                        other => Err(expected("integer", other)),
+                    }
+                }
+            }
+        )*
+    };
+}
+
+integer_conversions!(u8, u16, u32, u64, u128, usize, i8, i16, i32, i64, i128, isize);
+
+impl IntoLynx for f64 {
+    fn into_lynx(self) -> Result<Object, RuntimeError> {
+        Ok(Object::Float(self))
+    }
+}
+
+// integers are taken where a float is expected
+impl FromLynx for f64 {
+    fn from_lynx(object: &Object) -> Result<Self, RuntimeError> {
+        match object {
+            Object::Float(float) => Ok(*float),
+            Object::Integer(int) => Ok(*int as f64),
+            other => Err(expected("float", other)),
+        }
+    }
+}
+
+impl IntoLynx for f32 {
+    fn into_lynx(self) -> Result<Object, RuntimeError> {
+        Ok(Object::Float(self as f64))
+    }
+}
+
+impl FromLynx for f32 {
+    fn from_lynx(object: &Object) -> Result<Self, RuntimeError> {
+        f64::from_lynx(object).map(|float| float as f32)
+    }
+}
+
+impl IntoLynx for bool {
+    fn into_lynx(self) -> Result<Object, RuntimeError> {
+        Ok(Object::Boolean(self))
+    }
+}
+
+impl FromLynx for bool {
+    fn from_lynx(object: &Object) -> Result<Self, RuntimeError> {
+        match object {
+            Object::Boolean(bl) => Ok(*bl),
+            other => Err(expected("boolean", other)),
+        }
+    }
+}
+
+impl IntoLynx for String {
+    fn into_lynx(self) -> Result<Object, RuntimeError> {
+        Ok(Object::String(self))
+    }
+}
+
+impl IntoLynx for &str {
+    fn into_lynx(self) -> Result<Object, RuntimeError> {
+        Ok(Object::String(self.to_string()))
+    }
+}
+
+impl FromLynx for String {
+    fn from_lynx(object: &Object) -> Result<Self, RuntimeError> {
+        match object {
+            Object::String(string) => Ok(string.clone()),
+            other => Err(expected("string", other)),
+        }
+    }
+}
+
+impl IntoLynx for () {
+    fn into_lynx(self) -> Result<Object, RuntimeError> {
+        Ok(Object::Null)
+    }
+}
+
+impl<T: IntoLynx> IntoLynx for Option<T> {
+    fn into_lynx(self) -> Result<Object, RuntimeError> {
+        match self {
+            Some(value) => value.into_lynx(),
+            None => Ok(Object::Null),
+        }
+    }
+}
+
+impl<T: FromLynx> FromLynx for Option<T> {
+    fn from_lynx(object: &Object) -> Result<Self, RuntimeError> {
+        match object {
+            Object::Null => Ok(None),
+            object => T::from_lynx(object).map(Some),
+        }
+    }
+}
+
+// a host function failing with an error
+impl<T: IntoLynx> IntoLynx for Result<T, RuntimeError> {
+    fn into_lynx(self) -> Result<Object, RuntimeError> {
+        self.and_then(IntoLynx::into_lynx)
+    }
+}
+
+impl<T: IntoLynx> IntoLynx for Vec<T> {
+    fn into_lynx(self) -> Result<Object, RuntimeError> {
+        self.into_iter()
+            .map(IntoLynx::into_lynx)
+            .collect::<Result<Vec<_>, _>>()
+            .map(Object::Array)
+    }
+}
+
+impl<T: FromLynx> FromLynx for Vec<T> {
+    fn from_lynx(object: &Object) -> Result<Self, RuntimeError> {
+        match object {
+            Object::Array(items) => items.iter().map(T::from_lynx).collect(),
+            other => Err(expected("array", other)),
+        }
+    }
+}
+
+impl<K: IntoLynx, V: IntoLynx> IntoLynx for HashMap<K, V> {
+    fn into_lynx(self) -> Result<Object, RuntimeError> {
+        self.into_iter()
+            .map(|(key, value)| match key.into_lynx()? {
+                key if !key.is_hashable() => Err(RuntimeError::new(key.unhashable_error())),
+                key => Ok((key, value.into_lynx()?)),
+            })
+            .collect::<Result<OrderedHash, RuntimeError>>()
+            .map(Object::Hash)
+    }
+}
+
+impl<K: FromLynx + Eq + Hash, V: FromLynx> FromLynx for HashMap<K, V> {
+    fn from_lynx(object: &Object) -> Result<Self, RuntimeError> {
+        match object {
+            Object::Hash(pairs) => pairs
+                .iter()
+                .map(|(key, value)| Ok((K::from_lynx(key)?, V::from_lynx(value)?)))
+                .collect(),
+            other => Err(expected("hash", other)),
+        }
+    }
+}
+
+macro_rules! tuple_conversions {
+    ($(($len:expr, $($name:ident $index:tt),+))*) => {
+        $(
+            impl<$($name: IntoLynx),+> IntoLynx for ($($name,)+) {
+                fn into_lynx(self) -> Result<Object, RuntimeError> {
+                    Ok(Object::Array(vec![$(self.$index.into_lynx()?),+]))
+                }
+            }
+
+            impl<$($name: FromLynx),+> FromLynx for ($($name,)+) {
+                fn from_lynx(object: &Object) -> Result<Self, RuntimeError> {
+                    match object {
+                        Object::Array(items) if items.len() == $len => Ok(($($name::from_lynx(&items[$index])?,)+)),
+                        other => Err(expected(concat!("array of ", $len), other)),
+                    }
+                }
+            }
+        )*
+    };
+}
+
+tuple_conversions! {
+    (1, A 0)
+    (2, A 0, B 1)
+    (3, A 0, B 1, C 2)
+    (4, A 0, B 1, C 2, D 3)
+}
+
+// Anything `Evaluator::register_fn` takes: a closure over the raw arguments, or a rust function
+// of up to five `FromLynx` parameters returning an `IntoLynx` value. `Args` only tells the
+// implementations apart.
+pub trait IntoBuiltin<Args> {
+    fn into_builtin(self, name: &str) -> BuiltinFunction;
+}
+
+pub struct RawArguments;
+
+impl<F> IntoBuiltin<RawArguments> for F
+where
+    F: Fn(&mut Evaluator, &[Object]) -> Result<Object, RuntimeError> + 'static,
+{
+    fn into_builtin(self, name: &str) -> BuiltinFunction {
+        BuiltinFunction::new(name, None, self)
+    }
+}
+
+macro_rules! typed_builtins {
+    ($(($len:expr, $($name:ident $index:tt),*))*) => {
+        $(
+            impl<F, R, $($name),*> IntoBuiltin<fn($($name),*) -> R> for F
+            where
+                F: Fn($($name),*) -> R + 'static,
+                R: IntoLynx,
+                $($name: FromLynx),*
+            {
+                #[allow(unused_variables)]
+                fn into_builtin(self, name: &str) -> BuiltinFunction {
+                    let builtin_name = name.to_string();
+
+                    BuiltinFunction::new(name, Some($len), move |_, arguments| {
+                        self($($name::from_lynx(&arguments[$index]).map_err(|error| {
+                            RuntimeError::new(format!(
+                                "argument {} of {}: {}",
+                                $index + 1,
+                                builtin_name,
+                                error
+                            ))
+                        })?),*)
+                        .into_lynx()
+                    })
+                }
+            }
+        )*
+    };
+}
+
+typed_builtins! {
+    (0,)
+    (1, A 0)
+    (2, A 0, B 1)
+    (3, A 0, B 1, C 2)
+    (4, A 0, B 1, C 2, D 3)
+    (5, A 0, B 1, C 2, D 3, E 4)
+}
+
+#[cfg(test)]
+mod unit_test {
+    use crate::convert::{FromLynx, IntoLynx};
+    use crate::env::Env;
+    use crate::evaluator::Evaluator;
+    use crate::object::{Object, RuntimeError};
+    use crate::parser::Parser;
+    use std::cell::RefCell;
+    use std::collections::HashMap;
+    use std::rc::Rc;
+
+    #[test]
+    fn convert_round_trip() {
+        let value = (7u8, String::from("lynx"), vec![Some(true), None]);
+        let object = value.clone().into_lynx().unwrap();
+
+        assert_eq!(
+            object,
+            Object::Array(vec![
+                Object::Integer(7),
+                Object::String(String::from("lynx")),
+                Object::Array(vec![Object::Boolean(true), Object::Null])
+            ])
+        );
+        assert_eq!(<(u8, String, Vec<Option<bool>>)>::from_lynx(&object), Ok(value));
+
+        let hash = HashMap::from([(String::from("a"), 1i32)]);
+        assert_eq!(
+            HashMap::<String, i32>::from_lynx(&hash.clone().into_lynx().unwrap()),
+            Ok(hash)
+        );
+    }
+
+    #[test]
+    fn convert_errors() {
+        assert_eq!(
+            i64::from_lynx(&Object::String(String::from("1"))),
+            Err(RuntimeError::new("expected integer, got string"))
+        );
+        assert_eq!(
+            u8::from_lynx(&Object::Integer(256)),
+            Err(RuntimeError::new("integer out of range for u8: 256"))
+        );
+        assert_eq!(
+            u64::MAX.into_lynx(),
+            Err(RuntimeError::new("integer out of range: 18446744073709551615"))
+        );
+    }
+
+    fn repeat(text: String, count: u32) -> Vec<String> {
+        (0..count).map(|_| text.clone()).collect()
+    }
+
+    #[test]
+    fn convert_typed_register_fn() {
+        let mut evaluator = Evaluator::new(Rc::new(RefCell::new(Env::new())));
+
+        evaluator.register_fn("repeat", repeat);
+        evaluator.register_fn("join", |words: Vec<String>, separator: String| words.join(&separator));
+        evaluator.register_fn("checked_half", |int: u64| match int % 2 {
+            0 => Ok(int / 2),
+            _ => Err(RuntimeError::new("odd number")),
+        });
+
+        let program = Parser::get(r#"[join(repeat("ab", 3), "-"), checked_half(8)];"#).parse_program();
+        assert_eq!(
+            evaluator.eval_program(program),
+            Some(Object::Array(vec![
+                Object::String(String::from("ab-ab-ab")),
+                Object::Integer(4)
+            ]))
+        );
+
+        for (input, message) in [
+            (
+                r#"repeat("ab", true);"#,
+                "argument 2 of repeat: expected integer, got boolean",
+            ),
+            (
+                r#"repeat("ab");"#,
+                "wrong number of arguments for repeat: expected 2, got 1",
+            ),
+            ("checked_half(3);", "odd number"),
+        ] {
+            assert_eq!(
+                evaluator.eval_program(Parser::get(input).parse_program()),
+                Some(Object::Error(String::from(message)))
+            );
+        }
+    }
+}
@@ -1,12 +1,23 @@
 use std::cell::RefCell;
 use std::collections::HashMap;
+use std::fmt;
 use std::rc::Rc;
 
 use crate::object::Object;
 
-#[derive(Default, Debug, Clone, PartialEq)]
+// A scope of bindings. Closures hold on to the scope they were created in, so a binding
+// they refer to is shared rather than copied: `x = 1` inside a closure updates the same
+// binding its defining scope sees.
+//
+// The outermost scope (no `outer`) holds globals by name. They are late bound: a top level
+// `let` of an existing name replaces the value for everyone, which keeps the REPL usable.
+// Any nested scope (function call, `if`/`while` block) is a frame of slots the resolver
+// assigned. Redeclaring a name with `let` takes a new slot, so closures created before
+// keep seeing the old binding (see `Resolver`).
+#[derive(Default, Clone)]
 pub struct Env {
     store: HashMap<String, Object>,
+    slots: Vec<Option<Object>>,
     outer: Option<Rc<RefCell<Env>>>,
 }
 
@@ -14,6 +25,7 @@ impl Env {
     pub fn new() -> Self {
         Env {
             store: HashMap::new(),
+            slots: vec![],
             outer: None,
         }
     }
@@ -32,9 +44,64 @@ impl Env {
         self.store.insert(key, value);
     }
 
+    // update the nearest existing binding, returns false when the name is not bound anywhere
+    pub fn assign(&mut self, key: String, value: Object) -> bool {
+        if let Some(slot) = self.store.get_mut(&key) {
+            *slot = value;
+            return true;
+        }
+
+        match &self.outer {
+            Some(outer) => outer.borrow_mut().assign(key, value),
+            None => false,
+        }
+    }
+
+    // the slot of the scope `depth` levels up, `None` while its declaration did not run yet
+    pub fn get_slot(&self, depth: usize, slot: usize) -> Option<Object> {
+        if depth == 0 {
+            return self.slots.get(slot).cloned().flatten();
+        }
+
+        self.outer.as_ref()?.borrow().get_slot(depth - 1, slot)
+    }
+
+    pub fn set_slot(&mut self, slot: usize, value: Object) {
+        if slot >= self.slots.len() {
+            self.slots.resize(slot + 1, None);
+        }
+
+        self.slots[slot] = Some(value);
+    }
+
+    pub fn assign_slot(&mut self, depth: usize, slot: usize, value: Object) {
+        match &self.outer {
+            Some(outer) if depth > 0 => outer.borrow_mut().assign_slot(depth - 1, slot, value),
+            _ => self.set_slot(slot, value),
+        }
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = &String> {
+        self.store.keys()
+    }
+
+    pub fn outer(&self) -> Option<&Rc<RefCell<Env>>> {
+        self.outer.as_ref()
+    }
+
+    pub fn values(&self) -> impl Iterator<Item = &Object> {
+        self.store.values().chain(self.slots.iter().flatten())
+    }
+
+    // drop every binding and the outer scope, used to break reference cycles
+    pub fn clear(&mut self) -> Env {
+        std::mem::take(self)
+    }
+
     pub fn enclosed_outer_env(outer: Rc<RefCell<Env>>) -> Self {
         Env {
             store: HashMap::new(),
+            slots: vec![],
             outer: Some(outer),
         }
     }
@@ -42,7 +109,22 @@ impl Env {
     pub fn from(builtin: HashMap<String, Object>) -> Self {
         Env {
             store: builtin,
+            slots: vec![],
             outer: None,
         }
     }
 }
+
+// scopes usually contain closures pointing back at them, so only the names are printed
+impl fmt::Debug for Env {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut names = self.store.keys().collect::<Vec<_>>();
+        names.sort();
+
+        f.debug_struct("Env")
+            .field("store", &names)
+            .field("slots", &self.slots.len())
+            .field("outer", &self.outer.is_some())
+            .finish()
+    }
+}
@@ -1,36 +1,367 @@
 use std::cell::RefCell;
 use std::cell::RefMut;
 use std::collections::HashMap;
+use std::io::{self, BufRead, BufReader, Write};
 use std::ops::Deref;
 use std::rc::Rc;
-
+use std::time::{Duration, Instant};
+
+use crate::convert::IntoBuiltin;
+use crate::heap::{Heap, HeapStats};
+use crate::interrupt::{InterruptHandle, INTERRUPTED};
+use crate::object::{BuiltinFunction, RuntimeError};
+use crate::ordered_hash::OrderedHash;
+use crate::random::Rng;
+use crate::resolver::Resolver;
+use crate::trace::{Level, Stage, Trace, Tracer};
+use crate::vm::Machine;
 use crate::{ast::*, builtin::*, env::Env, object::Object, token::TokenType};
 
 pub struct Evaluator {
     env: Rc<RefCell<Env>>,
+    heap: Heap,
+    // names of the functions being evaluated, a `return` at the top level is not a tail call
+    call_stack: Vec<String>,
+    max_call_depth: usize,
+    // expressions being evaluated, in all frames together
+    expression_depth: usize,
+    max_expression_depth: usize,
+    // remaining budget of evaluated statements and expressions, `None` is unlimited
+    fuel: Option<u64>,
+    memory_limit: Option<usize>,
+    // bytes of live values, measured exactly from time to time and grown by every allocation in between
+    memory_used: usize,
+    memory_measured: usize,
+    // bytes of values that are only on the rust stack, like the items of an array being built,
+    // measuring does not see them so they are added back
+    memory_held: usize,
+    peak_memory: usize,
+    interrupt: InterruptHandle,
+    deadline: Option<Instant>,
+    // methods by type name and method name
+    methods: HashMap<(String, String), BuiltinFunction>,
+    rng: Rng,
+    // where `print`, `eprint` and `input` write and read, the process streams unless the host
+    // redirects them
+    stdout: Box<dyn Write>,
+    stderr: Box<dyn Write>,
+    stdin: Box<dyn BufRead>,
+    trace: Trace,
+    // the vm whose builtin is running, closures are called back on it
+    pub(crate) machine: Option<Machine>,
 }
 
+// A call takes about 16 KiB of native stack in a debug build and a nested expression about
+// 4 KiB, a release build needs a fraction of that. Together the default limits fit the 8 MiB
+// main thread in a debug build, a program on a smaller thread needs lower limits.
+pub const DEFAULT_MAX_CALL_DEPTH: usize = 256;
+
+pub const DEFAULT_MAX_EXPRESSION_DEPTH: usize = 1024;
+
+// the error of a program that used up its fuel
+pub const OUT_OF_FUEL: &str = "out of fuel";
+
+// the error of a program that ran past its deadline
+pub const TIMEOUT: &str = "timeout";
+
+// allocations after a measurement up to twice its size plus this many bytes go unmeasured
+const MEMORY_SLACK: usize = 64 * 1024;
+
 impl Evaluator {
     pub fn new(env: Rc<RefCell<Env>>) -> Self {
-        Evaluator { env }
+        let mut heap = Heap::new();
+        heap.track(&env);
+
+        Evaluator {
+            env,
+            heap,
+            call_stack: vec![],
+            max_call_depth: DEFAULT_MAX_CALL_DEPTH,
+            expression_depth: 0,
+            max_expression_depth: DEFAULT_MAX_EXPRESSION_DEPTH,
+            fuel: None,
+            memory_limit: None,
+            memory_used: 0,
+            memory_measured: 0,
+            memory_held: 0,
+            peak_memory: 0,
+            interrupt: InterruptHandle::new(),
+            deadline: None,
+            methods: HashMap::new(),
+            rng: Rng::from_time(),
+            stdout: Box::new(io::stdout()),
+            stderr: Box::new(io::stderr()),
+            stdin: Box::new(BufReader::new(io::stdin())),
+            trace: Trace::default(),
+            machine: None,
+        }
+    }
+
+    // calls nested deeper fail with an error instead of overflowing the native stack,
+    // tail calls reuse their frame and do not count
+    pub fn set_max_call_depth(&mut self, max_call_depth: usize) {
+        self.max_call_depth = max_call_depth;
+    }
+
+    pub fn max_call_depth(&self) -> usize {
+        self.max_call_depth
+    }
+
+    // expressions nested deeper, counted across the frames of all calls, fail with an error
+    // instead of overflowing the native stack
+    pub fn set_max_expression_depth(&mut self, max_expression_depth: usize) {
+        self.max_expression_depth = max_expression_depth;
+    }
+
+    pub fn max_expression_depth(&self) -> usize {
+        self.max_expression_depth
+    }
+
+    // the random builtins draw the same numbers after the same seed, without one they start
+    // from the clock
+    pub fn set_seed(&mut self, seed: u64) {
+        self.rng = Rng::new(seed);
+    }
+
+    pub(crate) fn rng(&mut self) -> &mut Rng {
+        &mut self.rng
+    }
+
+    pub fn set_stdout(&mut self, stdout: impl Write + 'static) {
+        self.stdout = Box::new(stdout);
+    }
+
+    pub fn set_stderr(&mut self, stderr: impl Write + 'static) {
+        self.stderr = Box::new(stderr);
+    }
+
+    pub fn set_stdin(&mut self, stdin: impl BufRead + 'static) {
+        self.stdin = Box::new(stdin);
+    }
+
+    // report programs, calls and errors to `tracer`, for debugging the evaluator
+    pub fn set_tracer(&mut self, tracer: Rc<dyn Tracer>) {
+        self.trace.set(tracer);
+    }
+
+    pub(crate) fn stdout(&mut self) -> &mut dyn Write {
+        &mut self.stdout
+    }
+
+    pub(crate) fn stderr(&mut self) -> &mut dyn Write {
+        &mut self.stderr
+    }
+
+    pub(crate) fn stdin(&mut self) -> &mut dyn BufRead {
+        &mut self.stdin
+    }
+
+    // every evaluated statement and expression costs one unit of fuel. A program that runs out
+    // fails with `OUT_OF_FUEL` and can not be continued, the bindings it made so far stay and
+    // `add_fuel` tops up for the next program. The vm pauses instead, see `Vm::resume`.
+    pub fn set_fuel(&mut self, fuel: Option<u64>) {
+        self.fuel = fuel;
+    }
+
+    pub fn fuel(&self) -> Option<u64> {
+        self.fuel
+    }
+
+    pub fn add_fuel(&mut self, fuel: u64) {
+        if let Some(remaining) = &mut self.fuel {
+            *remaining = remaining.saturating_add(fuel);
+        }
+    }
+
+    // strings, arrays and hashes are accounted when they are created, a program that needs
+    // more than `memory_limit` bytes for the values it holds fails with an error
+    pub fn set_memory_limit(&mut self, memory_limit: Option<usize>) {
+        self.memory_limit = memory_limit;
+    }
+
+    pub fn memory_limit(&self) -> Option<usize> {
+        self.memory_limit
+    }
+
+    // a builtin asks for about `bytes` bytes before it builds a value, so a value that can not
+    // fit the memory limit is an error instead of an allocation, the value itself is accounted
+    // once it is returned
+    pub fn reserve(&mut self, bytes: usize) -> Result<(), RuntimeError> {
+        let limit = match self.memory_limit {
+            Some(limit) => limit,
+            None => return Ok(()),
+        };
+
+        if self.memory_used.saturating_add(bytes) > limit {
+            self.heap.collect();
+            self.measure_memory();
+        }
+
+        match self.memory_used.saturating_add(bytes) > limit {
+            true => Err(RuntimeError::new(memory_limit_error(limit))),
+            false => Ok(()),
+        }
+    }
+
+    // a builtin collecting values, like the results of a callback, holds each of them against the
+    // memory limit until it gives the bytes back with `release`, an error when it does not fit
+    pub fn hold(&mut self, value: &Object) -> Result<usize, RuntimeError> {
+        let bytes = value.allocated_size();
+        self.reserve(bytes)?;
+
+        self.memory_held += bytes;
+        self.memory_used += bytes;
+        self.peak_memory = self.peak_memory.max(self.memory_used);
+
+        Ok(bytes)
+    }
+
+    pub fn release(&mut self, bytes: usize) {
+        self.memory_held = self.memory_held.saturating_sub(bytes);
+        self.memory_used = self.memory_used.saturating_sub(bytes);
+    }
+
+    // a handle other threads can use to stop the running program
+    pub fn interrupt_handle(&self) -> InterruptHandle {
+        self.interrupt.clone()
+    }
+
+    // most bytes in use during the last `eval_program`, an estimate that errs on the high side
+    pub fn peak_memory(&self) -> usize {
+        self.peak_memory
     }
 
     pub fn builtin(&mut self) {
         let builtins = make_builtin();
-        self.env = Rc::new(RefCell::new(Env::from(builtins)));
+        self.env = self.heap.alloc(Env::from(builtins));
+
+        let env = &self.env;
+        self.trace
+            .emit(Level::Debug, Stage::Eval, || format!("builtins {:?}", env));
+    }
+
+    // make a host function callable by programs under `name`, either a closure over the raw
+    // arguments or a rust function of plain types (see `IntoBuiltin`)
+    pub fn register_fn<Args, F: IntoBuiltin<Args>>(&mut self, name: &str, func: F) {
+        self.register_builtin(func.into_builtin(name));
+    }
+
+    pub fn register_builtin(&mut self, builtin: BuiltinFunction) {
+        self.env
+            .borrow_mut()
+            .set(builtin.name.clone(), Object::Builtin(builtin));
+    }
+
+    // make `value.method(..)` callable on values of `type_name`, a `NativeObject` or any other
+    // type like "string", the receiver is passed as first argument
+    pub fn register_method<Args, F: IntoBuiltin<Args>>(&mut self, type_name: &str, method: &str, func: F) {
+        let builtin = func.into_builtin(&format!("{}.{}", type_name, method));
+
+        self.methods
+            .insert((type_name.to_string(), method.to_string()), builtin);
+    }
+
+    pub(crate) fn call_method(&mut self, receiver: Object, method: &str, arguments: &[Object]) -> Object {
+        // a hash holding a function under the name is a namespace like `math`
+        if let Object::Hash(pairs) = &receiver {
+            if let Some(function) = pairs.get(&Object::String(method.to_string())) {
+                if function.type_name() == "function" {
+                    return match self.call_function(function, arguments.to_vec()) {
+                        Ok(value) => value,
+                        Err(error) => Object::Error(error.message),
+                    };
+                }
+            }
+        }
+
+        let key = (receiver.type_name().to_string(), method.to_string());
+
+        let builtin = match self.methods.get(&key) {
+            Some(builtin) => builtin.clone(),
+            None => return Object::Error(format!("no method {} on {}", method, receiver.type_name())),
+        };
+
+        let mut arguments = arguments.to_vec();
+        arguments.insert(0, receiver);
+
+        builtin.call(self, &arguments)
+    }
 
-        println!("22 {:?}", self.env);
+    // call a lynx function or builtin from the host, builtins use it to call back into the
+    // program, a closure of the vm only while the vm runs the builtin, an error of the call
+    // comes back as `Err`
+    pub fn call_function(&mut self, function: &Object, arguments: Vec<Object>) -> Result<Object, RuntimeError> {
+        let value = match function {
+            Object::Function(name, ..) => {
+                let name = match name.is_empty() {
+                    true => String::from("<anonymous>"),
+                    false => name.clone(),
+                };
+
+                self.apply_function(name, function.clone(), arguments)
+            }
+            Object::Builtin(builtin) => self.apply_function(builtin.name.clone(), function.clone(), arguments),
+            // the vm runs those on its own frames
+            Object::Closure(closure) => {
+                let mut machine = self
+                    .machine
+                    .take()
+                    .ok_or_else(|| RuntimeError::new("compiled functions can only be called back while the vm runs"))?;
+                let value = machine.call(self, Rc::clone(closure), arguments);
+                self.machine = Some(machine);
+
+                return value.map_err(RuntimeError::new);
+            }
+            other => return Err(RuntimeError::new(format!("not a function: {}", other.type_name()))),
+        };
+
+        match value {
+            Some(Object::Error(message)) => Err(RuntimeError::new(message)),
+            Some(value) => Ok(value),
+            None => Ok(Object::Null),
+        }
     }
 
     pub fn eval_program(&mut self, program: AstNode) -> Option<Object> {
-        println!("{:?}", program);
+        self.trace
+            .emit(Level::Info, Stage::Eval, || String::from("program starts"));
+        self.trace.emit(Level::Debug, Stage::Eval, || format!("{:?}", program));
+
+        self.memory_held = 0;
+        self.measure_memory();
+        self.peak_memory = self.memory_used;
+
+        let globals = self.env.borrow().names().cloned().collect::<Vec<_>>();
+
+        let program = match Resolver::new(globals).resolve(program) {
+            Ok(program) => program,
+            Err(message) => {
+                self.trace
+                    .emit(Level::Info, Stage::Eval, || format!("program failed: {}", message));
+
+                return Some(Object::Error(message));
+            }
+        };
 
         match program {
             AstNode::Program(statements) => {
                 let mut value = Some(Object::Null);
 
                 for statement in statements {
-                    value = self.eval_statement(statement);
+                    value = self.check_interrupt().or_else(|| self.eval_statement(statement));
+
+                    match value {
+                        Some(Object::ReturnValue(returned)) => {
+                            return Some(returned.deref().clone());
+                        }
+                        Some(Object::Error(ref message)) => {
+                            self.trace
+                                .emit(Level::Info, Stage::Eval, || format!("program failed: {}", message));
+
+                            return value;
+                        }
+                        _ => {}
+                    }
                 }
 
                 value
@@ -38,34 +369,150 @@ impl Evaluator {
         }
     }
 
+    // like `eval_program`, but fails with `TIMEOUT` once `timeout` has passed
+    pub fn eval_program_with_deadline(&mut self, program: AstNode, timeout: Duration) -> Option<Object> {
+        self.deadline = Some(Instant::now() + timeout);
+
+        let value = self.eval_program(program);
+
+        self.deadline = None;
+
+        value
+    }
+
     pub fn get_env(&self) -> RefMut<Env> {
         self.env.borrow_mut()
     }
 
+    // free closures and scopes that only keep each other alive, returns how many scopes were freed
+    pub fn collect_garbage(&mut self) -> usize {
+        self.heap.collect()
+    }
+
+    pub fn heap_stats(&mut self) -> HeapStats {
+        self.heap.stats()
+    }
+
+    // called at loop iterations and calls, everything still in use is referenced from
+    // the rust stack at that point and counts as a root
+    fn maybe_collect(&mut self) {
+        if self.heap.should_collect() {
+            self.heap.collect();
+        }
+    }
+
+    fn consume_fuel(&mut self) -> bool {
+        match &mut self.fuel {
+            Some(0) => false,
+            Some(remaining) => {
+                *remaining -= 1;
+                true
+            }
+            None => true,
+        }
+    }
+
+    // interruption and deadline, checked at loop iterations and calls
+    fn check_interrupt(&mut self) -> Option<Object> {
+        if self.interrupt.take() {
+            return Some(Object::Error(String::from(INTERRUPTED)));
+        }
+
+        if matches!(self.deadline, Some(deadline) if Instant::now() >= deadline) {
+            return Some(Object::Error(String::from(TIMEOUT)));
+        }
+
+        None
+    }
+
+    fn measure_memory(&mut self) {
+        self.memory_measured = self.heap.live_bytes();
+        self.memory_used = self.memory_measured + self.memory_held;
+    }
+
+    // account a newly created value, an error when it does not fit the memory limit
+    fn track_allocation(&mut self, value: Object) -> Object {
+        if !matches!(value, Object::String(_) | Object::Array(_) | Object::Hash(_)) {
+            return value;
+        }
+
+        let bytes = value.allocated_size();
+        let over_limit = |used: usize, limit: Option<usize>| matches!(limit, Some(limit) if used > limit);
+
+        self.memory_used += bytes;
+
+        if self.memory_used > self.memory_measured * 2 + MEMORY_SLACK || over_limit(self.memory_used, self.memory_limit)
+        {
+            // garbage cycles should not count against the limit
+            if over_limit(self.memory_used, self.memory_limit) {
+                self.heap.collect();
+            }
+
+            self.measure_memory();
+            self.memory_used += bytes;
+        }
+
+        self.peak_memory = self.peak_memory.max(self.memory_used);
+
+        match self.memory_limit {
+            Some(limit) if self.memory_used > limit => Object::Error(memory_limit_error(limit)),
+            _ => value,
+        }
+    }
+
     fn eval_statement(&mut self, statement: Statement) -> Option<Object> {
+        if !self.consume_fuel() {
+            return Some(Object::Error(String::from(OUT_OF_FUEL)));
+        }
+
         match statement {
-            Statement::Let(identifier, expr) => self.eval_let_statement(identifier, expr),
-            Statement::Return(expr) => self.eval_return_statement(expr),
-            Statement::Expr(expr) => self.eval_expression(expr),
+            Statement::Let(identifier, expr) => self.eval_let_statement(*identifier, *expr),
+            Statement::Return(expr) => self.eval_return_statement(*expr),
+            Statement::Expr(expr) => self.eval_expression(*expr),
             Statement::BlockStatement(statements) => self.eval_block_statements(statements),
         }
     }
 
-    fn eval_let_statement(&mut self, identifier: Box<Expression>, expr: Box<Expression>) -> Option<Object> {
-        match *identifier {
-            Expression::Identifier(ident) => match self.eval_expression(expr) {
-                Some(value) => {
-                    self.env.deref().borrow_mut().set(ident, value);
-                    None
-                }
-                None => None,
-            },
-            _ => None,
+    fn eval_let_statement(&mut self, identifier: Expression, expr: Expression) -> Option<Object> {
+        let literal = matches!(&expr, Expression::Fn(..));
+
+        match self.eval_expression(expr) {
+            Some(Object::Error(message)) => Some(Object::Error(message)),
+            // a function literal is named after the `let` that binds it, like in the vm
+            Some(Object::Function(name, parameters, body, env)) if literal && name.is_empty() => {
+                let name = callee_name(&identifier);
+                self.declare(identifier, Object::Function(name, parameters, body, env));
+                None
+            }
+            value => {
+                self.declare(identifier, value.unwrap_or(Object::Null));
+                None
+            }
         }
     }
 
-    fn eval_return_statement(&mut self, expr: Box<Expression>) -> Option<Object> {
-        self.eval_expression(expr)
+    // bind the target of a `let` or `fn` declaration, a global name or a slot of the current scope
+    fn declare(&mut self, identifier: Expression, value: Object) {
+        match identifier {
+            Expression::Identifier(name) => self.env.borrow_mut().set(name, value),
+            Expression::Local(_, _, slot) => self.env.borrow_mut().set_slot(slot, value),
+            _ => {}
+        }
+    }
+
+    fn eval_return_statement(&mut self, expr: Expression) -> Option<Object> {
+        let value = match expr {
+            Expression::FnCall(fn_name, fn_parameter) if !self.call_stack.is_empty() => {
+                self.eval_tail_call(*fn_name, fn_parameter)
+            }
+            expr => self.eval_expression(expr),
+        };
+
+        match value {
+            Some(Object::Error(message)) => Some(Object::Error(message)),
+            Some(value) => Some(Object::ReturnValue(Rc::new(value))),
+            None => Some(Object::ReturnValue(Rc::new(Object::Null))),
+        }
     }
 
     fn eval_block_statement(&mut self, block_stmt: Statement) -> Option<Object> {
@@ -80,48 +527,119 @@ impl Evaluator {
 
         for stmt in block_stmts {
             value = self.eval_statement(stmt);
+
+            // return, break and errors stop the block and bubble up to whoever handles them
+            if let Some(Object::ReturnValue(_) | Object::Break | Object::Error(_)) = value {
+                break;
+            }
+        }
+
+        value
+    }
+
+    // the body of a function, a call or `if` as last statement is in tail position
+    fn eval_tail_block_statement(&mut self, block_stmt: Statement) -> Option<Object> {
+        let mut block_stmts = match block_stmt {
+            Statement::BlockStatement(stmts) => stmts,
+            _ => return Some(Object::Null),
+        };
+
+        let last_stmt = match block_stmts.pop() {
+            Some(stmt) => stmt,
+            None => return Some(Object::Null),
+        };
+
+        let value = self.eval_block_statements(block_stmts);
+
+        if let Some(Object::ReturnValue(_) | Object::Break | Object::Error(_)) = value {
+            return value;
+        }
+
+        match last_stmt {
+            Statement::Expr(expr) => match *expr {
+                Expression::FnCall(fn_name, fn_parameter) => self.eval_tail_call(*fn_name, fn_parameter),
+                Expression::If(condition, statement, else_statement) => {
+                    self.eval_if_expression(*condition, statement, else_statement, true)
+                }
+                expr => self.eval_expression(expr),
+            },
+            stmt => self.eval_statement(stmt),
         }
+    }
+
+    // evaluate `if` and `while` bodies in their own scope
+    fn eval_scoped_block_statement(&mut self, block_stmt: Statement, tail: bool) -> Option<Object> {
+        let original_env = Rc::clone(&self.env);
+
+        self.env = self.heap.alloc(Env::enclosed_outer_env(Rc::clone(&original_env)));
+
+        let value = if tail {
+            self.eval_tail_block_statement(block_stmt)
+        } else {
+            self.eval_block_statement(block_stmt)
+        };
+
+        self.env = original_env;
 
         value
     }
 
-    fn eval_expression(&mut self, expr: Box<Expression>) -> Option<Object> {
-        match *expr {
+    fn eval_expression(&mut self, expr: Expression) -> Option<Object> {
+        if !self.consume_fuel() {
+            return Some(Object::Error(String::from(OUT_OF_FUEL)));
+        }
+
+        if self.expression_depth >= self.max_expression_depth {
+            return Some(Object::Error(format!(
+                "maximum expression depth of {} exceeded",
+                self.max_expression_depth
+            )));
+        }
+
+        self.expression_depth += 1;
+
+        let value = match expr {
             Expression::String(string) => self.eval_string(string),
             Expression::Integer(int) => self.eval_integer(int),
+            Expression::Float(float) => Some(Object::Float(float)),
             Expression::Boolean(bl) => self.eval_boolean(bl),
             Expression::Identifier(identifer) => self.eval_identifier(identifer),
+            Expression::Local(name, depth, slot) => self.eval_local(name, depth, slot),
             Expression::Array(exprs) => self.eval_array_expression(exprs),
             Expression::Hash(hashes) => self.eval_hash_expression(hashes),
-            Expression::Prefix(operator, expr) => self.eval_prefix_expression(operator, expr),
+            Expression::Prefix(operator, expr) => self.eval_prefix_expression(operator, *expr),
             Expression::Infix(left, operator, right) => self.eval_infix_expression(left, operator, right),
             Expression::If(condition, statement, else_statement) => {
-                self.eval_if_expression(condition, statement, else_statement)
+                self.eval_if_expression(*condition, statement, else_statement, false)
             }
             Expression::While(condition, block_statement) => self.eval_while_expression(condition, block_statement),
             Expression::Break => Some(Object::Break),
             Expression::Fn(fn_name, fn_parameter, fn_body) => self.eval_fn_expression(*fn_name, fn_parameter, fn_body),
-            Expression::FnCall(fn_name, fn_parameter) => self.eval_fn_call_expression(fn_name, fn_parameter),
+            Expression::FnCall(fn_name, fn_parameter) => self.eval_fn_call_expression(*fn_name, fn_parameter),
+            Expression::MethodCall(receiver, method, arguments) => {
+                self.eval_method_call_expression(*receiver, method, arguments)
+            }
+            Expression::Property(receiver, key) => {
+                let receiver = self.eval_expression(*receiver).unwrap_or(Object::Null);
+
+                Some(get_property(&receiver, &Object::String(key)))
+            }
+            Expression::Assign(target, value) => self.eval_assign_expression(*target, *value),
             _ => Some(Object::Null),
-        }
+        };
+
+        self.expression_depth -= 1;
+
+        value
     }
 
-    fn eval_prefix_expression(&mut self, operator: TokenType, expr: Box<Expression>) -> Option<Object> {
-        match operator {
-            TokenType::BANG => {
-                let value = self.eval_expression(expr).unwrap();
+    fn eval_prefix_expression(&mut self, operator: TokenType, expr: Expression) -> Option<Object> {
+        let value = self.eval_expression(expr).unwrap_or(Object::Null);
 
-                if value.is_truthy() {
-                    Some(Object::Boolean(false))
-                } else {
-                    Some(Object::Boolean(true))
-                }
-            }
-            _ => Some(Object::Null),
-        }
+        Some(eval_prefix_object(&operator, value))
     }
 
-    fn get_integer_val(&mut self, object: Object) -> usize {
+    fn get_integer_val(&mut self, object: Object) -> i64 {
         match object {
             Object::Integer(i) => i,
             _ => 0,
@@ -129,8 +647,8 @@ impl Evaluator {
     }
 
     fn get_infix_objects(&mut self, left: &InfixExpression, right: &InfixExpression) -> (Object, Object) {
-        let left = self.eval_expression(left.clone()).unwrap();
-        let right = self.eval_expression(right.clone()).unwrap();
+        let left = self.eval_expression(left.deref().clone()).unwrap_or(Object::Null);
+        let right = self.eval_expression(right.deref().clone()).unwrap_or(Object::Null);
 
         (left, right)
     }
@@ -141,39 +659,9 @@ impl Evaluator {
         operator: TokenType,
         right: InfixExpression,
     ) -> Option<Object> {
-        let value = Some(Object::Null);
-
-        match operator {
-            TokenType::ADD => {
-                let (left_obj, right_obj) = self.get_infix_objects(&left, &right);
-                let left_val = self.get_integer_val(left_obj);
-                let right_val = self.get_integer_val(right_obj);
-
-                Some(Object::Integer(left_val + right_val))
-            }
-            TokenType::MINUS => {
-                let (left_obj, right_obj) = self.get_infix_objects(&left, &right);
-                let left_val = self.get_integer_val(left_obj);
-                let right_val = self.get_integer_val(right_obj);
-
-                Some(Object::Integer(left_val - right_val))
-            }
-            TokenType::MULTIPLY => {
-                let (left_obj, right_obj) = self.get_infix_objects(&left, &right);
-                let left_val = self.get_integer_val(left_obj);
-                let right_val = self.get_integer_val(right_obj);
-
-                Some(Object::Integer(left_val * right_val))
-            }
-            TokenType::DIVIDE => {
-                let (left_obj, right_obj) = self.get_infix_objects(&left, &right);
-                let left_val = self.get_integer_val(left_obj);
-                let right_val = self.get_integer_val(right_obj);
+        let (left_obj, right_obj) = self.get_infix_objects(&left, &right);
 
-                Some(Object::Integer(left_val / right_val))
-            }
-            _ => value,
-        }
+        Some(self.track_allocation(eval_infix_objects(&operator, left_obj, right_obj)))
     }
 
     fn eval_identifier(&mut self, identifier: String) -> Option<Object> {
@@ -183,7 +671,39 @@ impl Evaluator {
         }
     }
 
-    fn eval_integer(&mut self, int: usize) -> Option<Object> {
+    fn eval_local(&mut self, name: String, depth: usize, slot: usize) -> Option<Object> {
+        match self.env.borrow().get_slot(depth, slot) {
+            Some(value) => Some(value),
+            None => Some(Object::Error(format!("no identifier found: {}", name))),
+        }
+    }
+
+    fn eval_assign_expression(&mut self, target: Expression, expr: Expression) -> Option<Object> {
+        let value = match self.eval_expression(expr) {
+            Some(Object::Error(message)) => {
+                return Some(Object::Error(message));
+            }
+            Some(value) => value,
+            None => Object::Null,
+        };
+
+        match target {
+            Expression::Local(_, depth, slot) => {
+                self.env.borrow_mut().assign_slot(depth, slot, value.clone());
+                Some(value)
+            }
+            Expression::Identifier(name) => {
+                if self.env.borrow_mut().assign(name.clone(), value.clone()) {
+                    Some(value)
+                } else {
+                    Some(Object::Error(format!("assignment to undefined variable: {}", name)))
+                }
+            }
+            _ => Some(Object::Error(String::from("invalid assignment target"))),
+        }
+    }
+
+    fn eval_integer(&mut self, int: i64) -> Option<Object> {
         Some(Object::Integer(int))
     }
 
@@ -196,67 +716,122 @@ impl Evaluator {
     }
 
     fn eval_array_expression(&mut self, exprs: Vec<Expression>) -> Option<Object> {
-        Some(Object::Array(
-            exprs
-                .into_iter()
-                .map(|expr| self.eval_expression(Box::new(expr)).unwrap_or(Object::Null))
-                .collect::<Vec<_>>(),
-        ))
+        match self.eval_operands(exprs) {
+            Ok(elements) => Some(self.track_allocation(Object::Array(elements))),
+            Err(message) => Some(Object::Error(message)),
+        }
     }
 
     fn eval_hash_expression(&mut self, hashes: Vec<(Expression, Expression)>) -> Option<Object> {
-        let mut hash_object = HashMap::<Object, Object>::new();
-
-        hashes.into_iter().for_each(|(k, v)| {
-            hash_object.insert(
-                self.eval_expression(Box::new(k)).unwrap_or(Object::Null),
-                self.eval_expression(Box::new(v)).unwrap_or(Object::Null),
-            );
+        let mut hash_object = OrderedHash::new();
+        let mut held = 0;
+
+        let outcome = hashes.into_iter().try_for_each(|(k, v)| {
+            let key = self.eval_expression(k).unwrap_or(Object::Null);
+            let value = self.eval_expression(v).unwrap_or(Object::Null);
+
+            match (key, value) {
+                (Object::Error(message), _) | (_, Object::Error(message)) => Err(message),
+                (key, _) if !key.is_hashable() => Err(key.unhashable_error()),
+                (key, value) => {
+                    held += self.hold(&key).map_err(|error| error.message)?;
+                    held += self.hold(&value).map_err(|error| error.message)?;
+                    hash_object.insert(key, value);
+
+                    Ok(())
+                }
+            }
         });
 
-        Some(Object::Hash(hash_object))
+        self.release(held);
+
+        match outcome {
+            Ok(()) => Some(self.track_allocation(Object::Hash(hash_object))),
+            Err(message) => Some(Object::Error(message)),
+        }
+    }
+
+    // the values of `exprs` in order, each is held against the memory limit until all are there
+    fn eval_operands(&mut self, exprs: Vec<Expression>) -> Result<Vec<Object>, String> {
+        let mut values = vec![];
+        let mut held = 0;
+
+        let outcome = exprs
+            .into_iter()
+            .try_for_each(|expr| match self.eval_expression(expr).unwrap_or(Object::Null) {
+                Object::Error(message) => Err(message),
+                value => {
+                    held += self.hold(&value).map_err(|error| error.message)?;
+                    values.push(value);
+
+                    Ok(())
+                }
+            });
+
+        self.release(held);
+
+        outcome.map(|_| values)
     }
 
     fn eval_if_expression(
         &mut self,
-        if_condition: IfCondition,
+        if_condition: Expression,
         statements: Statement,
         else_statements: Option<Statement>,
+        tail: bool,
     ) -> Option<Object> {
         let condition = self.eval_expression(if_condition);
 
         if let Some(condition_val) = condition {
-            if condition_val.is_truthy() {
-                self.eval_block_statement(statements)
+            if let Object::Error(_) = condition_val {
+                Some(condition_val)
+            } else if condition_val.is_truthy() {
+                Some(
+                    self.eval_scoped_block_statement(statements, tail)
+                        .unwrap_or(Object::Null),
+                )
             } else if let Some(else_stmts) = else_statements {
-                self.eval_block_statement(else_stmts)
+                Some(
+                    self.eval_scoped_block_statement(else_stmts, tail)
+                        .unwrap_or(Object::Null),
+                )
             } else {
-                None
+                Some(Object::Null)
             }
         } else {
-            None
+            Some(Object::Null)
         }
     }
 
     fn eval_while_expression(&mut self, while_condition: WhileCondition, block_stmt: Statement) -> Option<Object> {
-        let condition = self.eval_expression(while_condition).unwrap();
+        loop {
+            self.maybe_collect();
 
-        while condition.is_truthy() {
-            match block_stmt {
-                Statement::BlockStatement(ref stmts) => {
-                    for stmt in stmts {
-                        let mut _value = Some(Object::Null);
+            if let Some(error) = self.check_interrupt() {
+                return Some(error);
+            }
 
-                        _value = self.eval_statement(stmt.clone());
+            let condition = self
+                .eval_expression(while_condition.deref().clone())
+                .unwrap_or(Object::Null);
 
-                        if _value.eq(&Some(Object::Break)) {
-                            break;
-                        }
-                    }
-                }
-                _ => {
+            if let Object::Error(_) = condition {
+                return Some(condition);
+            }
+
+            if !condition.is_truthy() {
+                break;
+            }
+
+            // every iteration gets a fresh scope, closures created in the body keep their own bindings
+            match self.eval_scoped_block_statement(block_stmt.clone(), false) {
+                Some(Object::Break) => {
                     break;
                 }
+                Some(value @ (Object::ReturnValue(_) | Object::Error(_))) => {
+                    return Some(value);
+                }
+                _ => {}
             }
         }
 
@@ -269,20 +844,20 @@ impl Evaluator {
         fn_parameter: FnParameter,
         fn_body: FnBody,
     ) -> Option<Object> {
-        let fn_name = match fn_name_expr {
-            Expression::Identifier(str) => str,
-            _ => "".to_string(),
-        };
-
-        let fn_object = Object::Function(fn_parameter, fn_body, Rc::clone(&self.env));
-
-        if fn_name.is_empty() {
+        match fn_name_expr {
             // let fn expression
-            Some(fn_object)
-        } else {
+            Expression::Identifier(name) if name.is_empty() => {
+                Some(Object::Function(name, fn_parameter, fn_body, Rc::clone(&self.env)))
+            }
             // fn declaration
-            self.env.borrow_mut().set(fn_name, fn_object);
-            None
+            identifier => {
+                let name = callee_name(&identifier);
+                self.declare(
+                    identifier,
+                    Object::Function(name, fn_parameter, fn_body, Rc::clone(&self.env)),
+                );
+                None
+            }
         }
     }
 
@@ -293,51 +868,285 @@ impl Evaluator {
     ) -> Rc<RefCell<Env>> {
         let mut enclosed_env = Env::enclosed_outer_env(Rc::clone(&outer_env));
 
+        // the resolver put the parameters into the first slots
         for (key, value) in arg_pair_vec {
-            let name = match key {
-                Expression::Identifier(str) => str,
-                _ => "",
-            };
-
-            if !name.is_empty() {
-                enclosed_env.set(name.to_string(), value.clone());
+            if let Expression::Local(_, _, slot) = key {
+                enclosed_env.set_slot(*slot, value.clone());
             }
         }
 
-        Rc::new(RefCell::new(enclosed_env))
+        self.heap.alloc(enclosed_env)
     }
 
-    fn eval_fn_call_expression(&mut self, fn_name: FnName, fn_parameter: FnParameter) -> Option<Object> {
-        let arguments = fn_parameter
-            .iter()
-            .map(|expr| self.eval_expression(Box::new(expr.clone())).unwrap_or(Object::Null))
-            .collect::<Vec<Object>>();
+    fn eval_fn_call_expression(&mut self, fn_name: Expression, fn_parameter: FnParameter) -> Option<Object> {
+        self.maybe_collect();
 
-        let (parameters, stmt, outer_env) = match self.eval_expression(fn_name).unwrap() {
-            Object::Function(args, stmt, outer_env) => (args, stmt, outer_env),
-            Object::Builtin(func) => {
-                // invoke builtin methods
-                return Some(func(arguments));
-            }
-            _ => {
-                return None;
-            }
+        let name = callee_name(&fn_name);
+
+        match self.eval_fn_call_operands(fn_name, fn_parameter) {
+            Ok((function, arguments)) => self.apply_function(name, function, arguments),
+            Err(message) => Some(Object::Error(message)),
+        }
+    }
+
+    fn eval_method_call_expression(
+        &mut self,
+        receiver: Expression,
+        method: String,
+        arguments: FnParameter,
+    ) -> Option<Object> {
+        match self.eval_fn_call_operands(receiver, arguments) {
+            Ok((receiver, arguments)) => {
+                let value = self.call_method(receiver, &method, &arguments);
+
+                Some(self.track_allocation(value))
+            }
+            Err(message) => Some(Object::Error(message)),
+        }
+    }
+
+    // a call in tail position is left to the caller's `apply_function`, so it does not grow the rust
+    // stack
+    fn eval_tail_call(&mut self, fn_name: Expression, fn_parameter: FnParameter) -> Option<Object> {
+        let name = callee_name(&fn_name);
+
+        match self.eval_fn_call_operands(fn_name, fn_parameter) {
+            Ok((function @ Object::Function(..), arguments)) => {
+                Some(Object::TailCall(name, Box::new(function), arguments))
+            }
+            Ok((function, arguments)) => self.apply_function(name, function, arguments),
+            Err(message) => Some(Object::Error(message)),
+        }
+    }
+
+    fn eval_fn_call_operands(
+        &mut self,
+        fn_name: Expression,
+        fn_parameter: FnParameter,
+    ) -> Result<(Object, Vec<Object>), String> {
+        let function = match self.eval_expression(fn_name) {
+            Some(Object::Error(message)) => {
+                return Err(message);
+            }
+            Some(function) => function,
+            None => Object::Null,
         };
 
-        let original_env = Rc::clone(&self.env);
+        Ok((function, self.eval_operands(fn_parameter)?))
+    }
 
-        // https://stackoverflow.com/questions/156767/whats-the-difference-between-an-argument-and-a-parameter
-        let para_arg_pair = parameters.iter().zip(arguments.iter());
+    fn apply_function(&mut self, mut name: String, mut function: Object, mut arguments: Vec<Object>) -> Option<Object> {
+        // trampoline, tail calls of the body come back here instead of nesting
+        loop {
+            if let Some(error) = self.check_interrupt() {
+                return Some(error);
+            }
 
-        // create temporary env for eval function statements
-        self.env = self.enclose_fn_env(para_arg_pair.collect::<Vec<(&Expression, &Object)>>(), outer_env);
+            self.trace.emit(Level::Trace, Stage::Eval, || {
+                format!("call {} with {} arguments", name, arguments.len())
+            });
 
-        let fn_call_value = self.eval_block_statement(stmt);
+            let (parameters, stmt, outer_env) = match function {
+                Object::Function(_, args, stmt, outer_env) => (args, stmt, outer_env),
+                Object::Builtin(builtin) => {
+                    // invoke builtin methods
+                    let value = builtin.call(self, &arguments);
 
-        // restore original env
-        self.env = original_env;
+                    return Some(self.track_allocation(value));
+                }
+                other => {
+                    return Some(Object::Error(format!("not a function: {:?}", other)));
+                }
+            };
+
+            if self.call_stack.len() >= self.max_call_depth {
+                self.call_stack.push(name);
+                let message = call_depth_error(self.max_call_depth, &self.call_stack);
+                self.call_stack.pop();
+
+                return Some(Object::Error(message));
+            }
+
+            let original_env = Rc::clone(&self.env);
+
+            // parameters without an argument are bound to null
+            if arguments.len() < parameters.len() {
+                arguments.resize(parameters.len(), Object::Null);
+            }
+
+            // https://stackoverflow.com/questions/156767/whats-the-difference-between-an-argument-and-a-parameter
+            let para_arg_pair = parameters.iter().zip(arguments.iter());
+
+            // create temporary env for eval function statements
+            self.env = self.enclose_fn_env(para_arg_pair.collect::<Vec<(&Expression, &Object)>>(), outer_env);
+
+            self.call_stack.push(name);
+            let fn_call_value = self.eval_tail_block_statement(stmt);
+            self.call_stack.pop();
+
+            // restore original env
+            self.env = original_env;
+
+            let value = match fn_call_value {
+                Some(Object::ReturnValue(value)) => Rc::try_unwrap(value).unwrap_or_else(|value| value.deref().clone()),
+                // a stray `break` does not leave the function
+                Some(Object::Break) => Object::Null,
+                Some(value) => value,
+                None => Object::Null,
+            };
+
+            match value {
+                Object::TailCall(next_name, next_function, next_arguments) => {
+                    self.maybe_collect();
+
+                    name = next_name;
+                    function = *next_function;
+                    arguments = next_arguments;
+                }
+                value => return Some(value),
+            }
+        }
+    }
+}
+
+fn memory_limit_error(limit: usize) -> String {
+    format!("memory limit of {} bytes exceeded", limit)
+}
+
+fn callee_name(fn_name: &Expression) -> String {
+    match fn_name {
+        Expression::Identifier(name) | Expression::Local(name, _, _) => name.clone(),
+        _ => String::from("<anonymous>"),
+    }
+}
+
+// shared with the vm, `call_stack` ends with the call that did not fit
+pub(crate) fn call_depth_error(max_call_depth: usize, call_stack: &[String]) -> String {
+    format!(
+        "maximum call depth of {} exceeded, call stack: {}",
+        max_call_depth,
+        format_call_stack(call_stack)
+    )
+}
+
+// outermost call first, runs of the same function are collapsed
+fn format_call_stack(call_stack: &[String]) -> String {
+    let mut frames: Vec<(&String, usize)> = vec![];
+
+    for name in call_stack {
+        match frames.last_mut() {
+            Some((last, count)) if *last == name => *count += 1,
+            _ => frames.push((name, 1)),
+        }
+    }
+
+    frames
+        .iter()
+        .map(|(name, count)| match count {
+            1 => name.to_string(),
+            count => format!("{} (x{})", name, count),
+        })
+        .collect::<Vec<_>>()
+        .join(" -> ")
+}
+
+// shared with the vm so both agree on operator semantics and error messages
+pub(crate) fn eval_infix_objects(operator: &TokenType, left_obj: Object, right_obj: Object) -> Object {
+    match (left_obj, right_obj) {
+        (Object::Error(message), _) | (_, Object::Error(message)) => Object::Error(message),
+        (Object::Integer(left_val), Object::Integer(right_val)) => {
+            eval_integer_infix_objects(operator, left_val, right_val)
+        }
+        // an integer next to a float counts as float
+        (Object::Float(left_val), Object::Float(right_val)) => eval_float_infix_objects(operator, left_val, right_val),
+        (Object::Integer(left_val), Object::Float(right_val)) => {
+            eval_float_infix_objects(operator, left_val as f64, right_val)
+        }
+        (Object::Float(left_val), Object::Integer(right_val)) => {
+            eval_float_infix_objects(operator, left_val, right_val as f64)
+        }
+        (Object::String(left_val), Object::String(right_val)) if *operator == TokenType::ADD => {
+            Object::String(left_val + &right_val)
+        }
+        (left_obj, right_obj) => match operator {
+            TokenType::EQUAL_EQUAL => Object::Boolean(left_obj == right_obj),
+            TokenType::BANG_EQUAL => Object::Boolean(left_obj != right_obj),
+            _ => Object::Error(format!(
+                "unsupported operand types for {:?}: {:?} and {:?}",
+                operator, left_obj, right_obj
+            )),
+        },
+    }
+}
+
+// `!value` and `-value`, shared with the vm and the optimizer like `eval_infix_objects`
+pub(crate) fn eval_prefix_object(operator: &TokenType, value: Object) -> Object {
+    match (operator, value) {
+        (_, Object::Error(message)) => Object::Error(message),
+        (TokenType::BANG, value) => Object::Boolean(!value.is_truthy()),
+        (TokenType::MINUS, Object::Integer(int)) => match int.checked_neg() {
+            Some(negated) => Object::Integer(negated),
+            None => Object::Error(format!("integer overflow: -{}", int)),
+        },
+        (TokenType::MINUS, Object::Float(float)) => Object::Float(-float),
+        (operator, value) => Object::Error(format!("unsupported operand type for {:?}: {:?}", operator, value)),
+    }
+}
+
+// `receiver.key`, only hashes have properties, the key is a string constant
+pub(crate) fn get_property(receiver: &Object, key: &Object) -> Object {
+    match receiver {
+        Object::Error(_) => receiver.clone(),
+        Object::Hash(pairs) => match pairs.get(key) {
+            Some(value) => value.clone(),
+            None => Object::Error(format!("no property {} on hash", key)),
+        },
+        other => Object::Error(format!("no property {} on {}", key, other.type_name())),
+    }
+}
+
+fn eval_integer_infix_objects(operator: &TokenType, left_val: i64, right_val: i64) -> Object {
+    let value = match operator {
+        TokenType::ADD => left_val.checked_add(right_val).map(Object::Integer),
+        TokenType::MINUS => left_val.checked_sub(right_val).map(Object::Integer),
+        TokenType::MULTIPLY => left_val.checked_mul(right_val).map(Object::Integer),
+        TokenType::DIVIDE => {
+            if right_val == 0 {
+                return Object::Error(String::from("division by zero"));
+            }
+
+            // rounds towards zero, only -2^63 / -1 overflows
+            left_val.checked_div(right_val).map(Object::Integer)
+        }
+        TokenType::EQUAL_EQUAL => Some(Object::Boolean(left_val == right_val)),
+        TokenType::BANG_EQUAL => Some(Object::Boolean(left_val != right_val)),
+        TokenType::LESS => Some(Object::Boolean(left_val < right_val)),
+        TokenType::LESS_EQUAL => Some(Object::Boolean(left_val <= right_val)),
+        TokenType::GREATER => Some(Object::Boolean(left_val > right_val)),
+        TokenType::GREATER_EQUAL => Some(Object::Boolean(left_val >= right_val)),
+        _ => Some(Object::Null),
+    };
 
-        fn_call_value
+    match value {
+        Some(value) => value,
+        None => Object::Error(format!("integer overflow: {} {} {}", left_val, operator, right_val)),
+    }
+}
+
+fn eval_float_infix_objects(operator: &TokenType, left_val: f64, right_val: f64) -> Object {
+    match operator {
+        TokenType::ADD => Object::Float(left_val + right_val),
+        TokenType::MINUS => Object::Float(left_val - right_val),
+        TokenType::MULTIPLY => Object::Float(left_val * right_val),
+        TokenType::DIVIDE if right_val == 0.0 => Object::Error(String::from("division by zero")),
+        TokenType::DIVIDE => Object::Float(left_val / right_val),
+        TokenType::EQUAL_EQUAL => Object::Boolean(left_val == right_val),
+        TokenType::BANG_EQUAL => Object::Boolean(left_val != right_val),
+        TokenType::LESS => Object::Boolean(left_val < right_val),
+        TokenType::LESS_EQUAL => Object::Boolean(left_val <= right_val),
+        TokenType::GREATER => Object::Boolean(left_val > right_val),
+        TokenType::GREATER_EQUAL => Object::Boolean(left_val >= right_val),
+        _ => Object::Null,
     }
 }
 
@@ -345,7 +1154,7 @@ impl Evaluator {
 mod unit_test {
     use crate::env::Env;
     use crate::evaluator::Evaluator;
-    use crate::object::Object;
+    use crate::object::{BuiltinFunction, NativeObject, Object, RuntimeError};
     use crate::parser::Parser;
     use std::cell::RefCell;
     use std::rc::Rc;
@@ -360,6 +1169,16 @@ mod unit_test {
         evaluator.eval_program(program)
     }
 
+    // runs `prelude` and then each input as one program and compares the value in literal
+    // syntax, errors show as `error: <message>`
+    fn assert_displays(prelude: &str, cases: &[(&str, &str)]) {
+        for (input, expected) in cases {
+            let evaluated = get_eval_val(&format!("{} {}", prelude, input)).unwrap();
+
+            assert_eq!(evaluated.to_string(), *expected, "{}", input);
+        }
+    }
+
     #[test]
     fn eval_integer() {
         let evaluated = get_eval_val("12");
@@ -402,30 +1221,27 @@ mod unit_test {
         )
         .unwrap();
 
-        match hash_value {
-            Object::Hash(hashes) => {
-                assert_eq!(
-                    hashes.get(&Object::String(String::from("abc"))),
-                    Some(&Object::Boolean(true))
-                );
+        if let Object::Hash(hashes) = hash_value {
+            assert_eq!(
+                hashes.get(&Object::String(String::from("abc"))),
+                Some(&Object::Boolean(true))
+            );
 
-                assert_eq!(hashes.get(&Object::Integer(1)), Some(&Object::Integer(2)));
+            assert_eq!(hashes.get(&Object::Integer(1)), Some(&Object::Integer(2)));
 
-                assert_eq!(
-                    hashes.get(&Object::String(String::from("foo"))),
-                    Some(&Object::String(String::from("bar")))
-                );
+            assert_eq!(
+                hashes.get(&Object::String(String::from("foo"))),
+                Some(&Object::String(String::from("bar")))
+            );
 
-                assert_eq!(
-                    hashes.get(&Object::Integer(2)),
-                    Some(&Object::Array(vec![
-                        Object::Integer(1234),
-                        Object::Boolean(true),
-                        Object::String(String::from("Lynx programming language"))
-                    ]))
-                );
-            }
-            _ => (),
+            assert_eq!(
+                hashes.get(&Object::Integer(2)),
+                Some(&Object::Array(vec![
+                    Object::Integer(1234),
+                    Object::Boolean(true),
+                    Object::String(String::from("Lynx programming language"))
+                ]))
+            );
         }
     }
 
@@ -465,7 +1281,10 @@ mod unit_test {
             "#
                 )
             ),
-            format!("{:?}", Some(Object::Error(String::from("no identifier found: foo"))))
+            format!(
+                "{:?}",
+                Some(Object::Error(String::from("use of undefined variable: foo")))
+            )
         );
     }
 
@@ -522,6 +1341,25 @@ mod unit_test {
             format!("{:?}", get_eval_val(r#"!true"#)),
             format!("{:?}", Some(Object::Boolean(false)))
         );
+
+        let cases = [
+            ("-5", "-5"),
+            ("[-1.5, 1 - 5, -(2 - 7), --3]", "[-1.5, -4, 5, 3]"),
+            ("let x = 2.5; -x;", "-2.5"),
+            ("[-7 / 2, -7.0 / 2, -1 < 0]", "[-3, -3.5, true]"),
+            ("-9223372036854775807 - 1", "-9223372036854775808"),
+            (
+                "(-9223372036854775807 - 1) / -1",
+                "error: integer overflow: -9223372036854775808 / -1",
+            ),
+            (
+                "-(-9223372036854775807 - 1)",
+                "error: integer overflow: --9223372036854775808",
+            ),
+            (r#"-"a""#, r#"error: unsupported operand type for MINUS: String("a")"#),
+        ];
+
+        assert_displays("", &cases);
     }
 
     #[test]
@@ -766,4 +1604,1146 @@ mod unit_test {
         //     format!("{:?}", Some("[1, 2, 3]"))
         // );
     }
+
+    #[test]
+    fn eval_hash_builtins() {
+        let record = r#"let record = {"name": "lynx", "tags": {"a": 1, "b": 2}};"#;
+        let cases = [
+            ("keys(record)", r#"["name", "tags"]"#),
+            ("values(record)", r#"["lynx", {"a": 1, "b": 2}]"#),
+            (r#"entries({"a": 1})"#, r#"[["a", 1]]"#),
+            ("len(record)", "2"),
+            (r#"[has(record, "name"), has(record, "age")]"#, "[true, false]"),
+            (
+                r#"[get(record, "age"), get(record, "age", 3), get(record, "name", 3)]"#,
+                r#"[null, 3, "lynx"]"#,
+            ),
+            (
+                r#"set(record, "name", "cat")"#,
+                r#"{"name": "cat", "tags": {"a": 1, "b": 2}}"#,
+            ),
+            (r#"delete(record, "name")"#, r#"{"tags": {"a": 1, "b": 2}}"#),
+            (
+                r#"set(record, "age", 3); record"#,
+                r#"{"name": "lynx", "tags": {"a": 1, "b": 2}}"#,
+            ),
+            (
+                r#"merge(record, {"tags": {"c": 3}, "age": 3})"#,
+                r#"{"name": "lynx", "tags": {"c": 3}, "age": 3}"#,
+            ),
+            (
+                r#"deep_merge(record, {"tags": {"b": 4, "c": 3}})"#,
+                r#"{"name": "lynx", "tags": {"a": 1, "b": 4, "c": 3}}"#,
+            ),
+            ("keys([1])", "error: argument 1 of keys: expected hash, got array"),
+            ("set(record, len, 1)", "error: unhashable hash key: function"),
+            (
+                "get(record)",
+                "error: wrong number of arguments for get: expected 2 to 3, got 1",
+            ),
+        ];
+
+        assert_displays(record, &cases);
+    }
+
+    #[test]
+    fn eval_array_builtins() {
+        let cases = [
+            ("map([1, 2, 3], fn(x) { x * 2 })", "[2, 4, 6]"),
+            ("map([[1], [2, 3]], len)", "[1, 2]"),
+            ("filter(range(6), fn(x) { x > 3 })", "[4, 5]"),
+            ("reduce([1, 2, 3], fn(total, x) { total + x })", "6"),
+            ("reduce([], fn(total, x) { total + x }, 10)", "10"),
+            (
+                "[any([1, 2], fn(x) { x > 1 }), all([1, 2], fn(x) { x > 1 })]",
+                "[true, false]",
+            ),
+            (
+                "[find([1, 5, 7], fn(x) { x > 4 }), find_index([1, 5, 7], fn(x) { x > 4 })]",
+                "[5, 1]",
+            ),
+            ("find([1], fn(x) { x > 4 })", "null"),
+            (r#"sort(["b", "c", "a"])"#, r#"["a", "b", "c"]"#),
+            ("sort([1, 3, 2], fn(a, b) { a > b })", "[3, 2, 1]"),
+            ("sort_by([[3], [1, 1, 1], [2, 2]], len)", "[[3], [2, 2], [1, 1, 1]]"),
+            ("reverse([1, 2, 3])", "[3, 2, 1]"),
+            (r#"zip([1, 2, 3], ["a", "b"])"#, r#"[[1, "a"], [2, "b"]]"#),
+            ("flatten([[1], 2, [[3]]])", "[1, 2, [3]]"),
+            ("unique([1, 2, 1, [1], [1]])", "[1, 2, [1]]"),
+            (
+                "[slice([1, 2, 3, 4], 1, 3), slice([1, 2], 1), slice([1, 2], 5)]",
+                "[[2, 3], [2], []]",
+            ),
+            ("concat([1], [2, 3], [])", "[1, 2, 3]"),
+            (r#"join([1, "a", true], "-")"#, "1-a-true"),
+            (
+                "[range(3), range(2, 4), range(0, 10, 4)]",
+                "[[0, 1, 2], [2, 3], [0, 4, 8]]",
+            ),
+            ("sort([1, true])", "error: can not compare boolean and integer"),
+            (
+                "map([1], fn(x) { x + true })",
+                "error: unsupported operand types for ADD: Integer(1) and Boolean(true)",
+            ),
+            (
+                "sort([2, 1], fn(a, b) { first(a, b) })",
+                "error: wrong number of arguments for first: expected 1, got 2",
+            ),
+            ("map(1, len)", "error: argument 1 of map: expected array, got integer"),
+            ("range(0, 3, 0)", "error: range step must not be zero"),
+            (
+                "range(-9223372036854775807 - 1, 9223372036854775807)",
+                "error: range of 18446744073709551615 items is too large",
+            ),
+        ];
+
+        assert_displays("", &cases);
+    }
+
+    #[test]
+    fn eval_string_builtins() {
+        let cases = [
+            (r#"split("a,b,,c", ",")"#, r#"["a", "b", "", "c"]"#),
+            (r#"split("ab", "")"#, r#"["a", "b"]"#),
+            (r#"join(split("a b", " "), "-")"#, "a-b"),
+            (
+                r#"[trim("  a "), trim_start("  a "), trim_end("  a ")]"#,
+                r#"["a", "a ", "  a"]"#,
+            ),
+            (r#"[upper("lynx"), lower("LYNX")]"#, r#"["LYNX", "lynx"]"#),
+            (
+                r#"[contains("lynx", "yn"), starts_with("lynx", "ly"), ends_with("lynx", "ly")]"#,
+                "[true, true, false]",
+            ),
+            (r#"replace("a-b-c", "-", "+")"#, "a+b+c"),
+            (r#"[index_of("äbc", "c"), index_of("abc", "d")]"#, "[2, null]"),
+            (
+                r#"[substring("äbcd", 1, 3), substring("abc", 1), substring("abc", 5)]"#,
+                r#"["bc", "bc", ""]"#,
+            ),
+            (r#"chars("äb")"#, r#"["ä", "b"]"#),
+            (r#"[len("äb"), len("")]"#, "[2, 0]"),
+            (r#"repeat("ab", 3)"#, "ababab"),
+            (
+                r#"repeat("ab", 9223372036854775807)"#,
+                "error: the result of repeat is too large",
+            ),
+            (
+                r#"repeat("a", 4611686018427387904)"#,
+                "error: the result of repeat is too large",
+            ),
+            (
+                r#"pad_end("a", 9223372036854775807, "é")"#,
+                "error: the result of pad_end is too large",
+            ),
+            (
+                r#"pad_start("a", 4611686018427387904)"#,
+                "error: the result of pad_start is too large",
+            ),
+            (
+                r#"[pad_start("7", 3, "0"), pad_end("ab", 5, "-="), pad_start("abc", 2)]"#,
+                r#"["007", "ab-=-", "abc"]"#,
+            ),
+            (r#"to_int("42") + 1"#, "43"),
+            (r#"to_int("four")"#, r#"error: can not parse "four" as integer"#),
+            (r#"format("{} of {}, {{}}", 1, [true])"#, "1 of [true], {}"),
+            (
+                r#"format("{} and {}", 1)"#,
+                "error: format string has 2 placeholders, got 1 arguments",
+            ),
+            (r#"format("{")"#, r#"error: unmatched brace in format string "{""#),
+            (
+                r#"upper(1)"#,
+                "error: argument 1 of upper: expected string, got integer",
+            ),
+        ];
+
+        assert_displays("", &cases);
+    }
+
+    #[test]
+    fn eval_math_builtins() {
+        let cases = [
+            ("1.5 + 2", "3.5"),
+            ("[7 / 2, 7.0 / 2, 1 < 1.5, 2.0 == 2]", "[3, 3.5, true, true]"),
+            ("math.PI > 3.14 == (math.E < 2.72)", "true"),
+            ("[math.abs(3), math.abs(0.0 - 2.5)]", "[3, 2.5]"),
+            ("[math.min(3, 1.5, 2), math.max(3, 1.5, 2)]", "[1.5, 3]"),
+            ("[math.clamp(5, 0, 3), math.clamp(1, 0, 3)]", "[3, 1]"),
+            ("[math.pow(2, 10), math.pow(4, 0.5)]", "[1024, 2.0]"),
+            ("math.sqrt(16)", "4.0"),
+            (
+                "[math.floor(2.7), math.ceil(2.1), math.round(2.5), math.trunc(2.7), math.floor(3)]",
+                "[2.0, 3.0, 3.0, 2.0, 3]",
+            ),
+            ("[math.sin(0), math.cos(0), math.atan2(0, 1)]", "[0.0, 1.0, 0.0]"),
+            ("[math.log(math.E), math.log(8, 2), math.exp(0)]", "[1.0, 3.0, 1.0]"),
+            ("[math.gcd(12, 18), math.lcm(4, 6)]", "[6, 12]"),
+            (
+                "[math.floor(-2.5), math.ceil(-2.5), math.round(-2.5), math.trunc(-2.5)]",
+                "[-3.0, -2.0, -3.0, -2.0]",
+            ),
+            (
+                "[math.abs(1 - 5), math.abs(-2.5), math.min(-1, 2), math.clamp(-5, -3, 3)]",
+                "[4, 2.5, -1, -3]",
+            ),
+            (
+                "[math.pow(-2, 3), math.pow(2, -1), math.gcd(-12, 18), math.lcm(-4, 6)]",
+                "[-8, 0.5, 6, 12]",
+            ),
+            (
+                r#"[to_int("-5"), to_int(-2.9), range(2, -3, -2)]"#,
+                "[-5, -2, [2, 0, -2]]",
+            ),
+            (r#"[to_int(2.9), to_float("2.5"), to_float(3)]"#, "[2, 2.5, 3.0]"),
+            ("sort([2, 0.5, 1])", "[0.5, 1, 2]"),
+            ("math.sqrt(0.0 - 1)", "error: math.sqrt: the result is not a number"),
+            (
+                r#"math.abs("a")"#,
+                "error: argument 1 of math.abs: expected number, got string",
+            ),
+            ("math.pow(2, 100)", "error: integer overflow: 2 pow 100"),
+            ("math.tau", "error: no property tau on hash"),
+            ("1.5 / 0", "error: division by zero"),
+        ];
+
+        assert_displays("", &cases);
+    }
+
+    #[test]
+    fn eval_seeded_random() {
+        let run = |seed: u64| {
+            let mut evaluator = Evaluator::new(Rc::new(RefCell::new(Env::new())));
+            evaluator.builtin();
+            evaluator.set_seed(seed);
+
+            let program =
+                Parser::get("[random(), random_int(1, 6), shuffle(range(10)), choice([1, 2, 3]), random_int(5, 5)]")
+                    .parse_program();
+
+            evaluator.eval_program(program).unwrap().to_string()
+        };
+
+        assert_eq!(run(7), run(7));
+        assert_ne!(run(7), run(8));
+
+        let cases = [
+            ("sort(shuffle(range(20))) == range(20)", "true"),
+            (
+                "all(map(range(50), fn(_) { random_int(3, 4) }), fn(x) { x >= 3 == (x <= 4) })",
+                "true",
+            ),
+            (
+                "random_int(2, 1)",
+                "error: random_int: the low bound 2 is above the high bound 1",
+            ),
+            ("choice([])", "error: choice from an empty array"),
+        ];
+
+        assert_displays("", &cases);
+    }
+
+    #[test]
+    fn eval_json_builtins() {
+        let cases = [
+            (
+                r#"json_parse(json_stringify({"a": [1, 2.5, []], "b": {"c": true}}))"#,
+                r#"{"a": [1, 2.5, []], "b": {"c": true}}"#,
+            ),
+            (r#"len(json_stringify({"a": [1]}, 2))"#, "22"),
+            ("json_parse(json_stringify(1))", "1"),
+            (
+                r#"json_parse("[1, 2")"#,
+                "error: invalid json at byte 5: expected ',' or ']'",
+            ),
+            (
+                r#"json_stringify({"a": [1, fn() { 1 }]})"#,
+                "error: can not convert function to json at $.a[1]",
+            ),
+            (
+                "json_stringify({1: 2})",
+                "error: can not convert hash key 1 to json at $, keys have to be strings",
+            ),
+        ];
+
+        assert_displays("", &cases);
+    }
+
+    #[test]
+    fn eval_redirected_io() {
+        // output shared with the test after the evaluator took it
+        #[derive(Clone, Default)]
+        struct Captured(Rc<RefCell<Vec<u8>>>);
+
+        impl std::io::Write for Captured {
+            fn write(&mut self, bytes: &[u8]) -> std::io::Result<usize> {
+                self.0.borrow_mut().write(bytes)
+            }
+
+            fn flush(&mut self) -> std::io::Result<()> {
+                Ok(())
+            }
+        }
+
+        let stdout = Captured::default();
+        let stderr = Captured::default();
+
+        let mut evaluator = Evaluator::new(Rc::new(RefCell::new(Env::new())));
+        evaluator.builtin();
+        evaluator.set_stdout(stdout.clone());
+        evaluator.set_stderr(stderr.clone());
+        evaluator.set_stdin(std::io::Cursor::new("lynx\r\nlast"));
+
+        let program = Parser::get(
+            r#"
+            let name = input("name? ");
+            print("hello", name);
+            println("!", [1, "a"]);
+            eprintln("warning");
+            eprint(1);
+            [input(), input()]
+        "#,
+        )
+        .parse_program();
+
+        assert_eq!(
+            evaluator.eval_program(program).unwrap().to_string(),
+            r#"["last", null]"#
+        );
+        assert_eq!(
+            String::from_utf8(stdout.0.borrow().clone()).unwrap(),
+            "name? hello lynx\n! [1, \"a\"]\n"
+        );
+        assert_eq!(String::from_utf8(stderr.0.borrow().clone()).unwrap(), "warning\n1\n");
+    }
+
+    #[test]
+    fn eval_traced() {
+        use crate::trace::{Level, Stage, Tracer};
+
+        let events = Rc::new(RefCell::new(vec![]));
+        let recorded = Rc::clone(&events);
+        let tracer: Rc<dyn Tracer> = Rc::new(move |level: Level, stage: Stage, message: &str| {
+            recorded.borrow_mut().push(format!("{} {} {}", stage, level, message));
+        });
+
+        let mut evaluator = Evaluator::new(Rc::new(RefCell::new(Env::new())));
+        evaluator.set_tracer(Rc::clone(&tracer));
+
+        let mut parser = Parser::get("fn add(x, y) { x + y } add(1, 2); ");
+        parser.set_tracer(tracer);
+
+        assert_eq!(evaluator.eval_program(parser.parse_program()), Some(Object::Integer(3)));
+
+        let events = events.borrow();
+        assert!(events.contains(&String::from("token trace IDENTIFIER(\"add\")")));
+        assert!(events.contains(&String::from("parse debug program of 2 statements")));
+        assert!(events.contains(&String::from("eval info program starts")));
+        assert!(events.contains(&String::from("eval trace call add with 2 arguments")));
+
+        // only the events up to the level of the tracer are formatted
+        struct Failures(RefCell<Vec<String>>);
+
+        impl Tracer for Failures {
+            fn level(&self) -> Level {
+                Level::Info
+            }
+
+            fn event(&self, _: Level, _: Stage, message: &str) {
+                self.0.borrow_mut().push(message.to_string());
+            }
+        }
+
+        let failures = Rc::new(Failures(RefCell::new(vec![])));
+        evaluator.set_tracer(failures.clone());
+        evaluator.eval_program(Parser::get("add(1, true);").parse_program());
+
+        assert_eq!(
+            *failures.0.borrow(),
+            vec![
+                "program starts",
+                "program failed: unsupported operand types for ADD: Integer(1) and Boolean(true)"
+            ]
+        );
+    }
+
+    #[test]
+    fn eval_function_equality() {
+        // functions refer to themselves through the scope they were created in
+        assert_eq!(
+            format!("{:?}", get_eval_val("fn f() { 1 } f == f;")),
+            format!("{:?}", Some(Object::Boolean(true)))
+        );
+        assert_eq!(
+            format!("{:?}", get_eval_val("fn f() { 1 } len(unique([f, f, [f]]));")),
+            format!("{:?}", Some(Object::Integer(2)))
+        );
+        assert_eq!(
+            format!("{:?}", get_eval_val("fn f() { 1 } {\"f\": [f]} == {\"f\": [f]};")),
+            format!("{:?}", Some(Object::Boolean(true)))
+        );
+        assert_eq!(
+            format!(
+                "{:?}",
+                get_eval_val("let make = fn() { fn() { 1 } }; make() == make();")
+            ),
+            format!("{:?}", Some(Object::Boolean(false)))
+        );
+    }
+
+    #[test]
+    fn eval_closure_counter() {
+        assert_eq!(
+            format!(
+                "{:?}",
+                get_eval_val(
+                    r#"
+                fn counter() {
+                    let count = 0;
+                    return fn() {
+                        count = count + 1;
+                        return count;
+                    };
+                }
+                let foo = counter();
+                let bar = counter();
+                foo();
+                foo();
+                bar();
+                [foo(), bar()];
+            "#
+                )
+            ),
+            format!(
+                "{:?}",
+                Some(Object::Array(vec![Object::Integer(3), Object::Integer(2)]))
+            )
+        );
+    }
+
+    #[test]
+    fn eval_closure_ignores_later_rebinding() {
+        assert_eq!(
+            format!(
+                "{:?}",
+                get_eval_val(
+                    r#"
+                fn make() {
+                    let value = 1;
+                    let get = fn() { value };
+                    let value = 2;
+                    return [get(), value];
+                }
+                make();
+            "#
+                )
+            ),
+            format!(
+                "{:?}",
+                Some(Object::Array(vec![Object::Integer(1), Object::Integer(2)]))
+            )
+        );
+    }
+
+    #[test]
+    fn eval_closure_in_loop() {
+        assert_eq!(
+            format!(
+                "{:?}",
+                get_eval_val(
+                    r#"
+                let getters = [];
+                let i = 0;
+                while (i < 3) {
+                    let captured = i;
+                    getters = push(getters, fn() { captured });
+                    i = i + 1;
+                }
+                [first(getters)(), last(getters)()];
+            "#
+                )
+            ),
+            format!(
+                "{:?}",
+                Some(Object::Array(vec![Object::Integer(0), Object::Integer(2)]))
+            )
+        );
+    }
+
+    #[test]
+    fn eval_recursive_closure() {
+        assert_eq!(
+            format!(
+                "{:?}",
+                get_eval_val(
+                    r#"
+                fn outer() {
+                    let sum = fn(x) {
+                        if (x == 0) {
+                            return 0;
+                        }
+                        return x + sum(x - 1);
+                    };
+                    return sum;
+                }
+                outer()(10);
+            "#
+                )
+            ),
+            format!("{:?}", Some(Object::Integer(55)))
+        );
+    }
+
+    #[test]
+    fn eval_mutual_recursion() {
+        assert_eq!(
+            format!(
+                "{:?}",
+                get_eval_val(
+                    r#"
+                fn parity(n) {
+                    fn is_even(x) {
+                        if (x == 0) {
+                            return true;
+                        }
+                        return is_odd(x - 1);
+                    }
+                    fn is_odd(x) {
+                        if (x == 0) {
+                            return false;
+                        }
+                        return is_even(x - 1);
+                    }
+                    return [is_even(n), is_odd(n)];
+                }
+                parity(7);
+            "#
+                )
+            ),
+            format!(
+                "{:?}",
+                Some(Object::Array(vec![Object::Boolean(false), Object::Boolean(true)]))
+            )
+        );
+    }
+
+    #[test]
+    fn eval_assign_undefined_variable() {
+        assert_eq!(
+            format!("{:?}", get_eval_val(r#"foo = 1;"#)),
+            format!(
+                "{:?}",
+                Some(Object::Error(String::from("assignment to undefined variable: foo")))
+            )
+        );
+    }
+
+    #[test]
+    fn eval_closure_sees_later_declaration() {
+        assert_eq!(
+            format!(
+                "{:?}",
+                get_eval_val(
+                    r#"
+                fn make() {
+                    let get = fn() { later };
+                    let later = 42;
+                    return get();
+                }
+                make();
+            "#
+                )
+            ),
+            format!("{:?}", Some(Object::Integer(42)))
+        );
+    }
+
+    #[test]
+    fn eval_duplicate_parameter() {
+        assert_eq!(
+            format!("{:?}", get_eval_val(r#"fn foo(bar, bar) { bar }"#)),
+            format!("{:?}", Some(Object::Error(String::from("duplicate parameter: bar"))))
+        );
+    }
+
+    #[test]
+    fn eval_tail_call() {
+        assert_eq!(
+            format!(
+                "{:?}",
+                get_eval_val(
+                    r#"
+                fn count_down(n) {
+                    if (n == 0) {
+                        return "done";
+                    }
+                    return count_down(n - 1);
+                }
+                fn sum(n, total) {
+                    if (n == 0) { total } else { sum(n - 1, total + n) }
+                }
+                [count_down(100000), sum(100000, 0)];
+            "#
+                )
+            ),
+            format!(
+                "{:?}",
+                Some(Object::Array(vec![
+                    Object::String(String::from("done")),
+                    Object::Integer(5000050000)
+                ]))
+            )
+        );
+    }
+
+    #[test]
+    fn eval_mutual_tail_call() {
+        assert_eq!(
+            format!(
+                "{:?}",
+                get_eval_val(
+                    r#"
+                fn is_even(x) { if (x == 0) { return true; } return is_odd(x - 1); }
+                fn is_odd(x) { if (x == 0) { return false; } return is_even(x - 1); }
+                is_even(100001);
+            "#
+                )
+            ),
+            format!("{:?}", Some(Object::Boolean(false)))
+        );
+    }
+
+    #[test]
+    fn eval_max_expression_depth() {
+        let mut evaluator = Evaluator::new(Rc::new(RefCell::new(Env::new())));
+        evaluator.set_max_expression_depth(4);
+
+        let program = Parser::get("[[[1]]]; [[[[1]]]];").parse_program();
+        assert_eq!(
+            evaluator.eval_program(program).unwrap().to_string(),
+            "error: maximum expression depth of 4 exceeded"
+        );
+
+        // the depth is back to zero after the error
+        let program = Parser::get("fn f(x) { [x] } [f([1])]").parse_program();
+        assert_eq!(evaluator.eval_program(program).unwrap().to_string(), "[[[1]]]");
+    }
+
+    #[test]
+    fn eval_max_call_depth() {
+        let mut evaluator = Evaluator::new(Rc::new(RefCell::new(Env::new())));
+        evaluator.builtin();
+        evaluator.set_max_call_depth(4);
+
+        let program = Parser::get(
+            r#"
+            fn f(n) { if (n == 0) { 0 } else { 1 + f(n - 1) } }
+            fn main(n) { 1 + f(n) }
+            fn count_down(n) { if (n == 0) { "done" } else { count_down(n - 1) } }
+            main(10);
+        "#,
+        )
+        .parse_program();
+
+        assert_eq!(
+            format!("{:?}", evaluator.eval_program(program)),
+            format!(
+                "{:?}",
+                Some(Object::Error(String::from(
+                    "maximum call depth of 4 exceeded, call stack: main -> f (x4)"
+                )))
+            )
+        );
+
+        // the stack is unwound after the error and tail calls do not count
+        assert_eq!(
+            format!(
+                "{:?}",
+                evaluator.eval_program(Parser::get("[main(2), count_down(1000)];").parse_program())
+            ),
+            format!(
+                "{:?}",
+                Some(Object::Array(vec![
+                    Object::Integer(3),
+                    Object::String(String::from("done"))
+                ]))
+            )
+        );
+
+        // callbacks are named after their function
+        assert_eq!(
+            evaluator
+                .eval_program(Parser::get("map([5], f);").parse_program())
+                .unwrap()
+                .to_string(),
+            "error: maximum call depth of 4 exceeded, call stack: f (x5)"
+        );
+        assert_eq!(
+            evaluator
+                .eval_program(Parser::get("map([5], fn(n) { 1 + f(n) });").parse_program())
+                .unwrap()
+                .to_string(),
+            "error: maximum call depth of 4 exceeded, call stack: <anonymous> -> f (x4)"
+        );
+
+        // the default limits keep deep recursion off the native stack, also with many nested
+        // expressions in each frame
+        assert_eq!(
+            get_eval_val(
+                "fn f(n) { if (n == 0) { return 0; } let x = [[[[[[[[1 + f(n - 1)]]]]]]]]; return 1; } f(250);"
+            )
+            .unwrap()
+            .to_string(),
+            "error: maximum expression depth of 1024 exceeded"
+        );
+        assert!(matches!(
+            get_eval_val("fn f(n) { if (n == 0) { 0 } else { 1 + f(n - 1) } } f(100000);"),
+            Some(Object::Error(message)) if message.starts_with("maximum call depth of 256 exceeded")
+        ));
+    }
+
+    #[test]
+    fn eval_fuel() {
+        let mut evaluator = Evaluator::new(Rc::new(RefCell::new(Env::new())));
+        evaluator.set_fuel(Some(1000));
+
+        let program = Parser::get("let i = 0; while (true) { i = i + 1; }").parse_program();
+
+        assert_eq!(
+            format!("{:?}", evaluator.eval_program(program)),
+            format!("{:?}", Some(Object::Error(String::from("out of fuel"))))
+        );
+        assert_eq!(evaluator.fuel(), Some(0));
+
+        // the program got as far as the fuel allowed, its bindings stay
+        evaluator.add_fuel(10);
+        assert_eq!(
+            format!("{:?}", evaluator.eval_program(Parser::get("i > 100;").parse_program())),
+            format!("{:?}", Some(Object::Boolean(true)))
+        );
+        assert_eq!(evaluator.fuel(), Some(6));
+    }
+
+    #[test]
+    fn eval_memory_limit() {
+        let mut evaluator = Evaluator::new(Rc::new(RefCell::new(Env::new())));
+        evaluator.builtin();
+        evaluator.set_memory_limit(Some(64 * 1024));
+
+        // temporaries are freed again and do not add up
+        let program = Parser::get(
+            r#"
+            let i = 0;
+            while (i < 5000) {
+                let text = "lynx" + " programming language";
+                i = i + 1;
+            }
+            i;
+        "#,
+        )
+        .parse_program();
+        assert_eq!(
+            format!("{:?}", evaluator.eval_program(program)),
+            format!("{:?}", Some(Object::Integer(5000)))
+        );
+
+        let program = Parser::get(r#"let items = []; while (true) { items = push(items, "item"); }"#).parse_program();
+        assert_eq!(
+            format!("{:?}", evaluator.eval_program(program)),
+            format!(
+                "{:?}",
+                Some(Object::Error(String::from("memory limit of 65536 bytes exceeded")))
+            )
+        );
+    }
+
+    #[test]
+    fn eval_memory_limit_in_builtins() {
+        let mut evaluator = Evaluator::new(Rc::new(RefCell::new(Env::new())));
+        evaluator.builtin();
+        evaluator.set_memory_limit(Some(1 << 20));
+        evaluator.eval_program(Parser::get("let items = range(0, 4000);").parse_program());
+
+        // too big to build, each fails before it allocates
+        let cases = [
+            r#"repeat("ab", 100000000000)"#,
+            r#"pad_start("a", 100000000000)"#,
+            r#"pad_end("a", 100000000000, "é")"#,
+            "range(0, 100000000000)",
+            "range(100000000000, 0, -1)",
+            "concat(items, items, items, items)",
+            "flatten([items, items, items, items])",
+            "zip(items, items)",
+        ];
+
+        for input in cases {
+            assert_eq!(
+                evaluator
+                    .eval_program(Parser::get(input).parse_program())
+                    .unwrap()
+                    .to_string(),
+                "error: memory limit of 1048576 bytes exceeded",
+                "{}",
+                input
+            );
+        }
+
+        assert_eq!(
+            evaluator
+                .eval_program(Parser::get("len(concat(items, [1]))").parse_program())
+                .unwrap()
+                .to_string(),
+            "4001"
+        );
+    }
+
+    #[test]
+    fn eval_memory_limit_is_a_ceiling() {
+        let mut evaluator = Evaluator::new(Rc::new(RefCell::new(Env::new())));
+        evaluator.builtin();
+        evaluator.set_memory_limit(Some(1 << 20));
+
+        // values that are only on the rust stack while they are collected count as well
+        let cases = [
+            r#"map(range(300), fn(i) { repeat("a", 400000) })"#,
+            r#"filter(range(300), fn(i) { repeat("a", 400000) })"#,
+            r#"sort_by(range(300), fn(i) { repeat("a", 400000) })"#,
+            r#"reduce(range(300), fn(total, i) { total + repeat("a", 400000) }, "")"#,
+            r#"[repeat("a", 400000), repeat("a", 400000), repeat("a", 400000), repeat("a", 400000)]"#,
+            r#"{1: repeat("a", 400000), 2: repeat("a", 400000), 3: repeat("a", 400000)}"#,
+            r#"fn(a, b, c) { 0 }(repeat("a", 400000), repeat("a", 400000), repeat("a", 400000))"#,
+        ];
+
+        for input in cases {
+            let value = evaluator.eval_program(Parser::get(input).parse_program());
+            assert_eq!(
+                value.unwrap().to_string(),
+                "error: memory limit of 1048576 bytes exceeded",
+                "{}",
+                input
+            );
+            assert!(
+                evaluator.peak_memory() <= 1 << 20,
+                "{} {}",
+                input,
+                evaluator.peak_memory()
+            );
+        }
+    }
+
+    #[test]
+    fn eval_peak_memory() {
+        let mut evaluator = Evaluator::new(Rc::new(RefCell::new(Env::new())));
+        evaluator.builtin();
+
+        let program = Parser::get("let items = []; let i = 0; while (i < 1000) { items = push(items, i); i = i + 1; }")
+            .parse_program();
+        evaluator.eval_program(program);
+
+        let peak = evaluator.peak_memory();
+        assert!(peak >= 1000 * std::mem::size_of::<Object>(), "{}", peak);
+
+        evaluator.eval_program(Parser::get("items = [];").parse_program());
+        evaluator.eval_program(Parser::get("1 + 1;").parse_program());
+        assert!(evaluator.peak_memory() < peak);
+    }
+
+    #[test]
+    fn eval_interrupt_between_runs() {
+        let mut evaluator = Evaluator::new(Rc::new(RefCell::new(Env::new())));
+
+        evaluator.eval_program(Parser::get("let total = 1;").parse_program());
+
+        // sent before the program started, it still stops the program
+        evaluator.interrupt_handle().interrupt();
+
+        let program = Parser::get("let total = 2;").parse_program();
+        assert_eq!(
+            format!("{:?}", evaluator.eval_program(program)),
+            format!("{:?}", Some(Object::Error(String::from("interrupted"))))
+        );
+        assert!(!evaluator.interrupt_handle().is_interrupted());
+
+        let program = Parser::get("fn add(x) { total + x } add(1);").parse_program();
+        assert_eq!(
+            format!("{:?}", evaluator.eval_program(program)),
+            format!("{:?}", Some(Object::Integer(2)))
+        );
+    }
+
+    #[test]
+    fn eval_interrupt() {
+        let mut evaluator = Evaluator::new(Rc::new(RefCell::new(Env::new())));
+        let handle = evaluator.interrupt_handle();
+
+        let interrupter = std::thread::spawn(move || {
+            std::thread::sleep(std::time::Duration::from_millis(50));
+            handle.interrupt();
+        });
+
+        let program = Parser::get(
+            r#"
+            let total = 0;
+            fn spin() { while (true) { total = total + 1; } }
+            spin();
+        "#,
+        )
+        .parse_program();
+
+        assert_eq!(
+            format!("{:?}", evaluator.eval_program(program)),
+            format!("{:?}", Some(Object::Error(String::from("interrupted"))))
+        );
+        interrupter.join().unwrap();
+
+        // back in the global scope, the next program runs normally
+        let program = Parser::get("let done = total > 0; done;").parse_program();
+        assert_eq!(
+            format!("{:?}", evaluator.eval_program(program)),
+            format!("{:?}", Some(Object::Boolean(true)))
+        );
+        assert!(evaluator.get_env().get(String::from("done")).is_some());
+    }
+
+    #[test]
+    fn eval_program_with_deadline() {
+        let mut evaluator = Evaluator::new(Rc::new(RefCell::new(Env::new())));
+
+        let program = Parser::get("fn spin(n) { spin(n + 1) } spin(0);").parse_program();
+        assert_eq!(
+            format!(
+                "{:?}",
+                evaluator.eval_program_with_deadline(program, std::time::Duration::from_millis(20))
+            ),
+            format!("{:?}", Some(Object::Error(String::from("timeout"))))
+        );
+
+        let program = Parser::get("let i = 0; while (i < 10) { i = i + 1; } i;").parse_program();
+        assert_eq!(
+            format!(
+                "{:?}",
+                evaluator.eval_program_with_deadline(program, std::time::Duration::from_secs(10))
+            ),
+            format!("{:?}", Some(Object::Integer(10)))
+        );
+    }
+
+    #[test]
+    fn eval_hash_keys() {
+        let hash = get_eval_val(r#"{[1, 2]: "pair", {"a": 1}: "record", "b": 2, [1, 2]: "again"}"#).unwrap();
+
+        if let Object::Hash(pairs) = &hash {
+            assert_eq!(
+                pairs.get(&Object::Array(vec![Object::Integer(1), Object::Integer(2)])),
+                Some(&Object::String(String::from("again")))
+            );
+            assert_eq!(pairs.len(), 3);
+        }
+        assert_eq!(hash.to_string(), r#"{[1, 2]: "again", {"a": 1}: "record", "b": 2}"#);
+
+        assert_eq!(
+            format!("{:?}", get_eval_val(r#"{"a": 1, len: 2}"#)),
+            format!(
+                "{:?}",
+                Some(Object::Error(String::from("unhashable hash key: function")))
+            )
+        );
+        assert_eq!(
+            format!("{:?}", get_eval_val("{[fn() { 1 }]: 2}")),
+            format!(
+                "{:?}",
+                Some(Object::Error(String::from("unhashable hash key: function")))
+            )
+        );
+        assert_eq!(
+            format!("{:?}", get_eval_val("{[1, {\"a\": [len]}]: 2}")),
+            format!(
+                "{:?}",
+                Some(Object::Error(String::from("unhashable hash key: function")))
+            )
+        );
+    }
+
+    #[test]
+    fn eval_str_and_repr() {
+        let cases = [
+            (r#"str("lynx")"#, "lynx"),
+            (r#"repr("lynx")"#, "\"lynx\""),
+            (r#"str([1, "a", true, [[]]])"#, "[1, \"a\", true, [[]]]"),
+            (r#"str({"b": 2, "a": [1], 3: "c"})"#, "{\"b\": 2, \"a\": [1], 3: \"c\"}"),
+            ("str(fn(x, y) { x + y })", "fn(x, y) {...}"),
+            ("str(len)", "<builtin len>"),
+        ];
+
+        for (input, expected) in cases {
+            assert_eq!(
+                format!("{:?}", get_eval_val(input)),
+                format!("{:?}", Some(Object::String(String::from(expected)))),
+                "{}",
+                input
+            );
+        }
+    }
+
+    #[test]
+    fn eval_register_fn() {
+        let mut evaluator = Evaluator::new(Rc::new(RefCell::new(Env::new())));
+        evaluator.builtin();
+
+        // host state shared with the closure
+        let calls = Rc::new(std::cell::Cell::new(0));
+        let counter = Rc::clone(&calls);
+
+        evaluator.register_fn("count", move |_: &mut Evaluator, arguments: &[Object]| {
+            counter.set(counter.get() + arguments.len() as i64);
+            Ok(Object::Integer(counter.get()))
+        });
+        evaluator.register_fn("fail", |_: &mut Evaluator, _: &[Object]| {
+            Err(RuntimeError::new("database unavailable"))
+        });
+        evaluator.register_builtin(BuiltinFunction::new("pair", Some(2), |_, arguments| {
+            Ok(Object::Array(arguments.to_vec()))
+        }));
+
+        let program = Parser::get("count(1, 2); [count(3), pair(1, 2)];").parse_program();
+        assert_eq!(
+            format!("{:?}", evaluator.eval_program(program)),
+            format!(
+                "{:?}",
+                Some(Object::Array(vec![
+                    Object::Integer(3),
+                    Object::Array(vec![Object::Integer(1), Object::Integer(2)])
+                ]))
+            )
+        );
+        assert_eq!(calls.get(), 3);
+
+        assert_eq!(
+            format!("{:?}", evaluator.eval_program(Parser::get("fail();").parse_program())),
+            format!("{:?}", Some(Object::Error(String::from("database unavailable"))))
+        );
+        assert_eq!(
+            format!("{:?}", evaluator.eval_program(Parser::get("pair(1);").parse_program())),
+            format!(
+                "{:?}",
+                Some(Object::Error(String::from(
+                    "wrong number of arguments for pair: expected 2, got 1"
+                )))
+            )
+        );
+    }
+
+    #[test]
+    fn eval_native_methods() {
+        struct Counter {
+            count: std::cell::Cell<usize>,
+        }
+
+        let mut evaluator = Evaluator::new(Rc::new(RefCell::new(Env::new())));
+
+        evaluator.register_fn("counter", || {
+            NativeObject::new(
+                "Counter",
+                Counter {
+                    count: std::cell::Cell::new(0),
+                },
+            )
+        });
+        evaluator.register_method("Counter", "add", |counter: NativeObject, by: usize| {
+            let counter = counter.downcast_ref::<Counter>().unwrap();
+            counter.count.set(counter.count.get() + by);
+            counter.count.get()
+        });
+        evaluator.register_method("string", "shout", |text: String| text + "!");
+
+        let program = Parser::get(
+            r#"
+            let a = counter();
+            let b = counter();
+            a.add(2);
+            [a.add(3), b.add(1), a == a, a == b, "lynx".shout()];
+        "#,
+        )
+        .parse_program();
+        assert_eq!(
+            format!("{:?}", evaluator.eval_program(program)),
+            format!(
+                "{:?}",
+                Some(Object::Array(vec![
+                    Object::Integer(5),
+                    Object::Integer(1),
+                    Object::Boolean(true),
+                    Object::Boolean(false),
+                    Object::String(String::from("lynx!"))
+                ]))
+            )
+        );
+
+        // identity hashing, both counters are separate keys
+        let program = Parser::get("{a: 1, b: 2};").parse_program();
+        assert!(matches!(evaluator.eval_program(program), Some(Object::Hash(hash)) if hash.len() == 2));
+
+        assert_eq!(
+            format!(
+                "{:?}",
+                evaluator.eval_program(Parser::get("a.reset();").parse_program())
+            ),
+            format!("{:?}", Some(Object::Error(String::from("no method reset on Counter"))))
+        );
+    }
+
+    #[test]
+    fn eval_closure_cycles_stay_bounded() {
+        let program = Parser::get(
+            r#"
+            fn make() {
+                let self_ref = fn() { self_ref };
+                return 0;
+            }
+            let i = 0;
+            while (i < 5000) {
+                make();
+                i = i + 1;
+            }
+        "#,
+        )
+        .parse_program();
+        let mut evaluator = Evaluator::new(Rc::new(RefCell::new(Env::new())));
+        evaluator.eval_program(program);
+
+        let stats = evaluator.heap_stats();
+        assert!(stats.collections > 0);
+        assert!(stats.live_envs < 2048, "{:?}", stats);
+
+        evaluator.collect_garbage();
+        assert!(evaluator.heap_stats().live_envs <= 1);
+    }
+
+    #[test]
+    fn eval_collect_keeps_reachable_closures() {
+        let mut evaluator = Evaluator::new(Rc::new(RefCell::new(Env::new())));
+        evaluator.eval_program(
+            Parser::get(
+                r#"
+            fn counter() {
+                let count = 0;
+                return fn() {
+                    count = count + 1;
+                    return count;
+                };
+            }
+            let next = counter();
+            next();
+        "#,
+            )
+            .parse_program(),
+        );
+
+        assert_eq!(evaluator.collect_garbage(), 0);
+
+        assert_eq!(
+            format!("{:?}", evaluator.eval_program(Parser::get("next();").parse_program())),
+            format!("{:?}", Some(Object::Integer(2)))
+        );
+    }
 }
@@ -0,0 +1,237 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::{Rc, Weak};
+
+use crate::env::Env;
+use crate::object::Object;
+
+const INITIAL_THRESHOLD: usize = 1024;
+
+// Every `Object::Function` keeps its defining `Env` alive and that `Env` usually stores the
+// function again, a `Rc` cycle that is never freed on its own. The heap keeps a weak handle
+// to each scope the evaluator creates and breaks the cycles nobody outside can reach anymore.
+//
+// Collection is trial deletion, like CPython's cycle collector: subtract the references scopes
+// hold on each other from their strong counts. Whatever is left is referenced from outside
+// (the evaluator, the rust stack, values the host holds on to) and is a root. Scopes not
+// reachable from a root are garbage, clearing their bindings drops the cycle.
+#[derive(Debug)]
+pub struct Heap {
+    envs: Vec<Weak<RefCell<Env>>>,
+    threshold: usize,
+    collections: usize,
+    freed: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HeapStats {
+    // scopes that are still allocated
+    pub live_envs: usize,
+    // collections run so far, automatic and explicit
+    pub collections: usize,
+    // scopes freed by breaking their cycles so far
+    pub freed: usize,
+}
+
+impl Default for Heap {
+    fn default() -> Self {
+        Heap::new()
+    }
+}
+
+impl Heap {
+    pub fn new() -> Self {
+        Heap {
+            envs: vec![],
+            threshold: INITIAL_THRESHOLD,
+            collections: 0,
+            freed: 0,
+        }
+    }
+
+    pub fn alloc(&mut self, env: Env) -> Rc<RefCell<Env>> {
+        let env = Rc::new(RefCell::new(env));
+
+        self.track(&env);
+
+        env
+    }
+
+    pub fn track(&mut self, env: &Rc<RefCell<Env>>) {
+        self.envs.push(Rc::downgrade(env));
+    }
+
+    // whether enough scopes were allocated since the last collection to run another one
+    pub fn should_collect(&self) -> bool {
+        self.envs.len() >= self.threshold
+    }
+
+    pub fn stats(&mut self) -> HeapStats {
+        self.envs.retain(|env| env.strong_count() > 0);
+
+        HeapStats {
+            live_envs: self.envs.len(),
+            collections: self.collections,
+            freed: self.freed,
+        }
+    }
+
+    // bytes held by the bindings of all live scopes
+    pub fn live_bytes(&self) -> usize {
+        self.envs
+            .iter()
+            .filter_map(Weak::upgrade)
+            .map(|env| match env.try_borrow() {
+                Ok(env) => std::mem::size_of::<Env>() + env.values().map(Object::allocated_size).sum::<usize>(),
+                Err(_) => std::mem::size_of::<Env>(),
+            })
+            .sum()
+    }
+
+    // break unreachable cycles, returns how many scopes were freed
+    pub fn collect(&mut self) -> usize {
+        let envs = self.envs.iter().filter_map(Weak::upgrade).collect::<Vec<_>>();
+
+        let index = envs
+            .iter()
+            .enumerate()
+            .map(|(i, env)| (Rc::as_ptr(env), i))
+            .collect::<HashMap<_, _>>();
+
+        // scopes referenced by each scope, either as outer scope or through a closure it stores
+        let edges = envs
+            .iter()
+            .map(|env| match env.try_borrow() {
+                Ok(env) => {
+                    let mut children = vec![];
+
+                    if let Some(outer) = env.outer() {
+                        children.push(Rc::clone(outer));
+                    }
+
+                    env.values().for_each(|value| referenced_envs(value, &mut children));
+
+                    children
+                        .iter()
+                        .filter_map(|child| index.get(&Rc::as_ptr(child)).copied())
+                        .collect::<Vec<_>>()
+                }
+                // in use right now, the references it holds are left out and keep their targets alive
+                Err(_) => vec![],
+            })
+            .collect::<Vec<_>>();
+
+        // minus one for the handle upgraded above
+        let mut external = envs.iter().map(|env| Rc::strong_count(env) - 1).collect::<Vec<_>>();
+
+        for children in &edges {
+            for &child in children {
+                external[child] -= 1;
+            }
+        }
+
+        let mut reachable = vec![false; envs.len()];
+        let mut pending = (0..envs.len()).filter(|&i| external[i] > 0).collect::<Vec<_>>();
+
+        while let Some(i) = pending.pop() {
+            if reachable[i] {
+                continue;
+            }
+
+            reachable[i] = true;
+            pending.extend(edges[i].iter().filter(|&&child| !reachable[child]));
+        }
+
+        // the bindings are dropped only after every borrow is released, their destructors
+        // release other scopes of the same cycle
+        let mut garbage = vec![];
+
+        for (i, env) in envs.iter().enumerate() {
+            if !reachable[i] {
+                if let Ok(mut env) = env.try_borrow_mut() {
+                    garbage.push(env.clear());
+                }
+            }
+        }
+
+        let freed = garbage.len();
+
+        drop(envs);
+        drop(garbage);
+
+        self.envs.retain(|env| env.strong_count() > 0);
+        self.threshold = INITIAL_THRESHOLD.max(self.envs.len() * 2);
+        self.collections += 1;
+        self.freed += freed;
+
+        freed
+    }
+}
+
+fn referenced_envs(object: &Object, envs: &mut Vec<Rc<RefCell<Env>>>) {
+    match object {
+        Object::Function(_, _, _, env) => envs.push(Rc::clone(env)),
+        Object::Array(items) => items.iter().for_each(|item| referenced_envs(item, envs)),
+        Object::Hash(pairs) => pairs.iter().for_each(|(key, value)| {
+            referenced_envs(key, envs);
+            referenced_envs(value, envs);
+        }),
+        Object::ReturnValue(value) => referenced_envs(value, envs),
+        Object::TailCall(_, function, arguments) => {
+            referenced_envs(function, envs);
+            arguments.iter().for_each(|argument| referenced_envs(argument, envs));
+        }
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod unit_test {
+    use crate::ast::Statement;
+    use crate::env::Env;
+    use crate::heap::Heap;
+    use crate::object::Object;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    fn closure_in(env: &Rc<RefCell<Env>>) -> Object {
+        Object::Function(String::new(), vec![], Statement::BlockStatement(vec![]), Rc::clone(env))
+    }
+
+    #[test]
+    fn collect_unreachable_cycle() {
+        let mut heap = Heap::new();
+
+        let env = heap.alloc(Env::new());
+        let function = closure_in(&env);
+        env.borrow_mut().set("self".to_string(), function);
+
+        drop(env);
+
+        assert_eq!(heap.stats().live_envs, 1);
+        assert_eq!(heap.collect(), 1);
+        assert_eq!(heap.stats().live_envs, 0);
+    }
+
+    #[test]
+    fn keep_externally_referenced_cycle() {
+        let mut heap = Heap::new();
+
+        let outer = heap.alloc(Env::new());
+        let inner = heap.alloc(Env::enclosed_outer_env(Rc::clone(&outer)));
+        let function = closure_in(&inner);
+        inner.borrow_mut().set("self".to_string(), function.clone());
+
+        drop(inner);
+
+        // the host still holds the closure, so its scope and the outer one stay
+        assert_eq!(heap.collect(), 0);
+        assert_eq!(heap.stats().live_envs, 2);
+
+        drop(function);
+        drop(outer);
+
+        assert_eq!(heap.collect(), 2);
+        assert_eq!(heap.stats().live_envs, 0);
+    }
+}
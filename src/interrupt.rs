@@ -0,0 +1,35 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+// Stops a running evaluation from another thread. The evaluator checks the flag at every loop
+// iteration and function call and fails with `INTERRUPTED`, every scope it entered is left
+// again on the way out, so the bindings are usable afterwards. An interrupt sent before a program
+// starts stops that program at its first statement, the flag is cleared once it is delivered.
+#[derive(Debug, Clone, Default)]
+pub struct InterruptHandle {
+    interrupted: Arc<AtomicBool>,
+}
+
+// the error of an interrupted program
+pub const INTERRUPTED: &str = "interrupted";
+
+impl InterruptHandle {
+    pub fn new() -> Self {
+        InterruptHandle {
+            interrupted: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    pub fn interrupt(&self) {
+        self.interrupted.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_interrupted(&self) -> bool {
+        self.interrupted.load(Ordering::Relaxed)
+    }
+
+    // true once for every `interrupt`, the next program runs again
+    pub(crate) fn take(&self) -> bool {
+        self.interrupted.swap(false, Ordering::Relaxed)
+    }
+}
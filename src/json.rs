@@ -0,0 +1,495 @@
+use std::fmt::Write;
+
+use crate::object::{Object, RuntimeError};
+use crate::ordered_hash::OrderedHash;
+
+// JSON to values and back, behind the `json_parse` and `json_stringify` builtins.
+//
+// Objects become hashes in the order of their keys, numbers without fraction or exponent that
+// fit an integer become integers and every other number a float. Errors of `parse` name the
+// byte offset in the text, the ones of `stringify` the path to the value, like `$.items[2]`.
+
+// arrays and objects nested deeper fail instead of overflowing the native stack
+const MAX_DEPTH: usize = 512;
+
+pub fn parse(text: &str) -> Result<Object, RuntimeError> {
+    let mut parser = JsonParser {
+        text,
+        bytes: text.as_bytes(),
+        position: 0,
+        depth: 0,
+    };
+
+    parser.skip_white_space();
+    let value = parser.parse_value()?;
+    parser.skip_white_space();
+
+    match parser.position < parser.bytes.len() {
+        true => Err(parser.error("unexpected text after the value")),
+        false => Ok(value),
+    }
+}
+
+// `indent` spaces per level, 0 writes everything on one line
+pub fn stringify(value: &Object, indent: usize) -> Result<String, RuntimeError> {
+    let mut output = String::new();
+
+    write_value(value, indent, 0, &mut String::from("$"), &mut output)?;
+
+    Ok(output)
+}
+
+struct JsonParser<'a> {
+    text: &'a str,
+    bytes: &'a [u8],
+    position: usize,
+    depth: usize,
+}
+
+impl<'a> JsonParser<'a> {
+    fn error(&self, message: &str) -> RuntimeError {
+        RuntimeError::new(format!("invalid json at byte {}: {}", self.position, message))
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.bytes.get(self.position).copied()
+    }
+
+    fn skip_white_space(&mut self) {
+        while let Some(b' ' | b'\t' | b'\n' | b'\r') = self.peek() {
+            self.position += 1;
+        }
+    }
+
+    fn expect(&mut self, byte: u8) -> Result<(), RuntimeError> {
+        match self.peek() {
+            Some(found) if found == byte => {
+                self.position += 1;
+                Ok(())
+            }
+            _ => Err(self.error(&format!("expected '{}'", byte as char))),
+        }
+    }
+
+    fn parse_value(&mut self) -> Result<Object, RuntimeError> {
+        match self.peek() {
+            Some(b'{') => self.nested(JsonParser::parse_object),
+            Some(b'[') => self.nested(JsonParser::parse_array),
+            Some(b'"') => self.parse_string().map(Object::String),
+            Some(b'-' | b'0'..=b'9') => self.parse_number(),
+            Some(b't') => self.parse_keyword("true", Object::Boolean(true)),
+            Some(b'f') => self.parse_keyword("false", Object::Boolean(false)),
+            Some(b'n') => self.parse_keyword("null", Object::Null),
+            Some(_) => Err(self.error("expected a value")),
+            None => Err(self.error("unexpected end of input")),
+        }
+    }
+
+    fn nested(&mut self, parse: fn(&mut Self) -> Result<Object, RuntimeError>) -> Result<Object, RuntimeError> {
+        if self.depth == MAX_DEPTH {
+            return Err(self.error("nested too deep"));
+        }
+
+        self.depth += 1;
+        let value = parse(self);
+        self.depth -= 1;
+
+        value
+    }
+
+    fn parse_keyword(&mut self, keyword: &str, value: Object) -> Result<Object, RuntimeError> {
+        match self.bytes[self.position..].starts_with(keyword.as_bytes()) {
+            true => {
+                self.position += keyword.len();
+                Ok(value)
+            }
+            false => Err(self.error("expected a value")),
+        }
+    }
+
+    fn parse_object(&mut self) -> Result<Object, RuntimeError> {
+        let mut pairs = OrderedHash::new();
+
+        self.expect(b'{')?;
+        self.skip_white_space();
+
+        if self.peek() == Some(b'}') {
+            self.position += 1;
+            return Ok(Object::Hash(pairs));
+        }
+
+        loop {
+            self.skip_white_space();
+
+            if self.peek() != Some(b'"') {
+                return Err(self.error("expected a string key"));
+            }
+
+            let key = self.parse_string()?;
+
+            self.skip_white_space();
+            self.expect(b':')?;
+            self.skip_white_space();
+
+            let value = self.parse_value()?;
+            // a repeated key keeps the last value like most parsers
+            pairs.insert(Object::String(key), value);
+
+            self.skip_white_space();
+
+            match self.peek() {
+                Some(b',') => self.position += 1,
+                Some(b'}') => {
+                    self.position += 1;
+                    return Ok(Object::Hash(pairs));
+                }
+                _ => return Err(self.error("expected ',' or '}'")),
+            }
+        }
+    }
+
+    fn parse_array(&mut self) -> Result<Object, RuntimeError> {
+        let mut items = vec![];
+
+        self.expect(b'[')?;
+        self.skip_white_space();
+
+        if self.peek() == Some(b']') {
+            self.position += 1;
+            return Ok(Object::Array(items));
+        }
+
+        loop {
+            self.skip_white_space();
+            items.push(self.parse_value()?);
+            self.skip_white_space();
+
+            match self.peek() {
+                Some(b',') => self.position += 1,
+                Some(b']') => {
+                    self.position += 1;
+                    return Ok(Object::Array(items));
+                }
+                _ => return Err(self.error("expected ',' or ']'")),
+            }
+        }
+    }
+
+    fn parse_string(&mut self) -> Result<String, RuntimeError> {
+        let mut string = String::new();
+
+        self.expect(b'"')?;
+
+        loop {
+            let start = self.position;
+
+            // copy the run up to the next quote, escape or control char at once
+            while let Some(byte) = self.peek() {
+                if byte == b'"' || byte == b'\\' || byte < 0x20 {
+                    break;
+                }
+
+                self.position += 1;
+            }
+
+            string.push_str(&self.text[start..self.position]);
+
+            match self.peek() {
+                Some(b'"') => {
+                    self.position += 1;
+                    return Ok(string);
+                }
+                Some(b'\\') => {
+                    self.position += 1;
+                    string.push(self.parse_escape()?);
+                }
+                Some(_) => return Err(self.error("control character in string")),
+                None => return Err(self.error("unterminated string")),
+            }
+        }
+    }
+
+    fn parse_escape(&mut self) -> Result<char, RuntimeError> {
+        let escaped = match self.peek() {
+            Some(b'"') => '"',
+            Some(b'\\') => '\\',
+            Some(b'/') => '/',
+            Some(b'b') => '\u{8}',
+            Some(b'f') => '\u{c}',
+            Some(b'n') => '\n',
+            Some(b'r') => '\r',
+            Some(b't') => '\t',
+            Some(b'u') => {
+                self.position += 1;
+                return self.parse_unicode_escape();
+            }
+            _ => return Err(self.error("invalid escape")),
+        };
+
+        self.position += 1;
+
+        Ok(escaped)
+    }
+
+    // `\u` and four hex digits, chars outside the basic plane come as a surrogate pair
+    fn parse_unicode_escape(&mut self) -> Result<char, RuntimeError> {
+        let first = self.parse_hex()?;
+
+        let code = match first {
+            0xd800..=0xdbff => {
+                if !self.bytes[self.position..].starts_with(b"\\u") {
+                    return Err(self.error("unpaired surrogate"));
+                }
+
+                self.position += 2;
+                let second = self.parse_hex()?;
+
+                if !(0xdc00..=0xdfff).contains(&second) {
+                    return Err(self.error("unpaired surrogate"));
+                }
+
+                0x10000 + ((first - 0xd800) << 10) + (second - 0xdc00)
+            }
+            0xdc00..=0xdfff => return Err(self.error("unpaired surrogate")),
+            code => code,
+        };
+
+        char::from_u32(code).ok_or_else(|| self.error("invalid unicode escape"))
+    }
+
+    fn parse_hex(&mut self) -> Result<u32, RuntimeError> {
+        let digits = self
+            .text
+            .get(self.position..self.position + 4)
+            .filter(|digits| digits.bytes().all(|byte| byte.is_ascii_hexdigit()))
+            .ok_or_else(|| self.error("expected four hex digits"))?;
+
+        self.position += 4;
+
+        Ok(u32::from_str_radix(digits, 16).unwrap())
+    }
+
+    fn parse_number(&mut self) -> Result<Object, RuntimeError> {
+        let start = self.position;
+        let mut whole = true;
+
+        if self.peek() == Some(b'-') {
+            self.position += 1;
+        }
+
+        match self.peek() {
+            Some(b'0') => self.position += 1,
+            Some(b'1'..=b'9') => self.skip_digits(),
+            _ => return Err(self.error("expected a digit")),
+        }
+
+        if self.peek() == Some(b'.') {
+            whole = false;
+            self.position += 1;
+
+            if !matches!(self.peek(), Some(b'0'..=b'9')) {
+                return Err(self.error("expected a digit"));
+            }
+
+            self.skip_digits();
+        }
+
+        if let Some(b'e' | b'E') = self.peek() {
+            whole = false;
+            self.position += 1;
+
+            if let Some(b'+' | b'-') = self.peek() {
+                self.position += 1;
+            }
+
+            if !matches!(self.peek(), Some(b'0'..=b'9')) {
+                return Err(self.error("expected a digit"));
+            }
+
+            self.skip_digits();
+        }
+
+        let number = &self.text[start..self.position];
+
+        // huge integers do not fit an integer
+        match (whole, number.parse::<i64>()) {
+            (true, Ok(int)) => Ok(Object::Integer(int)),
+            _ => Ok(Object::Float(number.parse().unwrap())),
+        }
+    }
+
+    fn skip_digits(&mut self) {
+        while let Some(b'0'..=b'9') = self.peek() {
+            self.position += 1;
+        }
+    }
+}
+
+fn write_value(
+    value: &Object,
+    indent: usize,
+    level: usize,
+    path: &mut String,
+    output: &mut String,
+) -> Result<(), RuntimeError> {
+    match value {
+        Object::Null => output.push_str("null"),
+        Object::Boolean(bl) => write!(output, "{}", bl).unwrap(),
+        Object::Integer(int) => write!(output, "{}", int).unwrap(),
+        Object::Float(float) if float.is_finite() => write!(output, "{:?}", float).unwrap(),
+        Object::Float(float) => {
+            return Err(RuntimeError::new(format!(
+                "can not convert {:?} to json at {}",
+                float, path
+            )));
+        }
+        Object::String(string) => write_string(string, output),
+        Object::Array(items) => {
+            output.push('[');
+
+            for (index, item) in items.iter().enumerate() {
+                if index > 0 {
+                    output.push(',');
+                }
+
+                write_line_break(indent, level + 1, output);
+
+                let length = path.len();
+                write!(path, "[{}]", index).unwrap();
+                write_value(item, indent, level + 1, path, output)?;
+                path.truncate(length);
+            }
+
+            if !items.is_empty() {
+                write_line_break(indent, level, output);
+            }
+
+            output.push(']');
+        }
+        Object::Hash(pairs) => {
+            output.push('{');
+
+            for (index, (key, value)) in pairs.iter().enumerate() {
+                let key = match key {
+                    Object::String(key) => key,
+                    other => {
+                        return Err(RuntimeError::new(format!(
+                            "can not convert hash key {} to json at {}, keys have to be strings",
+                            other.repr(),
+                            path
+                        )));
+                    }
+                };
+
+                if index > 0 {
+                    output.push(',');
+                }
+
+                write_line_break(indent, level + 1, output);
+                write_string(key, output);
+                output.push_str(if indent > 0 { ": " } else { ":" });
+
+                let length = path.len();
+                write!(path, ".{}", key).unwrap();
+                write_value(value, indent, level + 1, path, output)?;
+                path.truncate(length);
+            }
+
+            if !pairs.is_empty() {
+                write_line_break(indent, level, output);
+            }
+
+            output.push('}');
+        }
+        other => {
+            return Err(RuntimeError::new(format!(
+                "can not convert {} to json at {}",
+                other.type_name(),
+                path
+            )));
+        }
+    }
+
+    Ok(())
+}
+
+fn write_line_break(indent: usize, level: usize, output: &mut String) {
+    if indent > 0 {
+        output.push('\n');
+        output.extend(std::iter::repeat(' ').take(indent * level));
+    }
+}
+
+fn write_string(string: &str, output: &mut String) {
+    output.push('"');
+
+    for char in string.chars() {
+        match char {
+            '"' => output.push_str("\\\""),
+            '\\' => output.push_str("\\\\"),
+            '\n' => output.push_str("\\n"),
+            '\r' => output.push_str("\\r"),
+            '\t' => output.push_str("\\t"),
+            char if (char as u32) < 0x20 => write!(output, "\\u{:04x}", char as u32).unwrap(),
+            char => output.push(char),
+        }
+    }
+
+    output.push('"');
+}
+
+#[cfg(test)]
+mod unit_test {
+    use crate::json::{parse, stringify};
+    use crate::object::Object;
+
+    #[test]
+    fn parse_values() {
+        let value = parse(r#" {"name": "lynx\nä😀", "tags": [1, -2, 2.5e1, true, null], "b": {}} "#).unwrap();
+
+        assert_eq!(
+            value.to_string(),
+            "{\"name\": \"lynx\\n\u{e4}\u{1f600}\", \"tags\": [1, -2, 25.0, true, null], \"b\": {}}"
+        );
+        assert_eq!(
+            parse("9223372036854775808").unwrap(),
+            Object::Float(9223372036854775808.0)
+        );
+    }
+
+    #[test]
+    fn parse_errors() {
+        let cases = [
+            ("", "invalid json at byte 0: unexpected end of input"),
+            ("[1, 2", "invalid json at byte 5: expected ',' or ']'"),
+            (r#"{"a" 1}"#, "invalid json at byte 5: expected ':'"),
+            ("[01]", "invalid json at byte 2: expected ',' or ']'"),
+            (r#""\x""#, "invalid json at byte 2: invalid escape"),
+            (r#""\ud83d""#, "invalid json at byte 7: unpaired surrogate"),
+            ("1 2", "invalid json at byte 2: unexpected text after the value"),
+            ("nul", "invalid json at byte 0: expected a value"),
+        ];
+
+        for (input, expected) in cases {
+            assert_eq!(parse(input).unwrap_err().message, expected, "{}", input);
+        }
+
+        let deep = "[".repeat(600);
+        assert_eq!(
+            parse(&deep).unwrap_err().message,
+            "invalid json at byte 512: nested too deep"
+        );
+    }
+
+    #[test]
+    fn stringify_values() {
+        let value = parse(r#"{"a": [1, 2.5, "x\"y"], "b": {}, "c": null}"#).unwrap();
+
+        assert_eq!(stringify(&value, 0).unwrap(), r#"{"a":[1,2.5,"x\"y"],"b":{},"c":null}"#);
+        assert_eq!(
+            stringify(&value, 2).unwrap(),
+            "{\n  \"a\": [\n    1,\n    2.5,\n    \"x\\\"y\"\n  ],\n  \"b\": {},\n  \"c\": null\n}"
+        );
+        assert_eq!(parse(&stringify(&value, 4).unwrap()).unwrap(), value);
+    }
+}
@@ -27,10 +27,8 @@ impl<'a> Lexer<'a> {
         self.current_pos.unwrap()
     }
 
-    pub fn is_end(&self) -> Option<bool> {
-        let pos = self.current_pos.unwrap();
-
-        Some(pos >= self.len - 1)
+    pub fn is_end(&mut self) -> Option<bool> {
+        Some(self.input.peek().is_none())
     }
 
     pub fn is_peekable(&mut self) -> bool {
@@ -38,15 +36,17 @@ impl<'a> Lexer<'a> {
     }
 
     pub fn skip_white_space(&mut self) {
-        if let Some((_, c)) = self.peek_char() {
-            if is_white_space(*c) {
+        while let Some(&(_, c)) = self.peek_char() {
+            if is_white_space(c) {
                 self.next_char();
+            } else {
+                break;
             }
         }
     }
 
     pub fn next_char(&mut self) -> Option<(usize, char)> {
-        let (pos, c) = self.input.next().unwrap();
+        let (pos, c) = self.input.next()?;
         self.current_pos = Some(pos);
 
         // println!("debug char {:?}", c);
@@ -58,6 +58,17 @@ impl<'a> Lexer<'a> {
         self.input.peek()
     }
 
+    // consume the next char only when it is `expected`, e.g. the `=` in `==`
+    fn next_char_if(&mut self, expected: char) -> bool {
+        match self.input.next_if(|&(_, c)| c == expected) {
+            Some((pos, _)) => {
+                self.current_pos = Some(pos);
+                true
+            }
+            None => false,
+        }
+    }
+
     pub fn read_identifier(&mut self, c: char) -> Vec<char> {
         let mut identifier = vec![];
 
@@ -137,11 +148,13 @@ impl<'a> Lexer<'a> {
     }
 
     pub fn read_number(&mut self, c: char) -> Option<TokenType> {
-        let mut chars = vec![];
-        if c.is_numeric() {
-            chars.push(c);
+        // the digits after an opening quote belong to the string
+        if !c.is_numeric() {
+            return None;
         }
 
+        let mut chars = vec![c];
+
         while let Some(&(_, p)) = self.peek_char() {
             if p.is_numeric() {
                 chars.push(p);
@@ -151,23 +164,37 @@ impl<'a> Lexer<'a> {
             }
         }
 
-        let is_all_number = chars.clone().into_iter().all(|c| c.is_numeric());
+        // `1.5` is a float, the dot of `1.abs()` starts a method call
+        let mut after_dot = self.input.clone().skip(1);
 
-        if is_all_number && !chars.is_empty() {
-            let num_str = String::from_iter(chars.into_iter());
-            Some(TokenType::NUMBER(num_str.parse::<usize>().unwrap()))
-        } else {
-            None
+        if self.peek_char().map(|&(_, p)| p) == Some('.') && after_dot.next().map_or(false, |(_, p)| p.is_numeric()) {
+            chars.push('.');
+            self.next_char();
+
+            while let Some(&(_, p)) = self.peek_char() {
+                if p.is_numeric() {
+                    chars.push(p);
+                    self.next_char();
+                } else {
+                    break;
+                }
+            }
+
+            return Some(TokenType::FLOAT(String::from_iter(chars.into_iter())));
+        }
+
+        let num_str = String::from_iter(chars.into_iter());
+
+        // like in json, an integer that does not fit 64 bits is a float
+        match num_str.parse::<i64>() {
+            Ok(int) => Some(TokenType::NUMBER(int)),
+            Err(_) => Some(TokenType::FLOAT(num_str)),
         }
     }
 
     pub fn next_token(&mut self) -> TokenType {
         self.skip_white_space();
 
-        if self.is_end().unwrap() {
-            return TokenType::EOF;
-        }
-
         if let Some((_, c)) = self.next_char() {
             // println!("102 {:?}", c);
 
@@ -187,63 +214,45 @@ impl<'a> Lexer<'a> {
                 '*' => TokenType::MULTIPLY,
                 '/' => TokenType::DIVIDE,
                 '!' => {
-                    // reference whole tuple
-                    let &(_, eq) = self.peek_char().unwrap();
-
-                    if eq == '=' {
-                        self.next_char();
+                    if self.next_char_if('=') {
                         TokenType::BANG_EQUAL
                     } else {
                         TokenType::BANG
                     }
                 }
                 '=' => {
-                    let (_, eq) = self.peek_char().unwrap();
-
-                    // or dereference eq
-                    if *eq == '=' {
-                        self.next_char();
+                    if self.next_char_if('=') {
                         TokenType::EQUAL_EQUAL
                     } else {
                         TokenType::ASSIGN
                     }
                 }
                 '>' => {
-                    let &(_, eq) = self.peek_char().unwrap();
-
-                    if eq == '=' {
-                        self.next_char();
+                    if self.next_char_if('=') {
                         TokenType::GREATER_EQUAL
                     } else {
                         TokenType::GREATER
                     }
                 }
                 '<' => {
-                    let &(_, eq) = self.peek_char().unwrap();
-
-                    if eq == '=' {
-                        self.next_char();
+                    if self.next_char_if('=') {
                         TokenType::LESS_EQUAL
                     } else {
                         TokenType::LESS
                     }
                 }
                 _ => {
-                    let numbers = self.read_number(c);
-                    let strings = self.read_string(c);
-
-                    if numbers.is_some() {
-                        numbers.unwrap()
-                    } else if strings.is_some() {
-                        strings.unwrap()
+                    if let Some(number) = self.read_number(c) {
+                        number
+                    } else if let Some(string) = self.read_string(c) {
+                        string
                     } else {
                         self.read_keyword(c)
                     }
                 }
             }
         } else {
-            // TokenType::NONE
-            self.next_token()
+            TokenType::EOF
         }
     }
 }
@@ -303,6 +312,37 @@ mod unit_test {
         assert_eq!(expected, parsed);
     }
 
+    #[test]
+    fn tokenize_string_with_digits() {
+        assert_eq!(
+            get_tokens(r#"f("42", "a1") "#),
+            vec![
+                TokenType::IDENTIFIER(String::from("f")),
+                TokenType::LEFT_PAREN,
+                TokenType::STRING(String::from("42")),
+                TokenType::COMMA,
+                TokenType::STRING(String::from("a1")),
+                TokenType::RIGHT_PAREN,
+            ]
+        );
+    }
+
+    #[test]
+    fn tokenize_float() {
+        assert_eq!(
+            get_tokens(" 1.25 + 2.abs() "),
+            vec![
+                TokenType::FLOAT(String::from("1.25")),
+                TokenType::ADD,
+                TokenType::NUMBER(2),
+                TokenType::DOT,
+                TokenType::IDENTIFIER(String::from("abs")),
+                TokenType::LEFT_PAREN,
+                TokenType::RIGHT_PAREN,
+            ]
+        );
+    }
+
     #[test]
     fn tokenize_hash_type() {
         let parsed = get_tokens(
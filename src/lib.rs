@@ -66,6 +66,97 @@ std::ops::Deref;
 ```
 
 
+## compiler and vm
+
+The compiler lowers the ast into bytecode, one byte per opcode with a constant pool per function.
+The vm runs it on a value stack with call frames, locals live in fixed slots of the frame and
+closures capture them through upvalues.
+
+```rust
+use lynxlang::{compiler::Compiler, parser::Parser, vm::Vm};
+
+let program = Parser::get("fn add(x, y) { x + y } add(1, 2);").parse_program();
+let bytecode = Compiler::new().compile(program).unwrap();
+
+Vm::new().run(&bytecode);
+```
+
+## host functions
+
+Functions registered with `register_fn` are callable by programs like builtins. Rust functions of
+plain types get their arguments converted and checked through `FromLynx` and `IntoLynx`, a closure
+over the raw arguments can hold host state and fail with a `RuntimeError`.
+`Evaluator::call_function` calls a function of the program back, like `map` does with its callback.
+
+```rust
+use lynxlang::{env::Env, evaluator::Evaluator, object::{Object, RuntimeError}, parser::Parser};
+use std::{cell::RefCell, rc::Rc};
+
+let mut evaluator = Evaluator::new(Rc::new(RefCell::new(Env::new())));
+
+evaluator.register_fn("double", |int: u32| int * 2);
+evaluator.register_fn("first_arg", |_: &mut Evaluator, arguments: &[Object]| match arguments.first() {
+    Some(argument) => Ok(argument.clone()),
+    None => Err(RuntimeError::new("first_arg takes an argument")),
+});
+
+let value = evaluator.eval_program(Parser::get("first_arg(double(21));").parse_program());
+assert_eq!(value, Some(Object::Integer(42)));
+```
+
+## optimize
+
+`optimize` rewrites a program before it runs: constant expressions are folded, branches with a
+constant condition are pruned and `let` bindings of a literal are inlined. Each pass can be
+switched off on its own.
+
+```rust
+use lynxlang::{optimize::{optimize, Passes}, parser::Parser};
+
+let program = Parser::get("let width = 10; width * 2 + 1;").parse_program();
+let passes = Passes { inline_constants: false, ..Passes::default() };
+
+optimize(program, passes);
+```
+
+## serde
+
+With the `serde` feature `Object` implements `Serialize` and `Deserialize`, `to_object` and
+`from_object` hand host values to a program and back. Functions and native objects can not be
+converted and are an error.
+
+```rust
+# #[cfg(feature = "serde")] {
+use lynxlang::{from_object, to_object};
+use std::collections::BTreeMap;
+
+let mut limits = BTreeMap::new();
+limits.insert("depth", vec![1, 2]);
+
+let object = to_object(&limits).unwrap();
+let limits: BTreeMap<String, Vec<u8>> = from_object(&object).unwrap();
+assert_eq!(limits["depth"], vec![1, 2]);
+# }
+```
+
+## tracing
+
+The parser and the evaluator are silent unless a `Tracer` is set, then they report token, parse and
+eval events up to the level of the tracer. `StderrTracer` writes them to stderr, a closure works too.
+
+```rust
+use lynxlang::{env::Env, evaluator::Evaluator, parser::Parser, trace::{Level, StderrTracer}};
+use std::{cell::RefCell, rc::Rc};
+
+let tracer = Rc::new(StderrTracer::new(Level::Debug));
+let mut parser = Parser::get("1 + 2;");
+parser.set_tracer(tracer.clone());
+
+let mut evaluator = Evaluator::new(Rc::new(RefCell::new(Env::new())));
+evaluator.set_tracer(tracer);
+evaluator.eval_program(parser.parse_program());
+```
+
 ## repl
 
 - <https://arzg.github.io/lang/7/>
@@ -82,10 +173,26 @@ std::ops::Deref;
 
 pub mod ast;
 pub mod builtin;
+pub mod compiler;
+pub mod convert;
 pub mod env;
 pub mod evaluator;
+pub mod heap;
+pub mod interrupt;
+pub mod json;
 pub mod lexer;
 pub mod object;
+pub mod optimize;
+pub mod ordered_hash;
 pub mod parser;
+pub mod random;
+pub mod resolver;
+#[cfg(feature = "serde")]
+pub mod serialize;
 pub mod token;
+pub mod trace;
 pub mod util;
+pub mod vm;
+
+#[cfg(feature = "serde")]
+pub use serialize::{from_object, to_object};
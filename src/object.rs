@@ -1,41 +1,110 @@
 #![warn(clippy::derive_hash_xor_eq)]
 
 use crate::ast::*;
+use std::any::Any;
 use std::cell::RefCell;
-use std::collections::HashMap;
+use std::collections::hash_map::DefaultHasher;
+use std::fmt;
 use std::hash::Hash;
 use std::hash::Hasher;
 use std::rc::Rc;
 
+use crate::compiler::CompiledFunction;
 use crate::env::Env;
+use crate::evaluator::Evaluator;
+use crate::ordered_hash::OrderedHash;
+use crate::vm::Closure;
 
+// a function provided by the host, it gets the evaluator running the program as context
 // https://stackoverflow.com/questions/64298245/in-rust-what-is-fn
-pub type FuncType = fn(Vec<Object>) -> Object;
+pub type NativeFn = dyn Fn(&mut Evaluator, &[Object]) -> Result<Object, RuntimeError>;
 
-#[derive(PartialEq, Debug, Clone)]
+#[derive(Debug, Clone)]
 pub enum Object {
-    Integer(usize),
+    Integer(i64),
+    Float(f64),
     Boolean(bool),
     String(String),
     Array(Vec<Object>),
-    Hash(HashMap<Object, Object>),
+    Hash(OrderedHash),
     Null,
     ReturnValue(Rc<Object>),
-    Function(Vec<Expression>, Statement, Rc<RefCell<Env>>),
-    Builtin(FuncType),
+    // a call in tail position, the caller runs it after its own frame is gone
+    TailCall(String, Box<Object>, Vec<Object>),
+    // the name of a declaration, empty for a function literal
+    Function(String, Vec<Expression>, Statement, Rc<RefCell<Env>>),
+    Builtin(BuiltinFunction),
     Break,
     Error(String),
+    CompiledFunction(Rc<CompiledFunction>),
+    Closure(Rc<Closure>),
+    Native(NativeObject),
+}
+
+// Values compare by contents, functions by identity. A function holds on to the scope it was
+// created in and that scope usually holds the function, comparing them field by field never ends.
+impl PartialEq for Object {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Object::Integer(int), Object::Integer(other)) => int == other,
+            (Object::Float(float), Object::Float(other)) => float == other,
+            (Object::Boolean(bl), Object::Boolean(other)) => bl == other,
+            (Object::String(str), Object::String(other)) => str == other,
+            (Object::Array(items), Object::Array(other)) => items == other,
+            (Object::Hash(pairs), Object::Hash(other)) => pairs == other,
+            (Object::Null, Object::Null) | (Object::Break, Object::Break) => true,
+            (Object::ReturnValue(value), Object::ReturnValue(other)) => value == other,
+            (Object::TailCall(name, function, arguments), Object::TailCall(other_name, other, other_arguments)) => {
+                name == other_name && function == other && arguments == other_arguments
+            }
+            // the same literal evaluated in the same scope
+            (
+                Object::Function(name, parameters, body, env),
+                Object::Function(other_name, other_parameters, other_body, other_env),
+            ) => {
+                Rc::ptr_eq(env, other_env) && name == other_name && parameters == other_parameters && body == other_body
+            }
+            (Object::Builtin(builtin), Object::Builtin(other)) => builtin == other,
+            (Object::Error(message), Object::Error(other)) => message == other,
+            (Object::CompiledFunction(function), Object::CompiledFunction(other)) => function == other,
+            (Object::Closure(closure), Object::Closure(other)) => closure == other,
+            (Object::Native(native), Object::Native(other)) => native == other,
+            _ => false,
+        }
+    }
 }
 
 impl Eq for Object {}
 
 // https://doc.rust-lang.org/std/hash/trait.Hash.html
+// still consistent with `PartialEq`, arrays and hashes hash their contents and the
+// values `is_hashable` rejects never end up as keys
+#[allow(clippy::derive_hash_xor_eq)]
 impl Hash for Object {
     fn hash<H: Hasher>(&self, state: &mut H) {
         match self {
             Object::Integer(int) => int.hash(state),
+            // `0.0 == -0.0` but their bits differ
+            Object::Float(float) if *float == 0.0 => 0u64.hash(state),
+            Object::Float(float) => float.to_bits().hash(state),
             Object::String(str) => str.hash(state),
             Object::Boolean(bl) => bl.hash(state),
+            Object::Array(items) => items.hash(state),
+            // equal hashes can differ in order, the pair hashes are combined in a way that ignores it
+            Object::Hash(pairs) => {
+                let combined = pairs
+                    .iter()
+                    .map(|pair| {
+                        let mut hasher = DefaultHasher::new();
+                        pair.hash(&mut hasher);
+                        hasher.finish()
+                    })
+                    .fold(0u64, u64::wrapping_add);
+
+                pairs.len().hash(state);
+                combined.hash(state);
+            }
+            Object::Native(native) => native.address().hash(state),
             _ => "".hash(state),
         }
     }
@@ -49,6 +118,243 @@ impl Object {
             _ => true,
         }
     }
+
+    // whether the value can be a hash key, functions have no value to compare by
+    pub fn is_hashable(&self) -> bool {
+        match self {
+            Object::Integer(_) | Object::Boolean(_) | Object::String(_) | Object::Null | Object::Native(_) => true,
+            // not a number is not equal to itself and could never be found again
+            Object::Float(float) => !float.is_nan(),
+            Object::Array(items) => items.iter().all(Object::is_hashable),
+            Object::Hash(pairs) => pairs.values().all(Object::is_hashable),
+            _ => false,
+        }
+    }
+
+    // the error for a key `is_hashable` rejects, names the value inside an array or hash that
+    // makes it unhashable
+    pub fn unhashable_error(&self) -> String {
+        let inner = match self {
+            Object::Array(items) => items.iter().find(|item| !item.is_hashable()),
+            Object::Hash(pairs) => pairs.values().find(|value| !value.is_hashable()),
+            _ => None,
+        };
+
+        match inner {
+            Some(inner) => inner.unhashable_error(),
+            None => format!("unhashable hash key: {}", self.type_name()),
+        }
+    }
+
+    pub fn type_name(&self) -> &str {
+        match self {
+            Object::Integer(_) => "integer",
+            Object::Float(_) => "float",
+            Object::Boolean(_) => "boolean",
+            Object::String(_) => "string",
+            Object::Array(_) => "array",
+            Object::Hash(_) => "hash",
+            Object::Null => "null",
+            Object::Function(..) | Object::Builtin(_) | Object::CompiledFunction(_) | Object::Closure(_) => "function",
+            Object::Error(_) => "error",
+            Object::Native(native) => &native.type_name,
+            Object::ReturnValue(_) | Object::TailCall(..) | Object::Break => "internal",
+        }
+    }
+
+    // bytes the value takes including its contents, a function does not count the scope it
+    // holds on to, the heap counts every scope once
+    pub fn allocated_size(&self) -> usize {
+        let contents = match self {
+            Object::String(string) => string.capacity(),
+            Object::Array(items) => items.iter().map(Object::allocated_size).sum(),
+            Object::Hash(pairs) => pairs
+                .iter()
+                .map(|(key, value)| key.allocated_size() + value.allocated_size())
+                .sum(),
+            Object::ReturnValue(value) => value.allocated_size(),
+            Object::Error(message) => message.capacity(),
+            _ => 0,
+        };
+
+        std::mem::size_of::<Object>() + contents
+    }
+
+    // the value in literal syntax, strings are quoted, `Display` only quotes the nested ones
+    pub fn repr(&self) -> String {
+        match self {
+            Object::String(string) => format!("{:?}", string),
+            other => other.to_string(),
+        }
+    }
+}
+
+impl fmt::Display for Object {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Object::Integer(int) => write!(f, "{}", int),
+            // with the decimal point, `2.0` is not the integer `2`
+            Object::Float(float) => write!(f, "{:?}", float),
+            Object::Boolean(bl) => write!(f, "{}", bl),
+            Object::String(string) => write!(f, "{}", string),
+            Object::Array(items) => {
+                let items = items.iter().map(Object::repr).collect::<Vec<_>>();
+
+                write!(f, "[{}]", items.join(", "))
+            }
+            Object::Hash(pairs) => {
+                let pairs = pairs
+                    .iter()
+                    .map(|(key, value)| format!("{}: {}", key.repr(), value.repr()))
+                    .collect::<Vec<_>>();
+
+                write!(f, "{{{}}}", pairs.join(", "))
+            }
+            Object::Null => write!(f, "null"),
+            Object::ReturnValue(value) => write!(f, "{}", value),
+            Object::TailCall(name, ..) => write!(f, "<tail call {}>", name),
+            Object::Function(_, parameters, ..) => {
+                let parameters = parameters
+                    .iter()
+                    .map(|parameter| match parameter {
+                        Expression::Identifier(name) | Expression::Local(name, ..) => name.clone(),
+                        other => format!("{:?}", other),
+                    })
+                    .collect::<Vec<_>>();
+
+                write!(f, "fn({}) {{...}}", parameters.join(", "))
+            }
+            Object::CompiledFunction(function) => write!(f, "fn({}) {{...}}", function.parameters.join(", ")),
+            Object::Closure(closure) => write!(f, "fn({}) {{...}}", closure.function.parameters.join(", ")),
+            Object::Builtin(builtin) => write!(f, "<builtin {}>", builtin.name),
+            Object::Break => write!(f, "break"),
+            Object::Error(message) => write!(f, "error: {}", message),
+            Object::Native(native) => write!(f, "<{}>", native.type_name),
+        }
+    }
+}
+
+// A rust value of the host, programs can hold and pass it around but only look into it through
+// the methods the host registered for its type name. Two natives are equal when they are the
+// same value.
+#[derive(Clone)]
+pub struct NativeObject {
+    pub type_name: String,
+    pub value: Rc<dyn Any>,
+}
+
+impl NativeObject {
+    pub fn new<T: Any>(type_name: &str, value: T) -> Self {
+        NativeObject {
+            type_name: type_name.to_string(),
+            value: Rc::new(value),
+        }
+    }
+
+    pub fn downcast_ref<T: Any>(&self) -> Option<&T> {
+        self.value.downcast_ref::<T>()
+    }
+
+    fn address(&self) -> usize {
+        Rc::as_ptr(&self.value).cast::<()>() as usize
+    }
+}
+
+impl PartialEq for NativeObject {
+    fn eq(&self, other: &Self) -> bool {
+        self.address() == other.address()
+    }
+}
+
+impl fmt::Debug for NativeObject {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("NativeObject").field(&self.type_name).finish()
+    }
+}
+
+// the error a builtin fails with, it becomes an `Object::Error` in the program
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RuntimeError {
+    pub message: String,
+}
+
+impl RuntimeError {
+    pub fn new(message: impl Into<String>) -> Self {
+        RuntimeError {
+            message: message.into(),
+        }
+    }
+}
+
+impl fmt::Display for RuntimeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl From<String> for RuntimeError {
+    fn from(message: String) -> Self {
+        RuntimeError { message }
+    }
+}
+
+impl From<&str> for RuntimeError {
+    fn from(message: &str) -> Self {
+        RuntimeError::new(message)
+    }
+}
+
+#[derive(Clone)]
+pub struct BuiltinFunction {
+    pub name: String,
+    // number of arguments it takes, `None` for any number
+    pub arity: Option<usize>,
+    pub func: Rc<NativeFn>,
+}
+
+impl BuiltinFunction {
+    pub fn new<F>(name: &str, arity: Option<usize>, func: F) -> Self
+    where
+        F: Fn(&mut Evaluator, &[Object]) -> Result<Object, RuntimeError> + 'static,
+    {
+        BuiltinFunction {
+            name: name.to_string(),
+            arity,
+            func: Rc::new(func),
+        }
+    }
+
+    pub fn call(&self, evaluator: &mut Evaluator, arguments: &[Object]) -> Object {
+        match self.arity {
+            Some(arity) if arity != arguments.len() => Object::Error(format!(
+                "wrong number of arguments for {}: expected {}, got {}",
+                self.name,
+                arity,
+                arguments.len()
+            )),
+            _ => match (self.func)(evaluator, arguments) {
+                Ok(value) => value,
+                Err(error) => Object::Error(error.message),
+            },
+        }
+    }
+}
+
+// the same function when it is the same closure
+impl PartialEq for BuiltinFunction {
+    fn eq(&self, other: &Self) -> bool {
+        self.name == other.name
+            && std::ptr::eq(
+                Rc::as_ptr(&self.func).cast::<()>(),
+                Rc::as_ptr(&other.func).cast::<()>(),
+            )
+    }
+}
+
+impl fmt::Debug for BuiltinFunction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("BuiltinFunction").field(&self.name).finish()
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -0,0 +1,499 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::evaluator::{eval_infix_objects, eval_prefix_object};
+use crate::{ast::*, object::Object};
+
+// the rewrites `optimize` applies, all of them by default
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Passes {
+    // `1 + 2 * 3` becomes `7`, `!true` becomes `false`
+    pub fold_constants: bool,
+    // `if (true) { a } else { b }` keeps `a` only, `while (false) { .. }` loses its body
+    pub prune_branches: bool,
+    // `let width = 10;` replaces the later uses of `width` with `10`
+    pub inline_constants: bool,
+}
+
+impl Default for Passes {
+    fn default() -> Self {
+        Passes {
+            fold_constants: true,
+            prune_branches: true,
+            inline_constants: true,
+        }
+    }
+}
+
+impl Passes {
+    pub fn none() -> Self {
+        Passes {
+            fold_constants: false,
+            prune_branches: false,
+            inline_constants: false,
+        }
+    }
+}
+
+// Rewrite a program before evaluation, the optimized program gives the same result.
+//
+// Operations that fail at runtime, like a division by zero, are left in place so the error stays
+// the same. A `let` is only inlined when its value is a literal, its name is declared once in the
+// whole program and never assigned. The program is optimized on its own, its globals must not be
+// assigned by functions of programs evaluated before. Names in pruned branches are not resolved
+// anymore, so the resolver does not report them.
+pub fn optimize(program: AstNode, passes: Passes) -> AstNode {
+    let AstNode::Program(statements) = program;
+
+    let mut declarations = HashMap::new();
+    let mut assigned = HashSet::new();
+
+    statements
+        .iter()
+        .for_each(|statement| collect_statement(statement, &mut declarations, &mut assigned));
+
+    let optimizer = Optimizer {
+        passes,
+        inlinable: declarations
+            .into_iter()
+            .filter(|(name, count)| *count == 1 && !assigned.contains(name))
+            .map(|(name, _)| name)
+            .collect(),
+    };
+
+    AstNode::Program(optimizer.optimize_statements(statements, &HashMap::new()))
+}
+
+struct Optimizer {
+    passes: Passes,
+    // names that may be inlined once their `let` is seen
+    inlinable: HashSet<String>,
+}
+
+// literal values of the constants in scope
+type Constants = HashMap<String, Expression>;
+
+impl Optimizer {
+    fn optimize_statements(&self, statements: Vec<Statement>, constants: &Constants) -> Vec<Statement> {
+        let mut constants = constants.clone();
+        let mut optimized = vec![];
+        let count = statements.len();
+
+        for (index, statement) in statements.into_iter().enumerate() {
+            let statement = self.optimize_statement(statement, &constants);
+
+            if let Statement::Let(identifier, value) = &statement {
+                if let Expression::Identifier(name) = identifier.as_ref() {
+                    if self.passes.inline_constants && self.inlinable.contains(name) && is_literal(value) {
+                        constants.insert(name.clone(), value.as_ref().clone());
+                    }
+                }
+            }
+
+            // the value of the last statement is the value of the block, it has to stay
+            if self.passes.prune_branches && index + 1 < count && is_dead(&statement) {
+                continue;
+            }
+
+            optimized.push(statement);
+        }
+
+        optimized
+    }
+
+    fn optimize_statement(&self, statement: Statement, constants: &Constants) -> Statement {
+        match statement {
+            Statement::Let(identifier, expr) => {
+                Statement::Let(identifier, Box::new(self.optimize_expression(*expr, constants)))
+            }
+            Statement::Return(expr) => Statement::Return(Box::new(self.optimize_expression(*expr, constants))),
+            Statement::Expr(expr) => Statement::Expr(Box::new(self.optimize_expression(*expr, constants))),
+            Statement::BlockStatement(statements) => {
+                Statement::BlockStatement(self.optimize_statements(statements, constants))
+            }
+        }
+    }
+
+    fn optimize_expression(&self, expr: Expression, constants: &Constants) -> Expression {
+        match expr {
+            Expression::Identifier(name) => match constants.get(&name) {
+                Some(value) => value.clone(),
+                None => Expression::Identifier(name),
+            },
+            Expression::Array(exprs) => Expression::Array(self.optimize_expressions(exprs, constants)),
+            Expression::Hash(hashes) => Expression::Hash(
+                hashes
+                    .into_iter()
+                    .map(|(key, value)| {
+                        (
+                            self.optimize_expression(key, constants),
+                            self.optimize_expression(value, constants),
+                        )
+                    })
+                    .collect(),
+            ),
+            Expression::Prefix(operator, expr) => {
+                let expr = self.optimize_expression(*expr, constants);
+
+                let folded = match literal_object(&expr) {
+                    Some(value) if self.passes.fold_constants => object_literal(eval_prefix_object(&operator, value)),
+                    _ => None,
+                };
+
+                folded.unwrap_or_else(|| Expression::Prefix(operator, Box::new(expr)))
+            }
+            Expression::Infix(left, operator, right) => {
+                let left = self.optimize_expression(*left, constants);
+                let right = self.optimize_expression(*right, constants);
+
+                let folded = match (literal_object(&left), literal_object(&right)) {
+                    (Some(left_obj), Some(right_obj)) if self.passes.fold_constants => {
+                        object_literal(eval_infix_objects(&operator, left_obj, right_obj))
+                    }
+                    _ => None,
+                };
+
+                folded.unwrap_or_else(|| Expression::Infix(Box::new(left), operator, Box::new(right)))
+            }
+            Expression::If(condition, statements, else_statements) => {
+                let condition = self.optimize_expression(*condition, constants);
+                let statements = self.optimize_statement(statements, constants);
+                let else_statements = else_statements.map(|statements| self.optimize_statement(statements, constants));
+
+                match literal_object(&condition) {
+                    // the taken branch keeps its own scope, only the condition is settled
+                    Some(value) if self.passes.prune_branches => match (value.is_truthy(), else_statements) {
+                        (true, _) => Expression::If(Box::new(Expression::Boolean(true)), statements, None),
+                        (false, Some(else_statements)) => {
+                            Expression::If(Box::new(Expression::Boolean(true)), else_statements, None)
+                        }
+                        (false, None) => Expression::If(
+                            Box::new(Expression::Boolean(false)),
+                            Statement::BlockStatement(vec![]),
+                            None,
+                        ),
+                    },
+                    _ => Expression::If(Box::new(condition), statements, else_statements),
+                }
+            }
+            Expression::While(condition, block_stmt) => {
+                let condition = self.optimize_expression(*condition, constants);
+
+                match literal_object(&condition) {
+                    Some(value) if self.passes.prune_branches && !value.is_truthy() => {
+                        Expression::While(Box::new(Expression::Boolean(false)), Statement::BlockStatement(vec![]))
+                    }
+                    _ => Expression::While(Box::new(condition), self.optimize_statement(block_stmt, constants)),
+                }
+            }
+            Expression::Fn(fn_name, parameters, body) => {
+                Expression::Fn(fn_name, parameters, self.optimize_statement(body, constants))
+            }
+            Expression::FnCall(function, arguments) => Expression::FnCall(
+                Box::new(self.optimize_expression(*function, constants)),
+                self.optimize_expressions(arguments, constants),
+            ),
+            Expression::MethodCall(receiver, method, arguments) => Expression::MethodCall(
+                Box::new(self.optimize_expression(*receiver, constants)),
+                method,
+                self.optimize_expressions(arguments, constants),
+            ),
+            Expression::Property(receiver, key) => {
+                Expression::Property(Box::new(self.optimize_expression(*receiver, constants)), key)
+            }
+            Expression::Assign(target, value) => {
+                Expression::Assign(target, Box::new(self.optimize_expression(*value, constants)))
+            }
+            expr => expr,
+        }
+    }
+
+    fn optimize_expressions(&self, exprs: Vec<Expression>, constants: &Constants) -> Vec<Expression> {
+        exprs
+            .into_iter()
+            .map(|expr| self.optimize_expression(expr, constants))
+            .collect()
+    }
+}
+
+fn literal_object(expr: &Expression) -> Option<Object> {
+    match expr {
+        Expression::Integer(int) => Some(Object::Integer(*int)),
+        Expression::Float(float) => Some(Object::Float(*float)),
+        Expression::Boolean(bl) => Some(Object::Boolean(*bl)),
+        Expression::String(string) => Some(Object::String(string.clone())),
+        _ => None,
+    }
+}
+
+fn object_literal(object: Object) -> Option<Expression> {
+    match object {
+        Object::Integer(int) => Some(Expression::Integer(int)),
+        Object::Float(float) => Some(Expression::Float(float)),
+        Object::Boolean(bl) => Some(Expression::Boolean(bl)),
+        Object::String(string) => Some(Expression::String(string)),
+        _ => None,
+    }
+}
+
+fn is_literal(expr: &Expression) -> bool {
+    literal_object(expr).is_some()
+}
+
+// a pruned branch or loop that does nothing
+fn is_dead(statement: &Statement) -> bool {
+    let expr = match statement {
+        Statement::Expr(expr) => expr.as_ref(),
+        _ => return false,
+    };
+
+    match expr {
+        Expression::If(condition, Statement::BlockStatement(statements), None) => {
+            **condition == Expression::Boolean(false) && statements.is_empty()
+        }
+        Expression::While(condition, _) => **condition == Expression::Boolean(false),
+        _ => false,
+    }
+}
+
+// count the declarations of each name and remember the assigned ones
+fn collect_statement(statement: &Statement, declarations: &mut HashMap<String, usize>, assigned: &mut HashSet<String>) {
+    match statement {
+        Statement::Let(identifier, expr) => {
+            collect_declaration(identifier, declarations);
+            collect_expression(expr, declarations, assigned);
+        }
+        Statement::Return(expr) | Statement::Expr(expr) => collect_expression(expr, declarations, assigned),
+        Statement::BlockStatement(statements) => statements
+            .iter()
+            .for_each(|statement| collect_statement(statement, declarations, assigned)),
+    }
+}
+
+fn collect_expression(expr: &Expression, declarations: &mut HashMap<String, usize>, assigned: &mut HashSet<String>) {
+    match expr {
+        Expression::Array(exprs) | Expression::FnCall(_, exprs) | Expression::MethodCall(_, _, exprs) => {
+            if let Expression::FnCall(function, _) | Expression::MethodCall(function, _, _) = expr {
+                collect_expression(function, declarations, assigned);
+            }
+
+            exprs
+                .iter()
+                .for_each(|expr| collect_expression(expr, declarations, assigned));
+        }
+        Expression::Hash(hashes) => hashes.iter().for_each(|(key, value)| {
+            collect_expression(key, declarations, assigned);
+            collect_expression(value, declarations, assigned);
+        }),
+        Expression::Prefix(_, expr) | Expression::Property(expr, _) => collect_expression(expr, declarations, assigned),
+        Expression::Infix(left, _, right) => {
+            collect_expression(left, declarations, assigned);
+            collect_expression(right, declarations, assigned);
+        }
+        Expression::If(condition, statements, else_statements) => {
+            collect_expression(condition, declarations, assigned);
+            collect_statement(statements, declarations, assigned);
+
+            if let Some(else_statements) = else_statements {
+                collect_statement(else_statements, declarations, assigned);
+            }
+        }
+        Expression::While(condition, block_stmt) => {
+            collect_expression(condition, declarations, assigned);
+            collect_statement(block_stmt, declarations, assigned);
+        }
+        Expression::Fn(fn_name, parameters, body) => {
+            collect_declaration(fn_name, declarations);
+            parameters
+                .iter()
+                .for_each(|parameter| collect_declaration(parameter, declarations));
+            collect_statement(body, declarations, assigned);
+        }
+        Expression::Assign(target, value) => {
+            if let Expression::Identifier(name) = target.as_ref() {
+                assigned.insert(name.clone());
+            }
+
+            collect_expression(value, declarations, assigned);
+        }
+        _ => {}
+    }
+}
+
+fn collect_declaration(identifier: &Expression, declarations: &mut HashMap<String, usize>) {
+    if let Expression::Identifier(name) = identifier {
+        if !name.is_empty() {
+            *declarations.entry(name.clone()).or_insert(0) += 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod unit_test {
+    use crate::ast::{AstNode, Expression, Statement};
+    use crate::env::Env;
+    use crate::evaluator::Evaluator;
+    use crate::object::Object;
+    use crate::optimize::{optimize, Passes};
+    use crate::parser::Parser;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    fn eval(program: AstNode) -> Option<Object> {
+        let mut evaluator = Evaluator::new(Rc::new(RefCell::new(Env::new())));
+
+        evaluator.builtin();
+
+        evaluator.eval_program(program)
+    }
+
+    fn optimized_statements(input: &str, passes: Passes) -> Vec<Statement> {
+        let AstNode::Program(statements) = optimize(Parser::get(input).parse_program(), passes);
+
+        statements
+    }
+
+    fn statements(input: &str) -> Vec<Statement> {
+        let AstNode::Program(statements) = Parser::get(input).parse_program();
+
+        statements
+    }
+
+    fn expr(expression: Expression) -> Statement {
+        Statement::Expr(Box::new(expression))
+    }
+
+    #[test]
+    fn fold_constants() {
+        let passes = Passes {
+            fold_constants: true,
+            ..Passes::none()
+        };
+
+        assert_eq!(
+            optimized_statements(r#"1 + 2 * 3; !true; "a" + "b"; 2 < 3;"#, passes),
+            vec![
+                expr(Expression::Integer(7)),
+                expr(Expression::Boolean(false)),
+                expr(Expression::String(String::from("ab"))),
+                expr(Expression::Boolean(true))
+            ]
+        );
+
+        // runtime errors stay where they are
+        assert_eq!(optimized_statements("1 / 0;", passes), statements("1 / 0;"));
+    }
+
+    #[test]
+    fn prune_branches() {
+        let passes = Passes {
+            prune_branches: true,
+            ..Passes::none()
+        };
+
+        assert_eq!(
+            optimized_statements("if (false) { 1 } else { 2 }; while (false) { 3 }; 4;", passes),
+            vec![
+                expr(Expression::If(
+                    Box::new(Expression::Boolean(true)),
+                    Statement::BlockStatement(vec![expr(Expression::Integer(2))]),
+                    None
+                )),
+                expr(Expression::Integer(4))
+            ]
+        );
+    }
+
+    #[test]
+    fn inline_constants() {
+        let passes = Passes {
+            inline_constants: true,
+            ..Passes::none()
+        };
+
+        assert_eq!(
+            optimized_statements("let width = 10; width * 2;", passes),
+            vec![
+                Statement::Let(
+                    Box::new(Expression::Identifier(String::from("width"))),
+                    Box::new(Expression::Integer(10))
+                ),
+                expr(Expression::Infix(
+                    Box::new(Expression::Integer(10)),
+                    crate::token::TokenType::MULTIPLY,
+                    Box::new(Expression::Integer(2))
+                ))
+            ]
+        );
+
+        // assigned or declared twice
+        let input = "let count = 0; count = count + 1; let other = 1; fn foo(other) { other } count;";
+        assert_eq!(optimized_statements(input, passes), statements(input));
+    }
+
+    #[test]
+    fn passes_off_keep_program() {
+        let input = "let debug = false; if (debug) { 1 + 2 }";
+
+        assert_eq!(optimized_statements(input, Passes::none()), statements(input));
+    }
+
+    #[test]
+    fn optimized_program_evaluates_identically() {
+        let programs = [
+            "1 + 2 * 3 - 4 / 2",
+            r#""lynx" + " " + "lang" == "lynx lang""#,
+            "let debug = false; if (debug) { 1 } else { 2 }",
+            "let limit = 3; let i = 0; while (i < limit) { i = i + 1; } i;",
+            "while (false) { 1 }",
+            "if (1 > 2) { 1 }",
+            "1 / 0",
+            "9223372036854775807 + 1",
+            "-2.5 * -2",
+            "-(1 - 5)",
+            "let base = 10; fn scale(x) { x * base } scale(2 + 3);",
+            r#"
+                fn make() {
+                    let step = 2;
+                    let count = 0;
+                    return fn() {
+                        count = count + step;
+                        return count;
+                    };
+                }
+                let next = make();
+                next();
+                next();
+            "#,
+            r#"
+                let value = 1;
+                fn shadow(value) { value + 1 }
+                [value, shadow(5)];
+            "#,
+            "let answer = 6 * 7; if (answer == 42) { let answer_text = \"yes\"; answer_text } else { \"no\" }",
+        ];
+
+        for input in programs {
+            let original = eval(Parser::get(input).parse_program());
+
+            for passes in [
+                Passes::default(),
+                Passes {
+                    fold_constants: false,
+                    ..Passes::default()
+                },
+                Passes {
+                    prune_branches: false,
+                    ..Passes::default()
+                },
+                Passes {
+                    inline_constants: false,
+                    ..Passes::default()
+                },
+            ] {
+                let optimized = optimize(Parser::get(input).parse_program(), passes);
+
+                assert_eq!(eval(optimized), original, "{} {:?}", input, passes);
+            }
+        }
+    }
+}
@@ -0,0 +1,147 @@
+use std::collections::HashMap;
+use std::fmt;
+
+use crate::object::Object;
+
+// The pairs of an `Object::Hash`. Iteration follows insertion order so printing a hash and
+// walking its keys gives the same result on every run, assigning an existing key keeps its
+// place. Equality ignores the order like the literal `{"a": 1, "b": 2} == {"b": 2, "a": 1}`.
+#[derive(Clone, Default)]
+pub struct OrderedHash {
+    entries: Vec<(Object, Object)>,
+    // position of each key in `entries`
+    index: HashMap<Object, usize>,
+}
+
+impl OrderedHash {
+    pub fn new() -> Self {
+        OrderedHash::default()
+    }
+
+    // the previous value of the key, the caller checks `Object::is_hashable` first
+    pub fn insert(&mut self, key: Object, value: Object) -> Option<Object> {
+        match self.index.get(&key) {
+            Some(&position) => Some(std::mem::replace(&mut self.entries[position].1, value)),
+            None => {
+                self.index.insert(key.clone(), self.entries.len());
+                self.entries.push((key, value));
+
+                None
+            }
+        }
+    }
+
+    pub fn get(&self, key: &Object) -> Option<&Object> {
+        self.index.get(key).map(|&position| &self.entries[position].1)
+    }
+
+    pub fn contains_key(&self, key: &Object) -> bool {
+        self.index.contains_key(key)
+    }
+
+    // the later keys move up one place, the order of the rest stays
+    pub fn remove(&mut self, key: &Object) -> Option<Object> {
+        let position = self.index.remove(key)?;
+        let (_, value) = self.entries.remove(position);
+
+        for (key, _) in &self.entries[position..] {
+            if let Some(later) = self.index.get_mut(key) {
+                *later -= 1;
+            }
+        }
+
+        Some(value)
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&Object, &Object)> {
+        self.entries.iter().map(|(key, value)| (key, value))
+    }
+
+    pub fn keys(&self) -> impl Iterator<Item = &Object> {
+        self.entries.iter().map(|(key, _)| key)
+    }
+
+    pub fn values(&self) -> impl Iterator<Item = &Object> {
+        self.entries.iter().map(|(_, value)| value)
+    }
+}
+
+impl PartialEq for OrderedHash {
+    fn eq(&self, other: &Self) -> bool {
+        self.len() == other.len() && self.iter().all(|(key, value)| other.get(key) == Some(value))
+    }
+}
+
+impl fmt::Debug for OrderedHash {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_map().entries(self.iter()).finish()
+    }
+}
+
+impl IntoIterator for OrderedHash {
+    type Item = (Object, Object);
+    type IntoIter = std::vec::IntoIter<(Object, Object)>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.entries.into_iter()
+    }
+}
+
+impl FromIterator<(Object, Object)> for OrderedHash {
+    fn from_iter<I: IntoIterator<Item = (Object, Object)>>(pairs: I) -> Self {
+        let mut hash = OrderedHash::new();
+
+        pairs.into_iter().for_each(|(key, value)| {
+            hash.insert(key, value);
+        });
+
+        hash
+    }
+}
+
+#[cfg(test)]
+mod unit_test {
+    use crate::object::Object;
+    use crate::ordered_hash::OrderedHash;
+
+    fn string(value: &str) -> Object {
+        Object::String(value.to_string())
+    }
+
+    #[test]
+    fn keep_insertion_order() {
+        let mut hash = OrderedHash::new();
+
+        hash.insert(string("b"), Object::Integer(1));
+        hash.insert(string("a"), Object::Integer(2));
+        hash.insert(string("c"), Object::Integer(3));
+        assert_eq!(hash.insert(string("b"), Object::Integer(4)), Some(Object::Integer(1)));
+
+        assert_eq!(
+            hash.keys().cloned().collect::<Vec<_>>(),
+            vec![string("b"), string("a"), string("c")]
+        );
+        assert_eq!(hash.get(&string("b")), Some(&Object::Integer(4)));
+
+        assert_eq!(hash.remove(&string("a")), Some(Object::Integer(2)));
+        assert_eq!(hash.get(&string("c")), Some(&Object::Integer(3)));
+        assert_eq!(hash.keys().cloned().collect::<Vec<_>>(), vec![string("b"), string("c")]);
+    }
+
+    #[test]
+    fn compare_without_order() {
+        let first = OrderedHash::from_iter([(string("a"), Object::Integer(1)), (string("b"), Object::Integer(2))]);
+        let second = OrderedHash::from_iter([(string("b"), Object::Integer(2)), (string("a"), Object::Integer(1))]);
+
+        assert_eq!(first, second);
+        assert_ne!(first, OrderedHash::from_iter([(string("a"), Object::Integer(1))]));
+    }
+}
@@ -1,13 +1,15 @@
 use crate::ast::{AstNode, Expression, Precedence, Statement};
 use crate::lexer::Lexer;
 use crate::token::TokenType;
-use std::ops::Deref;
+use crate::trace::{Level, Stage, Trace, Tracer};
+use std::rc::Rc;
 
 #[derive(Debug)]
 pub struct Parser<'a> {
     lexer: Lexer<'a>,
     curToken: TokenType,
     peekToken: TokenType,
+    trace: Trace,
 }
 
 impl<'a> Parser<'a> {
@@ -16,31 +18,39 @@ impl<'a> Parser<'a> {
             lexer,
             curToken: TokenType::NONE,
             peekToken: TokenType::NONE,
+            trace: Trace::default(),
         }
     }
 
+    // report tokens and parsed expressions to `tracer`, for debugging the parser
+    pub fn set_tracer(&mut self, tracer: Rc<dyn Tracer>) {
+        self.trace.set(tracer);
+    }
+
     fn next_token(&mut self) {
         self.curToken = self.peekToken.clone();
-
-        // println!("curToken {:?}", self.curToken);
-
         self.peekToken = self.lexer.next_token();
 
-        // println!("29 peekToken {:?}", self.peekToken);
+        let token = &self.peekToken;
+        self.trace.emit(Level::Trace, Stage::Token, || format!("{:?}", token));
     }
 
-    fn expect_cur_token_is(&self, _t: TokenType) -> bool {
-        let matched = matches!(&self.curToken, _t);
-        // println!("current {:?} {:?} {:?}", &self.curToken, _t, matched);
-
-        matched
+    fn expect_cur_token_is(&self, t: TokenType) -> bool {
+        self.curToken.eq(&t)
     }
 
-    fn expect_peek_token_is(&self, _t: TokenType) -> bool {
-        let matched = matches!(&self.peekToken, _t);
-        // println!("peek {:?} {:?} {:?}", &self.peekToken, _t, matched);
+    fn expect_peek_token_is(&self, t: TokenType) -> bool {
+        self.peekToken.eq(&t)
+    }
 
-        matched
+    // advance only when the peek token is the expected one
+    fn expect_peek(&mut self, t: TokenType) -> Option<()> {
+        if self.expect_peek_token_is(t) {
+            self.next_token();
+            Some(())
+        } else {
+            None
+        }
     }
 
     fn parse_statement(&mut self) -> Option<Statement> {
@@ -52,9 +62,12 @@ impl<'a> Parser<'a> {
     }
 
     fn parse_expression_statement(&mut self) -> Option<Statement> {
-        let precedence = self.cur_precedence();
+        let precedence = Precedence::Lowest;
 
-        println!("parse_expression_statement {:?} {:?}", self.curToken, precedence);
+        let token = &self.curToken;
+        self.trace.emit(Level::Trace, Stage::Parse, || {
+            format!("expression statement at {:?}", token)
+        });
 
         match self.parse_expression(precedence) {
             Some(expr) => {
@@ -116,8 +129,6 @@ impl<'a> Parser<'a> {
             }
         };
 
-        self.next_token();
-
         if self.peekToken.eq(&TokenType::SEMICOLON) {
             self.next_token();
         }
@@ -143,19 +154,14 @@ impl<'a> Parser<'a> {
     fn parse_number_expression(&mut self) -> Option<Expression> {
         match &self.curToken {
             &TokenType::NUMBER(num) => Some(Expression::Integer(num)),
+            TokenType::FLOAT(digits) => digits.parse().ok().map(Expression::Float),
             _ => None,
         }
     }
 
     fn parse_identifier(&mut self) -> Option<Expression> {
         match &self.curToken {
-            TokenType::IDENTIFIER(name) => {
-                match self.peekToken {
-                    // TokenType::LEFT_PAREN => self.parse_fn_call_expression(name.to_string()),
-                    // TokenType::LEFT_BRACE => {}, // arr[0]
-                    _ => Some(Expression::Identifier(String::from(name))),
-                }
-            }
+            TokenType::IDENTIFIER(name) => Some(Expression::Identifier(String::from(name))),
             _ => None,
         }
     }
@@ -168,7 +174,7 @@ impl<'a> Parser<'a> {
             TokenType::TRUE => self.parse_boolean_expression(),
             TokenType::FALSE => self.parse_boolean_expression(),
             TokenType::STRING(_) => self.parse_string_expression(),
-            &TokenType::NUMBER(_) => self.parse_number_expression(),
+            &TokenType::NUMBER(_) | TokenType::FLOAT(_) => self.parse_number_expression(),
             TokenType::IDENTIFIER(_) => self.parse_identifier(),
             TokenType::LEFT_BRACE => self.parse_array_expression(),
             TokenType::LEFT_CURLY_BRACE => self.parse_hash_expression(),
@@ -181,13 +187,10 @@ impl<'a> Parser<'a> {
             _ => Some(Expression::NON_PARSED_EXPR((cur_pos, self.curToken.clone()))),
         };
 
-        println!(
-            "parse_expression {:?} {:?} {:?} {:?}",
-            self.curToken,
-            self.peekToken,
-            precedence < self.peek_precedence(),
-            left
-        );
+        let (token, peek) = (&self.curToken, &self.peekToken);
+        self.trace.emit(Level::Trace, Stage::Parse, || {
+            format!("expression {:?} at {:?}, next {:?}", left, token, peek)
+        });
 
         // infix expression
         while !self.peekToken.eq(&TokenType::SEMICOLON) && precedence < self.peek_precedence() {
@@ -203,11 +206,19 @@ impl<'a> Parser<'a> {
                 | TokenType::GREATER_EQUAL
                 | TokenType::BANG_EQUAL => {
                     self.next_token();
-                    left = self.parse_infix_expression(left.unwrap(), &self.curToken.clone());
+                    left = self.parse_infix_expression(left?, &self.curToken.clone());
                 }
                 TokenType::LEFT_PAREN => {
                     self.next_token();
-                    left = self.parse_fn_call_expression(left.unwrap());
+                    left = self.parse_fn_call_expression(left?);
+                }
+                TokenType::DOT => {
+                    self.next_token();
+                    left = self.parse_method_call_expression(left?);
+                }
+                TokenType::ASSIGN => {
+                    self.next_token();
+                    left = self.parse_assign_expression(left?);
                 }
                 _ => {
                     return left;
@@ -218,148 +229,119 @@ impl<'a> Parser<'a> {
         left
     }
 
-    fn parse_fn_call_expression(&mut self, func: Expression) -> Option<Expression> {
-        self.next_token();
+    // <expression>, <expression>, ... <end>
+    // expects the current token to be the opening token and stops at `end`
+    fn parse_expression_list(&mut self, end: TokenType) -> Option<Vec<Expression>> {
+        let mut expressions = vec![];
 
-        if self.curToken.eq(&TokenType::LEFT_PAREN) {
+        while !self.peekToken.eq(&end) {
             self.next_token();
-        }
 
-        let mut fn_parameters = vec![];
+            expressions.push(self.parse_expression(Precedence::Lowest)?);
 
-        // println!("166 {:?}", self.curToken);
+            if self.peekToken.eq(&TokenType::COMMA) {
+                self.next_token();
+            } else if !self.peekToken.eq(&end) {
+                return None;
+            }
+        }
 
-        while !self.curToken.eq(&TokenType::SEMICOLON) {
-            match self.curToken {
-                TokenType::COMMA | TokenType::RIGHT_PAREN => {
-                    self.next_token();
-                }
-                _ => {
-                    let parameter = self.parse_expression(Precedence::Lowest).unwrap();
+        self.next_token();
 
-                    fn_parameters.push(parameter);
+        Some(expressions)
+    }
 
-                    self.next_token();
-                }
-            }
-        }
+    fn parse_fn_call_expression(&mut self, func: Expression) -> Option<Expression> {
+        let fn_parameters = self.parse_expression_list(TokenType::RIGHT_PAREN)?;
 
         Some(Expression::FnCall(Box::new(func), fn_parameters))
     }
 
-    fn parse_array_expression(&mut self) -> Option<Expression> {
-        self.next_token();
+    fn parse_method_call_expression(&mut self, receiver: Expression) -> Option<Expression> {
+        let method = match &self.peekToken {
+            TokenType::IDENTIFIER(name) => name.clone(),
+            _ => return None,
+        };
 
-        let mut expressions = vec![];
+        self.next_token();
 
-        while !self.curToken.eq(&TokenType::RIGHT_BRACE) {
-            match self.curToken {
-                TokenType::COMMA => {
-                    self.next_token();
-                }
-                _ => {
-                    let expression = self.parse_expression(Precedence::Lowest).unwrap();
+        if !self.peekToken.eq(&TokenType::LEFT_PAREN) {
+            return Some(Expression::Property(Box::new(receiver), method));
+        }
 
-                    expressions.push(expression);
+        self.next_token();
 
-                    self.next_token();
-                }
-            }
-        }
+        let arguments = self.parse_expression_list(TokenType::RIGHT_PAREN)?;
 
-        Some(Expression::Array(expressions))
+        Some(Expression::MethodCall(Box::new(receiver), method, arguments))
     }
 
-    fn parse_if_expression(&mut self) -> Option<Expression> {
+    fn parse_assign_expression(&mut self, target: Expression) -> Option<Expression> {
+        if !matches!(target, Expression::Identifier(_)) {
+            return None;
+        }
+
         self.next_token();
 
-        if self.curToken.eq(&TokenType::LEFT_PAREN) {
-            self.next_token();
-        }
+        // right associative, a = b = 1 assigns both
+        let value = self.parse_expression(Precedence::Lowest)?;
 
-        let ifCondition = match self.parse_expression(Precedence::Lowest) {
-            Some(expr) => expr,
-            None => {
-                return None;
-            }
-        };
+        Some(Expression::Assign(Box::new(target), Box::new(value)))
+    }
 
-        self.next_token();
+    fn parse_array_expression(&mut self) -> Option<Expression> {
+        let expressions = self.parse_expression_list(TokenType::RIGHT_BRACE)?;
 
-        if self.curToken.eq(&TokenType::RIGHT_PAREN) {
-            self.next_token();
-        }
+        Some(Expression::Array(expressions))
+    }
 
-        if self.curToken.eq(&TokenType::LEFT_CURLY_BRACE) {
-            self.next_token();
-        }
+    fn parse_if_expression(&mut self) -> Option<Expression> {
+        self.next_token();
 
-        let ifStatements = self.parse_block_statements().unwrap();
+        // the parentheses around the condition are optional, a grouped expression covers them
+        let ifCondition = self.parse_expression(Precedence::Lowest)?;
 
-        if self.curToken.eq(&TokenType::RIGHT_CURLY_BRACE) {
-            self.next_token();
-        }
+        self.expect_peek(TokenType::LEFT_CURLY_BRACE)?;
+        self.next_token();
 
-        // to be refactored
-        let mut elseStatements = vec![];
+        let ifStatements = self.parse_block_statements()?;
 
-        if self.curToken.eq(&TokenType::ELSE) {
+        let elseStatements = if self.expect_peek_token_is(TokenType::ELSE) {
             self.next_token();
 
-            while !self.curToken.eq(&TokenType::RIGHT_CURLY_BRACE) {
-                match self.curToken {
-                    TokenType::LEFT_CURLY_BRACE | TokenType::SEMICOLON => {
-                        self.next_token();
-                    }
-                    _ => {
-                        let statement = self.parse_statement().unwrap();
+            if self.expect_peek_token_is(TokenType::IF) {
+                // else if (...) { ... }
+                self.next_token();
 
-                        elseStatements.push(statement);
-                    }
-                }
-            }
+                let elseIf = self.parse_if_expression()?;
 
-            if self.curToken.eq(&TokenType::RIGHT_CURLY_BRACE) {
+                Some(Statement::BlockStatement(vec![Statement::Expr(Box::new(elseIf))]))
+            } else {
+                self.expect_peek(TokenType::LEFT_CURLY_BRACE)?;
                 self.next_token();
+
+                Some(Statement::BlockStatement(self.parse_block_statements()?))
             }
-        }
+        } else {
+            Option::None
+        };
 
         Some(Expression::If(
             Box::new(ifCondition),
             Statement::BlockStatement(ifStatements),
-            if !elseStatements.is_empty() {
-                Some(Statement::BlockStatement(elseStatements))
-            } else {
-                Option::None
-            },
+            elseStatements,
         ))
     }
 
     fn parse_while_expression(&mut self) -> Option<Expression> {
         self.next_token();
 
-        if self.curToken.eq(&TokenType::LEFT_PAREN) {
-            self.next_token();
-        }
-
-        let whileCondition = match self.parse_expression(Precedence::Lowest) {
-            Some(expr) => expr,
-            None => {
-                return None;
-            }
-        };
+        let whileCondition = self.parse_expression(Precedence::Lowest)?;
 
+        self.expect_peek(TokenType::LEFT_CURLY_BRACE)?;
         self.next_token();
 
-        if self.curToken.eq(&TokenType::RIGHT_PAREN) {
-            self.next_token();
-        }
-
-        if self.curToken.eq(&TokenType::LEFT_CURLY_BRACE) {
-            self.next_token();
-        }
-
-        let whileStatements = self.parse_block_statements().unwrap();
+        let whileStatements = self.parse_block_statements()?;
 
         Some(Expression::While(
             Box::new(whileCondition),
@@ -371,44 +353,7 @@ impl<'a> Parser<'a> {
         // function literal does not have function name
         let fn_name = Expression::Identifier(String::from(""));
 
-        self.next_token();
-
-        if self.curToken.eq(&TokenType::LEFT_PAREN) {
-            self.next_token();
-        }
-
-        let mut fn_parameters = vec![];
-
-        while !self.curToken.eq(&TokenType::RIGHT_PAREN) {
-            match self.curToken {
-                TokenType::COMMA => {
-                    self.next_token();
-                }
-                _ => {
-                    let parameter = self.parse_expression(Precedence::Lowest).unwrap();
-
-                    fn_parameters.push(parameter);
-
-                    self.next_token();
-                }
-            }
-        }
-
-        if self.curToken.eq(&TokenType::RIGHT_PAREN) {
-            self.next_token();
-        }
-
-        if self.curToken.eq(&TokenType::LEFT_CURLY_BRACE) {
-            self.next_token();
-        }
-
-        let fn_body = self.parse_block_statements().unwrap();
-
-        Some(Expression::Fn(
-            Box::new(fn_name),
-            fn_parameters,
-            Statement::BlockStatement(fn_body),
-        ))
+        self.parse_fn_literal(fn_name)
     }
 
     fn parse_fn_expression(&mut self) -> Option<Expression> {
@@ -426,38 +371,19 @@ impl<'a> Parser<'a> {
             }
         };
 
-        self.next_token();
-
-        if self.curToken.eq(&TokenType::LEFT_PAREN) {
-            self.next_token();
-        }
-
-        let mut fn_parameters = vec![];
-
-        while !self.curToken.eq(&TokenType::RIGHT_PAREN) {
-            match self.curToken {
-                TokenType::COMMA => {
-                    self.next_token();
-                }
-                _ => {
-                    let parameter = self.parse_expression(Precedence::Lowest).unwrap();
-
-                    fn_parameters.push(parameter);
+        self.parse_fn_literal(fn_name)
+    }
 
-                    self.next_token();
-                }
-            }
-        }
+    // (<parameter one>, <parameter two>, ...) { <block statement> }
+    fn parse_fn_literal(&mut self, fn_name: Expression) -> Option<Expression> {
+        self.expect_peek(TokenType::LEFT_PAREN)?;
 
-        if self.curToken.eq(&TokenType::RIGHT_PAREN) {
-            self.next_token();
-        }
+        let fn_parameters = self.parse_expression_list(TokenType::RIGHT_PAREN)?;
 
-        if self.curToken.eq(&TokenType::LEFT_CURLY_BRACE) {
-            self.next_token();
-        }
+        self.expect_peek(TokenType::LEFT_CURLY_BRACE)?;
+        self.next_token();
 
-        let fn_body = self.parse_block_statements().unwrap();
+        let fn_body = self.parse_block_statements()?;
 
         Some(Expression::Fn(
             Box::new(fn_name),
@@ -466,18 +392,24 @@ impl<'a> Parser<'a> {
         ))
     }
 
+    // expects the current token to be the first one after `{` and stops at the matching `}`
     fn parse_block_statements(&mut self) -> Option<Vec<Statement>> {
         let mut block_statements = vec![];
 
         while !self.curToken.eq(&TokenType::RIGHT_CURLY_BRACE) {
             match self.curToken {
+                TokenType::EOF => {
+                    return None;
+                }
                 TokenType::SEMICOLON => {
                     self.next_token();
                 }
                 _ => {
-                    let statement = self.parse_statement().unwrap();
+                    if let Some(statement) = self.parse_statement() {
+                        block_statements.push(statement);
+                    }
 
-                    block_statements.push(statement);
+                    self.next_token();
                 }
             }
         }
@@ -486,71 +418,30 @@ impl<'a> Parser<'a> {
     }
 
     fn parse_hash_expression(&mut self) -> Option<Expression> {
-        self.next_token();
-
-        let mut flat_hash_vec = vec![];
-
-        while !self.curToken.eq(&TokenType::RIGHT_CURLY_BRACE) {
-            match self.curToken {
-                TokenType::COLON => {
-                    self.next_token();
-                }
-                TokenType::COMMA => {
-                    self.next_token();
-                }
-                TokenType::LEFT_BRACE => {
-                    let hash_value = self.parse_expression(Precedence::Lowest).unwrap();
-                    flat_hash_vec.push(hash_value);
+        let mut hash_tuple = vec![];
 
-                    self.next_token();
-                }
-                _ => {
-                    // { key: value, ... }
-                    match &self.peekToken {
-                        TokenType::COLON => {
-                            let hash_key = self.parse_expression(Precedence::Lowest).unwrap();
-                            flat_hash_vec.push(hash_key);
-
-                            self.next_token();
-                        }
-                        TokenType::COMMA => {
-                            let hash_value = self.parse_expression(Precedence::Lowest).unwrap();
-                            flat_hash_vec.push(hash_value);
-
-                            self.next_token();
-                        }
-                        TokenType::RIGHT_CURLY_BRACE => {
-                            let hash_value = self.parse_expression(Precedence::Lowest).unwrap();
-                            flat_hash_vec.push(hash_value);
-                            self.next_token();
-                            break;
-                        }
-                        _ => {
-                            self.next_token();
-                        }
-                    }
-                }
-            }
-        }
+        // { key: value, ... }
+        while !self.peekToken.eq(&TokenType::RIGHT_CURLY_BRACE) {
+            self.next_token();
 
-        println!("445 {:?}", flat_hash_vec);
+            let hash_key = self.parse_expression(Precedence::Lowest)?;
 
-        let mut hash_tuple = vec![];
+            self.expect_peek(TokenType::COLON)?;
+            self.next_token();
 
-        // https://stackoverflow.com/questions/66386013/how-to-iterate-over-two-elements-in-a-collection-stepping-by-one-using-iterator
-        // let mut hash_tuple = flat_hash_vec
-        //     .windows(2)
-        //     .map(|&w: Windows<&[Expression; 2]>| (*w.nth(0), *w.nth(1)))
-        //     .collect();
+            let hash_value = self.parse_expression(Precedence::Lowest)?;
 
-        let mut chunks = flat_hash_vec.chunks(2);
+            hash_tuple.push((hash_key, hash_value));
 
-        while !chunks.is_empty() {
-            if let [hash_key, hash_value] = chunks.next().unwrap() {
-                hash_tuple.push((hash_key.deref().clone(), hash_value.deref().clone()));
+            if self.peekToken.eq(&TokenType::COMMA) {
+                self.next_token();
+            } else if !self.peekToken.eq(&TokenType::RIGHT_CURLY_BRACE) {
+                return None;
             }
         }
 
+        self.next_token();
+
         Some(Expression::Hash(hash_tuple))
     }
 
@@ -563,8 +454,6 @@ impl<'a> Parser<'a> {
     }
 
     fn parse_prefix_expression(&mut self) -> Option<Expression> {
-        println!("parse_prefix_expression {:?}", self.curToken);
-
         let operator = self.curToken.clone();
         self.next_token();
 
@@ -581,14 +470,8 @@ impl<'a> Parser<'a> {
 
         self.next_token();
 
-        match self.parse_expression(cur_precedence) {
-            Some(expr) => {
-                println!("parse_infix_expression {:?}", self.curToken);
-
-                Some(Expression::Infix(Box::new(left), token.clone(), Box::new(expr)))
-            }
-            None => None,
-        }
+        self.parse_expression(cur_precedence)
+            .map(|expr| Expression::Infix(Box::new(left), token.clone(), Box::new(expr)))
     }
 
     fn parse_grouped_expression(&mut self) -> Option<Expression> {
@@ -619,20 +502,6 @@ impl<'a> Parser<'a> {
 
         let mut statements = vec![];
 
-        // while let Some(ended) = self.lexer.is_end() {
-        //     if ended {
-        //         break;
-        //     } else {
-        //         match self.parse_statement() {
-        //             Some(statement) => {
-        //                 statements.push(statement);
-        //             }
-        //             _ => {}
-        //         }
-        //         self.next_token();
-        //     }
-        // }
-
         while !self.curToken.eq(&TokenType::EOF) {
             if let Some(statement) = self.parse_statement() {
                 statements.push(statement);
@@ -640,6 +509,10 @@ impl<'a> Parser<'a> {
             self.next_token();
         }
 
+        self.trace.emit(Level::Debug, Stage::Parse, || {
+            format!("program of {} statements", statements.len())
+        });
+
         AstNode::Program(statements)
     }
 }
@@ -651,7 +524,7 @@ mod unit_test {
     use crate::token::TokenType;
 
     fn get_parser(input: &str) -> Parser {
-        return Parser::get(input);
+        Parser::get(input)
     }
 
     #[test]
@@ -1103,6 +976,48 @@ mod unit_test {
         assert_eq!(format!("{:?}", rootNode), format!("{:?}", expected));
     }
 
+    #[test]
+    fn parse_method_call_expression() {
+        let input = r#"
+            file.seek(0).read(10, "utf8");
+        "#;
+        let mut parser = get_parser(input);
+        let rootNode = parser.parse_program();
+
+        let expected = AstNode::Program(vec![Statement::Expr(Box::new(Expression::MethodCall(
+            Box::new(Expression::MethodCall(
+                Box::new(Expression::Identifier(String::from("file"))),
+                String::from("seek"),
+                vec![Expression::Integer(0)],
+            )),
+            String::from("read"),
+            vec![Expression::Integer(10), Expression::String(String::from("utf8"))],
+        )))]);
+
+        assert_eq!(format!("{:?}", rootNode), format!("{:?}", expected));
+    }
+
+    #[test]
+    fn parse_property_expression() {
+        let mut parser = get_parser(" math.sqrt(2.5) + math.PI; ");
+        let rootNode = parser.parse_program();
+
+        let expected = AstNode::Program(vec![Statement::Expr(Box::new(Expression::Infix(
+            Box::new(Expression::MethodCall(
+                Box::new(Expression::Identifier(String::from("math"))),
+                String::from("sqrt"),
+                vec![Expression::Float(2.5)],
+            )),
+            TokenType::ADD,
+            Box::new(Expression::Property(
+                Box::new(Expression::Identifier(String::from("math"))),
+                String::from("PI"),
+            )),
+        )))]);
+
+        assert_eq!(format!("{:?}", rootNode), format!("{:?}", expected));
+    }
+
     #[test]
     fn parse_fn_call_expression() {
         let input = r#"
@@ -1128,6 +1043,29 @@ mod unit_test {
         assert_eq!(format!("{:?}", rootNode), format!("{:?}", expected));
     }
 
+    #[test]
+    fn parse_assign_expression() {
+        let input = r#"
+            foo = bar = 1 + 2;
+        "#;
+        let mut parser = get_parser(input);
+        let rootNode = parser.parse_program();
+
+        let expected = AstNode::Program(vec![Statement::Expr(Box::new(Expression::Assign(
+            Box::new(Expression::Identifier(String::from("foo"))),
+            Box::new(Expression::Assign(
+                Box::new(Expression::Identifier(String::from("bar"))),
+                Box::new(Expression::Infix(
+                    Box::new(Expression::Integer(1)),
+                    TokenType::ADD,
+                    Box::new(Expression::Integer(2)),
+                )),
+            )),
+        )))]);
+
+        assert_eq!(format!("{:?}", rootNode), format!("{:?}", expected));
+    }
+
     #[test]
     fn parse_builtin_expression() {
         let input = r#"
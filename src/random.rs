@@ -0,0 +1,80 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// SplitMix64, the generator behind the random builtins. Plain 64 bit integer arithmetic, so a
+// seed gives the same numbers on every platform and in every version of the crate. It is not
+// meant for anything secret.
+#[derive(Debug, Clone)]
+pub struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        Rng { state: seed }
+    }
+
+    // seeded from the clock, for programs that do not need to be reproduced
+    pub fn from_time() -> Self {
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |elapsed| elapsed.as_nanos() as u64);
+
+        Rng::new(nanos)
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+
+        z ^ (z >> 31)
+    }
+
+    // uniform in [0, 1), the top 53 bits fill the mantissa
+    pub fn next_float(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    // uniform in [0, bound), draws that would favour the low numbers are thrown away
+    pub fn below(&mut self, bound: u64) -> u64 {
+        assert!(bound > 0, "empty range");
+
+        let threshold = bound.wrapping_neg() % bound;
+
+        loop {
+            let value = self.next_u64();
+
+            if value >= threshold {
+                return value % bound;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod unit_test {
+    use crate::random::Rng;
+
+    #[test]
+    fn reproduce_sequence() {
+        // the reference outputs of SplitMix64 for seed 0
+        let mut rng = Rng::new(0);
+
+        assert_eq!(rng.next_u64(), 0xe220_a839_7b1d_cdaf);
+        assert_eq!(rng.next_u64(), 0x6e78_9e6a_a1b9_65f4);
+
+        let mut first = Rng::new(42);
+        let mut second = Rng::new(42);
+
+        for _ in 0..100 {
+            let value = first.below(10);
+
+            assert_eq!(value, second.below(10));
+            assert!(value < 10);
+            assert!((0.0..1.0).contains(&first.next_float()));
+            second.next_float();
+        }
+    }
+}
@@ -0,0 +1,489 @@
+use std::collections::HashSet;
+
+use crate::ast::*;
+
+struct Declaration {
+    name: String,
+    slot: usize,
+    // statement of the scope that declares the name, parameters come before the first one
+    position: usize,
+}
+
+struct Scope {
+    declarations: Vec<Declaration>,
+    // statement of this scope that is being resolved right now
+    position: usize,
+    // the scope of a call, parameters and the function body
+    function: bool,
+}
+
+impl Scope {
+    fn new(function: bool) -> Self {
+        Scope {
+            declarations: vec![],
+            position: 0,
+            function,
+        }
+    }
+
+    fn declare(&mut self, name: String, position: usize) -> usize {
+        let slot = self.declarations.len();
+
+        self.declarations.push(Declaration { name, slot, position });
+
+        slot
+    }
+
+    // the binding of a name seen from the current statement. Code running right away sees the
+    // declarations before it. Closures run later and also see names declared after them,
+    // which is what makes recursion and mutual recursion work.
+    fn lookup(&self, name: &str, in_closure: bool) -> Option<usize> {
+        let mut declarations = self.declarations.iter().filter(|declaration| declaration.name == name);
+
+        let before = declarations
+            .clone()
+            .filter(|declaration| declaration.position < self.position)
+            .last();
+
+        match before {
+            Some(declaration) => Some(declaration.slot),
+            None if in_closure => declarations.next().map(|declaration| declaration.slot),
+            None => None,
+        }
+    }
+
+    // the slot the declaration of the current statement reserved up front
+    fn declared(&self, name: &str) -> Option<usize> {
+        self.declarations
+            .iter()
+            .find(|declaration| declaration.position == self.position && declaration.name == name)
+            .map(|declaration| declaration.slot)
+    }
+}
+
+// Static pass between parsing and evaluation.
+//
+// Every identifier declared in a function or block is rewritten to `Expression::Local` with the
+// number of scopes to walk up and its slot in that scope, so the evaluator indexes into a frame
+// instead of hashing names. Redeclaring a name with `let` takes a new slot, closures created
+// before keep the old binding. Top level names stay identifiers, globals are late bound.
+//
+// Names that are neither declared nor a known global, repeated parameters and a `break` outside
+// of a loop and of a function are reported before anything runs.
+pub struct Resolver {
+    globals: HashSet<String>,
+    scopes: Vec<Scope>,
+    // loops around the expression being resolved
+    loops: usize,
+}
+
+impl Resolver {
+    // `globals` are the names already bound when the program runs, e.g. builtins
+    pub fn new<I: IntoIterator<Item = String>>(globals: I) -> Self {
+        Resolver {
+            globals: globals.into_iter().collect(),
+            scopes: vec![],
+            loops: 0,
+        }
+    }
+
+    // the top level declarations of a resolved program are known globals for the next one
+    pub fn resolve(&mut self, program: AstNode) -> Result<AstNode, String> {
+        let AstNode::Program(statements) = program;
+        let known = self.globals.clone();
+
+        for statement in &statements {
+            if let Some(name) = declared_name(statement) {
+                self.globals.insert(name.to_string());
+            }
+        }
+
+        self.scopes.clear();
+        self.loops = 0;
+
+        let resolved = statements
+            .into_iter()
+            .map(|statement| self.resolve_statement(statement))
+            .collect::<Result<Vec<_>, _>>();
+
+        match resolved {
+            Ok(statements) => Ok(AstNode::Program(statements)),
+            Err(message) => {
+                self.globals = known;
+                Err(message)
+            }
+        }
+    }
+
+    fn resolve_statement(&mut self, statement: Statement) -> Result<Statement, String> {
+        match statement {
+            Statement::Let(identifier, expr) => {
+                let expr = self.resolve_expression(*expr)?;
+                let identifier = self.resolve_declaration(*identifier);
+
+                Ok(Statement::Let(Box::new(identifier), Box::new(expr)))
+            }
+            Statement::Return(expr) => Ok(Statement::Return(Box::new(self.resolve_expression(*expr)?))),
+            Statement::Expr(expr) => Ok(Statement::Expr(Box::new(self.resolve_expression(*expr)?))),
+            Statement::BlockStatement(statements) => Ok(Statement::BlockStatement(
+                statements
+                    .into_iter()
+                    .map(|statement| self.resolve_statement(statement))
+                    .collect::<Result<Vec<_>, _>>()?,
+            )),
+        }
+    }
+
+    // resolve the statements of a block into the innermost scope
+    fn resolve_block(&mut self, statements: Vec<Statement>) -> Result<Vec<Statement>, String> {
+        let scope = self.scopes.last_mut().unwrap();
+
+        for (index, statement) in statements.iter().enumerate() {
+            if let Some(name) = declared_name(statement) {
+                scope.declare(name.to_string(), index + 1);
+            }
+        }
+
+        let mut resolved = vec![];
+
+        for (index, statement) in statements.into_iter().enumerate() {
+            self.scopes.last_mut().unwrap().position = index + 1;
+            resolved.push(self.resolve_statement(statement)?);
+        }
+
+        Ok(resolved)
+    }
+
+    // `if` and `while` bodies get their own scope
+    fn resolve_scoped_block(&mut self, block_stmt: Statement) -> Result<Statement, String> {
+        let statements = match block_stmt {
+            Statement::BlockStatement(statements) => statements,
+            statement => vec![statement],
+        };
+
+        self.scopes.push(Scope::new(false));
+
+        let resolved = self.resolve_block(statements);
+
+        self.scopes.pop();
+
+        Ok(Statement::BlockStatement(resolved?))
+    }
+
+    fn resolve_expression(&mut self, expr: Expression) -> Result<Expression, String> {
+        let resolved = match expr {
+            Expression::Identifier(name) => match self.lookup(&name) {
+                Some((depth, slot)) => Expression::Local(name, depth, slot),
+                None if self.globals.contains(&name) => Expression::Identifier(name),
+                None => return Err(format!("use of undefined variable: {}", name)),
+            },
+            Expression::Array(exprs) => Expression::Array(self.resolve_expressions(exprs)?),
+            Expression::Hash(hashes) => Expression::Hash(
+                hashes
+                    .into_iter()
+                    .map(|(key, value)| Ok((self.resolve_expression(key)?, self.resolve_expression(value)?)))
+                    .collect::<Result<Vec<_>, String>>()?,
+            ),
+            Expression::Prefix(operator, expr) => {
+                Expression::Prefix(operator, Box::new(self.resolve_expression(*expr)?))
+            }
+            Expression::Infix(left, operator, right) => Expression::Infix(
+                Box::new(self.resolve_expression(*left)?),
+                operator,
+                Box::new(self.resolve_expression(*right)?),
+            ),
+            Expression::If(condition, statements, else_statements) => Expression::If(
+                Box::new(self.resolve_expression(*condition)?),
+                self.resolve_scoped_block(statements)?,
+                match else_statements {
+                    Some(else_statements) => Some(self.resolve_scoped_block(else_statements)?),
+                    None => None,
+                },
+            ),
+            Expression::While(condition, block_stmt) => {
+                let condition = self.resolve_expression(*condition)?;
+
+                self.loops += 1;
+                let block_stmt = self.resolve_scoped_block(block_stmt);
+                self.loops -= 1;
+
+                Expression::While(Box::new(condition), block_stmt?)
+            }
+            // a `break` in a function but outside of a loop leaves the function with null
+            Expression::Break if self.loops == 0 && !self.scopes.iter().any(|scope| scope.function) => {
+                return Err(String::from("break outside of a loop"));
+            }
+            Expression::Fn(fn_name, parameters, body) => {
+                let fn_name = match *fn_name {
+                    Expression::Identifier(name) if !name.is_empty() => {
+                        if self.scopes.is_empty() {
+                            self.globals.insert(name.clone());
+                        }
+
+                        self.resolve_declaration(Expression::Identifier(name))
+                    }
+                    fn_name => fn_name,
+                };
+
+                let (parameters, body) = self.resolve_function(parameters, body)?;
+
+                Expression::Fn(Box::new(fn_name), parameters, body)
+            }
+            Expression::FnCall(function, arguments) => Expression::FnCall(
+                Box::new(self.resolve_expression(*function)?),
+                self.resolve_expressions(arguments)?,
+            ),
+            Expression::MethodCall(receiver, method, arguments) => Expression::MethodCall(
+                Box::new(self.resolve_expression(*receiver)?),
+                method,
+                self.resolve_expressions(arguments)?,
+            ),
+            Expression::Property(receiver, key) => {
+                Expression::Property(Box::new(self.resolve_expression(*receiver)?), key)
+            }
+            Expression::Assign(target, value) => {
+                let value = self.resolve_expression(*value)?;
+
+                let target = match *target {
+                    Expression::Identifier(name) => match self.lookup(&name) {
+                        Some((depth, slot)) => Expression::Local(name, depth, slot),
+                        None if self.globals.contains(&name) => Expression::Identifier(name),
+                        None => return Err(format!("assignment to undefined variable: {}", name)),
+                    },
+                    target => target,
+                };
+
+                Expression::Assign(Box::new(target), Box::new(value))
+            }
+            expr => expr,
+        };
+
+        Ok(resolved)
+    }
+
+    fn resolve_expressions(&mut self, exprs: Vec<Expression>) -> Result<Vec<Expression>, String> {
+        exprs.into_iter().map(|expr| self.resolve_expression(expr)).collect()
+    }
+
+    fn resolve_function(&mut self, parameters: FnParameter, body: FnBody) -> Result<(FnParameter, FnBody), String> {
+        let mut scope = Scope::new(true);
+        let mut resolved_parameters = vec![];
+
+        for parameter in parameters {
+            match parameter {
+                Expression::Identifier(name) => {
+                    if scope.declarations.iter().any(|declaration| declaration.name == name) {
+                        return Err(format!("duplicate parameter: {}", name));
+                    }
+
+                    let slot = scope.declare(name.clone(), 0);
+                    resolved_parameters.push(Expression::Local(name, 0, slot));
+                }
+                parameter => resolved_parameters.push(parameter),
+            }
+        }
+
+        self.scopes.push(scope);
+
+        let statements = match body {
+            Statement::BlockStatement(statements) => statements,
+            statement => vec![statement],
+        };
+
+        let resolved = self.resolve_block(statements);
+
+        self.scopes.pop();
+
+        Ok((resolved_parameters, Statement::BlockStatement(resolved?)))
+    }
+
+    // the target of a `let` or `fn` declaration, globals keep their name
+    fn resolve_declaration(&mut self, identifier: Expression) -> Expression {
+        let name = match identifier {
+            Expression::Identifier(name) => name,
+            identifier => return identifier,
+        };
+
+        let scope = match self.scopes.last_mut() {
+            Some(scope) => scope,
+            None => return Expression::Identifier(name),
+        };
+
+        // declarations nested in an expression were not reserved with the block
+        let slot = match scope.declared(&name) {
+            Some(slot) => slot,
+            None => scope.declare(name.clone(), scope.position),
+        };
+
+        Expression::Local(name, 0, slot)
+    }
+
+    fn lookup(&self, name: &str) -> Option<(usize, usize)> {
+        let mut in_closure = false;
+
+        for (depth, scope) in self.scopes.iter().rev().enumerate() {
+            if let Some(slot) = scope.lookup(name, in_closure) {
+                return Some((depth, slot));
+            }
+
+            in_closure = in_closure || scope.function;
+        }
+
+        None
+    }
+}
+
+// the name a `let` or `fn` declaration statement binds
+fn declared_name(statement: &Statement) -> Option<&str> {
+    let identifier = match statement {
+        Statement::Let(identifier, _) => identifier.as_ref(),
+        Statement::Expr(expr) => match expr.as_ref() {
+            Expression::Fn(fn_name, _, _) => fn_name.as_ref(),
+            _ => return None,
+        },
+        _ => return None,
+    };
+
+    match identifier {
+        Expression::Identifier(name) if !name.is_empty() => Some(name),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod unit_test {
+    use crate::ast::{AstNode, Expression, Statement};
+    use crate::parser::Parser;
+    use crate::resolver::Resolver;
+
+    fn resolve(input: &str) -> Result<AstNode, String> {
+        let program = Parser::get(input).parse_program();
+
+        Resolver::new(vec![String::from("len")]).resolve(program)
+    }
+
+    fn fn_body(program: AstNode) -> Vec<Statement> {
+        let AstNode::Program(statements) = program;
+
+        match statements.into_iter().next() {
+            Some(Statement::Expr(expr)) => match *expr {
+                Expression::Fn(_, _, Statement::BlockStatement(statements)) => statements,
+                _ => vec![],
+            },
+            _ => vec![],
+        }
+    }
+
+    #[test]
+    fn resolve_slots() {
+        let body = fn_body(resolve("fn foo(a, b) { let c = a; if (c) { len(b) } }").unwrap());
+
+        assert_eq!(
+            body[0],
+            Statement::Let(
+                Box::new(Expression::Local(String::from("c"), 0, 2)),
+                Box::new(Expression::Local(String::from("a"), 0, 0))
+            )
+        );
+
+        if let Statement::Expr(expr) = &body[1] {
+            if let Expression::If(condition, Statement::BlockStatement(statements), None) = expr.as_ref() {
+                assert_eq!(**condition, Expression::Local(String::from("c"), 0, 2));
+                assert_eq!(
+                    statements[0],
+                    Statement::Expr(Box::new(Expression::FnCall(
+                        Box::new(Expression::Identifier(String::from("len"))),
+                        vec![Expression::Local(String::from("b"), 1, 1)]
+                    )))
+                );
+                return;
+            }
+        }
+
+        panic!("unexpected {:?}", body[1]);
+    }
+
+    #[test]
+    fn resolve_rebinding_to_new_slot() {
+        let body = fn_body(resolve("fn foo() { let x = 1; let get = fn() { x }; let x = x; }").unwrap());
+
+        assert_eq!(
+            body[2],
+            Statement::Let(
+                Box::new(Expression::Local(String::from("x"), 0, 2)),
+                Box::new(Expression::Local(String::from("x"), 0, 0))
+            )
+        );
+    }
+
+    #[test]
+    fn resolve_forward_reference_in_closure() {
+        let body = fn_body(resolve("fn foo() { let get = fn() { later }; let later = 1; }").unwrap());
+
+        assert_eq!(
+            body[0],
+            Statement::Let(
+                Box::new(Expression::Local(String::from("get"), 0, 0)),
+                Box::new(Expression::Fn(
+                    Box::new(Expression::Identifier(String::new())),
+                    vec![],
+                    Statement::BlockStatement(vec![Statement::Expr(Box::new(Expression::Local(
+                        String::from("later"),
+                        1,
+                        1
+                    )))])
+                ))
+            )
+        );
+    }
+
+    #[test]
+    fn resolve_undefined_variable() {
+        assert_eq!(
+            resolve("fn foo() { let x = y; }").err(),
+            Some(String::from("use of undefined variable: y"))
+        );
+
+        // only closures see later declarations
+        assert_eq!(
+            resolve("fn foo() { let x = y; let y = 1; }").err(),
+            Some(String::from("use of undefined variable: y"))
+        );
+
+        // globals are late bound
+        assert!(resolve("fn foo() { bar } let bar = 1;").is_ok());
+    }
+
+    #[test]
+    fn resolve_duplicate_parameter() {
+        assert_eq!(
+            resolve("let foo = fn(a, b, a) { a };").err(),
+            Some(String::from("duplicate parameter: a"))
+        );
+    }
+
+    #[test]
+    fn resolve_break_outside_loop() {
+        for input in ["break;", "if (true) { break; }", "while (break) {}"] {
+            assert_eq!(
+                resolve(input).err(),
+                Some(String::from("break outside of a loop")),
+                "{}",
+                input
+            );
+        }
+
+        assert!(resolve("while (true) { if (true) { break; } }").is_ok());
+        assert!(resolve("fn foo() { break; }").is_ok());
+    }
+
+    #[test]
+    fn resolve_keeps_globals_between_programs() {
+        let mut resolver = Resolver::new(vec![]);
+
+        assert!(resolver.resolve(Parser::get("let foo = 1;").parse_program()).is_ok());
+        assert!(resolver.resolve(Parser::get("foo;").parse_program()).is_ok());
+        assert!(resolver.resolve(Parser::get("let bar = baz;").parse_program()).is_err());
+        assert!(resolver.resolve(Parser::get("bar;").parse_program()).is_err());
+    }
+}
@@ -0,0 +1,682 @@
+use std::fmt;
+
+use serde::de::value::{MapAccessDeserializer, MapDeserializer, SeqDeserializer};
+use serde::de::{self, DeserializeOwned, IntoDeserializer, MapAccess, SeqAccess, Visitor};
+use serde::ser::{self, Serialize, SerializeMap, SerializeSeq};
+use serde::{forward_to_deserialize_any, Deserialize, Deserializer, Serializer};
+
+use crate::object::{Object, RuntimeError};
+use crate::ordered_hash::OrderedHash;
+
+// `Object` as serde data, behind the `serde` feature.
+//
+// Values map like in `json`: null is a unit, hashes are maps in the order of their keys, integers
+// are `i64` and a larger `u64` is an error. Functions, builtins and native objects have no data to
+// serialize and fail. Structs become hashes with string keys, an enum variant becomes its name or a
+// hash with the name as the only key, like serde_json does.
+
+pub fn to_object<T: Serialize + ?Sized>(value: &T) -> Result<Object, RuntimeError> {
+    value.serialize(ObjectSerializer)
+}
+
+pub fn from_object<T: DeserializeOwned>(object: &Object) -> Result<T, RuntimeError> {
+    T::deserialize(object.clone())
+}
+
+impl std::error::Error for RuntimeError {}
+
+impl ser::Error for RuntimeError {
+    fn custom<T: fmt::Display>(message: T) -> Self {
+        RuntimeError::new(message.to_string())
+    }
+}
+
+impl de::Error for RuntimeError {
+    fn custom<T: fmt::Display>(message: T) -> Self {
+        RuntimeError::new(message.to_string())
+    }
+}
+
+fn unsupported(object: &Object) -> String {
+    match object {
+        Object::Native(native) => format!("can not convert native object {} to serde data", native.type_name),
+        object => format!("can not convert {} to serde data", object.type_name()),
+    }
+}
+
+fn integer<T: TryInto<i64> + fmt::Display + Copy>(int: T) -> Result<Object, RuntimeError> {
+    match int.try_into() {
+        Ok(int) => Ok(Object::Integer(int)),
+        Err(_) => Err(RuntimeError::new(format!("integer out of range: {}", int))),
+    }
+}
+
+fn insert(pairs: &mut OrderedHash, key: Object, value: Object) -> Result<(), String> {
+    if !key.is_hashable() {
+        return Err(key.unhashable_error());
+    }
+
+    pairs.insert(key, value);
+
+    Ok(())
+}
+
+fn variant_hash(variant: &str, contents: Object) -> Object {
+    let mut pairs = OrderedHash::new();
+    pairs.insert(Object::String(variant.to_string()), contents);
+
+    Object::Hash(pairs)
+}
+
+impl Serialize for Object {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            Object::Integer(int) => serializer.serialize_i64(*int),
+            Object::Float(float) => serializer.serialize_f64(*float),
+            Object::Boolean(bool) => serializer.serialize_bool(*bool),
+            Object::String(string) => serializer.serialize_str(string),
+            Object::Array(items) => {
+                let mut seq = serializer.serialize_seq(Some(items.len()))?;
+
+                for item in items {
+                    seq.serialize_element(item)?;
+                }
+
+                seq.end()
+            }
+            Object::Hash(pairs) => {
+                let mut map = serializer.serialize_map(Some(pairs.len()))?;
+
+                for (key, value) in pairs.iter() {
+                    map.serialize_entry(key, value)?;
+                }
+
+                map.end()
+            }
+            Object::Null => serializer.serialize_unit(),
+            object => Err(ser::Error::custom(unsupported(object))),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Object {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_any(ObjectVisitor)
+    }
+}
+
+struct ObjectVisitor;
+
+impl<'de> Visitor<'de> for ObjectVisitor {
+    type Value = Object;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "a lynx value")
+    }
+
+    fn visit_bool<E: de::Error>(self, bool: bool) -> Result<Object, E> {
+        Ok(Object::Boolean(bool))
+    }
+
+    fn visit_i64<E: de::Error>(self, int: i64) -> Result<Object, E> {
+        Ok(Object::Integer(int))
+    }
+
+    fn visit_u64<E: de::Error>(self, int: u64) -> Result<Object, E> {
+        integer(int).map_err(E::custom)
+    }
+
+    fn visit_f64<E: de::Error>(self, float: f64) -> Result<Object, E> {
+        Ok(Object::Float(float))
+    }
+
+    fn visit_str<E: de::Error>(self, string: &str) -> Result<Object, E> {
+        Ok(Object::String(string.to_string()))
+    }
+
+    fn visit_string<E: de::Error>(self, string: String) -> Result<Object, E> {
+        Ok(Object::String(string))
+    }
+
+    fn visit_unit<E: de::Error>(self) -> Result<Object, E> {
+        Ok(Object::Null)
+    }
+
+    fn visit_none<E: de::Error>(self) -> Result<Object, E> {
+        Ok(Object::Null)
+    }
+
+    fn visit_some<D: Deserializer<'de>>(self, deserializer: D) -> Result<Object, D::Error> {
+        Object::deserialize(deserializer)
+    }
+
+    fn visit_newtype_struct<D: Deserializer<'de>>(self, deserializer: D) -> Result<Object, D::Error> {
+        Object::deserialize(deserializer)
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Object, A::Error> {
+        let mut items = Vec::with_capacity(seq.size_hint().unwrap_or(0).min(4096));
+
+        while let Some(item) = seq.next_element()? {
+            items.push(item);
+        }
+
+        Ok(Object::Array(items))
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Object, A::Error> {
+        let mut pairs = OrderedHash::new();
+
+        while let Some((key, value)) = map.next_entry()? {
+            insert(&mut pairs, key, value).map_err(de::Error::custom)?;
+        }
+
+        Ok(Object::Hash(pairs))
+    }
+}
+
+// reads serde data out of a value, `from_object` deserializes from a clone
+impl<'de> Deserializer<'de> for Object {
+    type Error = RuntimeError;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, RuntimeError> {
+        match self {
+            Object::Integer(int) => visitor.visit_i64(int),
+            Object::Float(float) => visitor.visit_f64(float),
+            Object::Boolean(bool) => visitor.visit_bool(bool),
+            Object::String(string) => visitor.visit_string(string),
+            Object::Array(items) => {
+                let mut seq = SeqDeserializer::new(items.into_iter());
+                let value = visitor.visit_seq(&mut seq)?;
+                seq.end()?;
+
+                Ok(value)
+            }
+            Object::Hash(pairs) => {
+                let mut map = MapDeserializer::new(pairs.into_iter());
+                let value = visitor.visit_map(&mut map)?;
+                map.end()?;
+
+                Ok(value)
+            }
+            Object::Null => visitor.visit_unit(),
+            object => Err(RuntimeError::new(unsupported(&object))),
+        }
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, RuntimeError> {
+        match self {
+            Object::Null => visitor.visit_none(),
+            object => visitor.visit_some(object),
+        }
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, RuntimeError> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    // a variant is its name, or a hash with the name as the only key and the contents as value
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, RuntimeError> {
+        match self {
+            Object::String(variant) => visitor.visit_enum(variant.into_deserializer()),
+            Object::Hash(pairs) if pairs.len() == 1 => {
+                visitor.visit_enum(MapAccessDeserializer::new(MapDeserializer::new(pairs.into_iter())))
+            }
+            object => Err(RuntimeError::new(format!(
+                "expected a string or a hash with one key for an enum, got {}",
+                object.type_name()
+            ))),
+        }
+    }
+
+    forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf unit unit_struct seq tuple tuple_struct map struct identifier ignored_any
+    }
+}
+
+impl<'de> IntoDeserializer<'de, RuntimeError> for Object {
+    type Deserializer = Object;
+
+    fn into_deserializer(self) -> Object {
+        self
+    }
+}
+
+// builds a value out of serde data, see `to_object`
+struct ObjectSerializer;
+
+impl Serializer for ObjectSerializer {
+    type Ok = Object;
+    type Error = RuntimeError;
+
+    type SerializeSeq = ArraySerializer;
+    type SerializeTuple = ArraySerializer;
+    type SerializeTupleStruct = ArraySerializer;
+    type SerializeTupleVariant = VariantSerializer<ArraySerializer>;
+    type SerializeMap = HashSerializer;
+    type SerializeStruct = HashSerializer;
+    type SerializeStructVariant = VariantSerializer<HashSerializer>;
+
+    fn serialize_bool(self, bool: bool) -> Result<Object, RuntimeError> {
+        Ok(Object::Boolean(bool))
+    }
+
+    fn serialize_i8(self, int: i8) -> Result<Object, RuntimeError> {
+        integer(int)
+    }
+
+    fn serialize_i16(self, int: i16) -> Result<Object, RuntimeError> {
+        integer(int)
+    }
+
+    fn serialize_i32(self, int: i32) -> Result<Object, RuntimeError> {
+        integer(int)
+    }
+
+    fn serialize_i64(self, int: i64) -> Result<Object, RuntimeError> {
+        Ok(Object::Integer(int))
+    }
+
+    fn serialize_i128(self, int: i128) -> Result<Object, RuntimeError> {
+        integer(int)
+    }
+
+    fn serialize_u8(self, int: u8) -> Result<Object, RuntimeError> {
+        integer(int)
+    }
+
+    fn serialize_u16(self, int: u16) -> Result<Object, RuntimeError> {
+        integer(int)
+    }
+
+    fn serialize_u32(self, int: u32) -> Result<Object, RuntimeError> {
+        integer(int)
+    }
+
+    fn serialize_u64(self, int: u64) -> Result<Object, RuntimeError> {
+        integer(int)
+    }
+
+    fn serialize_u128(self, int: u128) -> Result<Object, RuntimeError> {
+        integer(int)
+    }
+
+    fn serialize_f32(self, float: f32) -> Result<Object, RuntimeError> {
+        self.serialize_f64(float.into())
+    }
+
+    fn serialize_f64(self, float: f64) -> Result<Object, RuntimeError> {
+        Ok(Object::Float(float))
+    }
+
+    fn serialize_char(self, char: char) -> Result<Object, RuntimeError> {
+        Ok(Object::String(char.to_string()))
+    }
+
+    fn serialize_str(self, string: &str) -> Result<Object, RuntimeError> {
+        Ok(Object::String(string.to_string()))
+    }
+
+    // there are no byte strings, bytes become an array of integers
+    fn serialize_bytes(self, bytes: &[u8]) -> Result<Object, RuntimeError> {
+        Ok(Object::Array(
+            bytes.iter().map(|&byte| Object::Integer(byte.into())).collect(),
+        ))
+    }
+
+    fn serialize_none(self) -> Result<Object, RuntimeError> {
+        Ok(Object::Null)
+    }
+
+    fn serialize_some<T: Serialize + ?Sized>(self, value: &T) -> Result<Object, RuntimeError> {
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> Result<Object, RuntimeError> {
+        Ok(Object::Null)
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<Object, RuntimeError> {
+        Ok(Object::Null)
+    }
+
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        _index: u32,
+        variant: &'static str,
+    ) -> Result<Object, RuntimeError> {
+        self.serialize_str(variant)
+    }
+
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        value: &T,
+    ) -> Result<Object, RuntimeError> {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        _index: u32,
+        variant: &'static str,
+        value: &T,
+    ) -> Result<Object, RuntimeError> {
+        Ok(variant_hash(variant, value.serialize(self)?))
+    }
+
+    fn serialize_seq(self, len: Option<usize>) -> Result<ArraySerializer, RuntimeError> {
+        Ok(ArraySerializer {
+            items: Vec::with_capacity(len.unwrap_or(0)),
+        })
+    }
+
+    fn serialize_tuple(self, len: usize) -> Result<ArraySerializer, RuntimeError> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_struct(self, _name: &'static str, len: usize) -> Result<ArraySerializer, RuntimeError> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        _index: u32,
+        variant: &'static str,
+        len: usize,
+    ) -> Result<VariantSerializer<ArraySerializer>, RuntimeError> {
+        Ok(VariantSerializer {
+            variant,
+            contents: self.serialize_seq(Some(len))?,
+        })
+    }
+
+    fn serialize_map(self, _len: Option<usize>) -> Result<HashSerializer, RuntimeError> {
+        Ok(HashSerializer {
+            pairs: OrderedHash::new(),
+            key: None,
+        })
+    }
+
+    fn serialize_struct(self, _name: &'static str, len: usize) -> Result<HashSerializer, RuntimeError> {
+        self.serialize_map(Some(len))
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        _index: u32,
+        variant: &'static str,
+        len: usize,
+    ) -> Result<VariantSerializer<HashSerializer>, RuntimeError> {
+        Ok(VariantSerializer {
+            variant,
+            contents: self.serialize_map(Some(len))?,
+        })
+    }
+}
+
+struct ArraySerializer {
+    items: Vec<Object>,
+}
+
+impl SerializeSeq for ArraySerializer {
+    type Ok = Object;
+    type Error = RuntimeError;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), RuntimeError> {
+        self.items.push(value.serialize(ObjectSerializer)?);
+
+        Ok(())
+    }
+
+    fn end(self) -> Result<Object, RuntimeError> {
+        Ok(Object::Array(self.items))
+    }
+}
+
+impl ser::SerializeTuple for ArraySerializer {
+    type Ok = Object;
+    type Error = RuntimeError;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), RuntimeError> {
+        SerializeSeq::serialize_element(self, value)
+    }
+
+    fn end(self) -> Result<Object, RuntimeError> {
+        SerializeSeq::end(self)
+    }
+}
+
+impl ser::SerializeTupleStruct for ArraySerializer {
+    type Ok = Object;
+    type Error = RuntimeError;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), RuntimeError> {
+        SerializeSeq::serialize_element(self, value)
+    }
+
+    fn end(self) -> Result<Object, RuntimeError> {
+        SerializeSeq::end(self)
+    }
+}
+
+struct HashSerializer {
+    pairs: OrderedHash,
+    // the key of `serialize_key` waiting for its value
+    key: Option<Object>,
+}
+
+impl SerializeMap for HashSerializer {
+    type Ok = Object;
+    type Error = RuntimeError;
+
+    fn serialize_key<T: Serialize + ?Sized>(&mut self, key: &T) -> Result<(), RuntimeError> {
+        self.key = Some(key.serialize(ObjectSerializer)?);
+
+        Ok(())
+    }
+
+    fn serialize_value<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), RuntimeError> {
+        let key = self.key.take().unwrap_or(Object::Null);
+        insert(&mut self.pairs, key, value.serialize(ObjectSerializer)?)?;
+
+        Ok(())
+    }
+
+    fn end(self) -> Result<Object, RuntimeError> {
+        Ok(Object::Hash(self.pairs))
+    }
+}
+
+impl ser::SerializeStruct for HashSerializer {
+    type Ok = Object;
+    type Error = RuntimeError;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, key: &'static str, value: &T) -> Result<(), RuntimeError> {
+        SerializeMap::serialize_entry(self, key, value)
+    }
+
+    fn end(self) -> Result<Object, RuntimeError> {
+        SerializeMap::end(self)
+    }
+}
+
+// the contents of a tuple or struct variant, wrapped in a hash under the name of the variant
+struct VariantSerializer<S> {
+    variant: &'static str,
+    contents: S,
+}
+
+impl ser::SerializeTupleVariant for VariantSerializer<ArraySerializer> {
+    type Ok = Object;
+    type Error = RuntimeError;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), RuntimeError> {
+        SerializeSeq::serialize_element(&mut self.contents, value)
+    }
+
+    fn end(self) -> Result<Object, RuntimeError> {
+        Ok(variant_hash(self.variant, SerializeSeq::end(self.contents)?))
+    }
+}
+
+impl ser::SerializeStructVariant for VariantSerializer<HashSerializer> {
+    type Ok = Object;
+    type Error = RuntimeError;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, key: &'static str, value: &T) -> Result<(), RuntimeError> {
+        SerializeMap::serialize_entry(&mut self.contents, key, value)
+    }
+
+    fn end(self) -> Result<Object, RuntimeError> {
+        Ok(variant_hash(self.variant, SerializeMap::end(self.contents)?))
+    }
+}
+
+#[cfg(test)]
+mod unit_test {
+    use std::collections::BTreeMap;
+
+    use serde::ser::{Serialize, SerializeStruct, Serializer};
+
+    use crate::object::{BuiltinFunction, Object, RuntimeError};
+    use crate::serialize::{from_object, to_object};
+
+    // what `#[derive(Serialize)]` writes for a struct
+    struct Point {
+        x: i64,
+        y: Option<f64>,
+    }
+
+    impl Serialize for Point {
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            let mut point = serializer.serialize_struct("Point", 2)?;
+            point.serialize_field("x", &self.x)?;
+            point.serialize_field("y", &self.y)?;
+            point.end()
+        }
+    }
+
+    fn string(string: &str) -> Object {
+        Object::String(string.to_string())
+    }
+
+    fn hash(pairs: Vec<(Object, Object)>) -> Object {
+        Object::Hash(pairs.into_iter().collect())
+    }
+
+    #[test]
+    fn serde_to_object() {
+        assert_eq!(
+            to_object(&Point { x: -3, y: Some(0.5) }),
+            Ok(hash(vec![
+                (string("x"), Object::Integer(-3)),
+                (string("y"), Object::Float(0.5))
+            ]))
+        );
+
+        let mut tags = BTreeMap::new();
+        tags.insert("b", vec![(1u8, 'c')]);
+        tags.insert("a", vec![]);
+        assert_eq!(
+            to_object(&tags),
+            Ok(hash(vec![
+                (string("a"), Object::Array(vec![])),
+                (
+                    string("b"),
+                    Object::Array(vec![Object::Array(vec![Object::Integer(1), string("c")])])
+                ),
+            ]))
+        );
+
+        assert_eq!(
+            to_object(&Ok::<_, String>(true)),
+            Ok(hash(vec![(string("Ok"), Object::Boolean(true))]))
+        );
+        assert_eq!(
+            to_object(&u64::MAX),
+            Err(RuntimeError::new("integer out of range: 18446744073709551615"))
+        );
+    }
+
+    #[test]
+    fn serde_from_object() {
+        let value = hash(vec![
+            (string("a"), Object::Array(vec![Object::Integer(1), Object::Integer(2)])),
+            (string("b"), Object::Array(vec![])),
+        ]);
+        let map: BTreeMap<String, Vec<u8>> = from_object(&value).unwrap();
+        assert_eq!(map["a"], vec![1, 2]);
+        assert!(map["b"].is_empty());
+
+        let value = Object::Array(vec![Object::Null, string("lynx"), Object::Float(2.5)]);
+        assert_eq!(
+            from_object::<(Option<i64>, String, f64)>(&value),
+            Ok((None, String::from("lynx"), 2.5))
+        );
+
+        let value = hash(vec![(string("Err"), string("failed"))]);
+        assert_eq!(
+            from_object::<Result<u64, String>>(&value),
+            Ok(Err(String::from("failed")))
+        );
+
+        assert_eq!(
+            from_object::<u8>(&Object::Integer(-1)),
+            Err(RuntimeError::new("invalid value: integer `-1`, expected u8"))
+        );
+        assert_eq!(
+            from_object::<(u64, u64)>(&Object::Array(vec![Object::Integer(1); 3])),
+            Err(RuntimeError::new("invalid length 3, expected 2 elements in sequence"))
+        );
+    }
+
+    #[test]
+    fn serde_object_round_trip() {
+        let value = hash(vec![
+            (string("name"), string("lynx")),
+            (
+                string("tags"),
+                Object::Array(vec![
+                    Object::Integer(-1),
+                    Object::Float(2.5),
+                    Object::Boolean(true),
+                    Object::Null,
+                ]),
+            ),
+            (Object::Integer(1), hash(vec![])),
+        ]);
+
+        assert_eq!(to_object(&value), Ok(value.clone()));
+        assert_eq!(from_object::<Object>(&value), Ok(value));
+    }
+
+    #[test]
+    fn serde_functions_fail() {
+        let builtin = Object::Builtin(BuiltinFunction::new("first", Some(1), |_, arguments| {
+            Ok(arguments[0].clone())
+        }));
+        let error = RuntimeError::new("can not convert function to serde data");
+
+        assert_eq!(
+            to_object(&Object::Array(vec![Object::Null, builtin.clone()])),
+            Err(error.clone())
+        );
+        assert_eq!(from_object::<Object>(&builtin), Err(error.clone()));
+        assert_eq!(from_object::<Vec<u64>>(&Object::Array(vec![builtin])), Err(error));
+    }
+}
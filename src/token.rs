@@ -38,7 +38,9 @@ pub enum TokenType {
     // Literals
     IDENTIFIER(String),
     STRING(String),
-    NUMBER(usize),
+    NUMBER(i64),
+    // the digits as written, a float is neither `Eq` nor `Hash`
+    FLOAT(String),
 
     // Keywords.
     TRUE,
@@ -50,7 +52,7 @@ pub enum TokenType {
     WHILE,
     BREAK,
     RETURN,
-        
+
     // End of File
     EOF,
 }
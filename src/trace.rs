@@ -0,0 +1,109 @@
+use std::fmt;
+use std::io::{self, Write};
+use std::rc::Rc;
+
+// How much detail an event carries, a tracer keeps the events up to its level.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Level {
+    // a program starts or fails
+    Info,
+    // a whole program or environment
+    Debug,
+    // every token, expression and call
+    Trace,
+}
+
+// The part of the interpreter an event comes from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stage {
+    Token,
+    Parse,
+    Eval,
+}
+
+impl fmt::Display for Level {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Level::Info => write!(f, "info"),
+            Level::Debug => write!(f, "debug"),
+            Level::Trace => write!(f, "trace"),
+        }
+    }
+}
+
+impl fmt::Display for Stage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Stage::Token => write!(f, "token"),
+            Stage::Parse => write!(f, "parse"),
+            Stage::Eval => write!(f, "eval"),
+        }
+    }
+}
+
+// Receives the events of a parser or evaluator it is set on, one tracer can be shared by both.
+// Nothing is traced by default, and events above `level` are not even formatted.
+pub trait Tracer {
+    fn level(&self) -> Level {
+        Level::Trace
+    }
+
+    fn event(&self, level: Level, stage: Stage, message: &str);
+}
+
+impl<F: Fn(Level, Stage, &str)> Tracer for F {
+    fn event(&self, level: Level, stage: Stage, message: &str) {
+        self(level, stage, message)
+    }
+}
+
+// writes every event up to `level` as a line like `[parse trace] ...` to stderr
+#[derive(Debug, Clone, Copy)]
+pub struct StderrTracer {
+    level: Level,
+}
+
+impl StderrTracer {
+    pub fn new(level: Level) -> Self {
+        StderrTracer { level }
+    }
+}
+
+impl Tracer for StderrTracer {
+    fn level(&self) -> Level {
+        self.level
+    }
+
+    fn event(&self, level: Level, stage: Stage, message: &str) {
+        writeln!(io::stderr(), "[{} {}] {}", stage, level, message);
+    }
+}
+
+// the tracer of a parser or evaluator, if any
+#[derive(Clone, Default)]
+pub(crate) struct Trace {
+    tracer: Option<Rc<dyn Tracer>>,
+}
+
+impl Trace {
+    pub(crate) fn set(&mut self, tracer: Rc<dyn Tracer>) {
+        self.tracer = Some(tracer);
+    }
+
+    pub(crate) fn emit(&self, level: Level, stage: Stage, message: impl FnOnce() -> String) {
+        if let Some(tracer) = &self.tracer {
+            if level <= tracer.level() {
+                tracer.event(level, stage, &message());
+            }
+        }
+    }
+}
+
+impl fmt::Debug for Trace {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.tracer {
+            Some(_) => write!(f, "Trace(on)"),
+            None => write!(f, "Trace(off)"),
+        }
+    }
+}
@@ -2,12 +2,8 @@
 
 pub fn is_white_space(c: char) -> bool {
     let chars = " \n\t\r";
-    let result = chars.find(c);
 
-    match result {
-        Some(pos) => pos > 0,
-        None => false,
-    }
+    chars.contains(c)
 }
 
 pub fn is_line_break(c: char) -> bool {
@@ -0,0 +1,766 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt;
+use std::rc::Rc;
+
+use crate::builtin::make_builtin;
+use crate::compiler::{Bytecode, CompiledFunction, Opcode};
+use crate::env::Env;
+use crate::evaluator::{
+    call_depth_error, eval_infix_objects, eval_prefix_object, get_property, Evaluator, DEFAULT_MAX_CALL_DEPTH,
+    OUT_OF_FUEL,
+};
+use crate::object::Object;
+use crate::ordered_hash::OrderedHash;
+use crate::token::TokenType;
+
+// a function value of the vm, the compiled code plus the variables it captured
+pub struct Closure {
+    pub function: Rc<CompiledFunction>,
+    pub upvalues: Vec<Rc<RefCell<Upvalue>>>,
+}
+
+// compared by identity like `Object::Function` closures sharing one env
+impl PartialEq for Closure {
+    fn eq(&self, other: &Self) -> bool {
+        std::ptr::eq(self, other)
+    }
+}
+
+// captured values can refer back to the closure, only print the name
+impl fmt::Debug for Closure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("Closure").field(&self.function.display_name()).finish()
+    }
+}
+
+// a captured variable points into the stack while its frame or loop iteration is alive,
+// afterwards it owns the value
+#[derive(Debug)]
+pub enum Upvalue {
+    Open(usize),
+    Closed(Object),
+}
+
+struct Frame {
+    closure: Rc<Closure>,
+    ip: usize,
+    // stack index of the first slot, the callee sits right below
+    base: usize,
+}
+
+impl Frame {
+    fn read_u8(&mut self) -> u8 {
+        let byte = self.closure.function.code[self.ip];
+        self.ip += 1;
+
+        byte
+    }
+
+    fn read_u16(&mut self) -> usize {
+        let code = &self.closure.function.code;
+        let value = u16::from_be_bytes([code[self.ip], code[self.ip + 1]]);
+        self.ip += 2;
+
+        value as usize
+    }
+}
+
+// Stack based vm for the bytecode of `Compiler`.
+//
+// Every call frame owns `num_slots` fixed slots for its parameters and locals, temporaries go on
+// top of them. Results and error messages are the same as the ones of `Evaluator::eval_program`.
+// Reference cycles between closures are not collected.
+//
+// Builtins run on a host evaluator, closures they call back like the one of `map` run on the
+// frames of the program.
+//
+// With fuel every executed instruction costs one unit. Running out pauses the program with
+// `OUT_OF_FUEL`, after `add_fuel` the next `resume` continues where it stopped. Running out
+// inside a callback can not pause and ends the program.
+pub struct Vm {
+    machine: Machine,
+    builtins: HashMap<String, Object>,
+    // builtins run with an evaluator as context, the vm lends them this one
+    host: Evaluator,
+}
+
+// The state of a running program. While a builtin runs it is lent to the host evaluator, which
+// calls closures back on it with `Machine::call`.
+#[derive(Default)]
+pub(crate) struct Machine {
+    stack: Vec<Object>,
+    frames: Vec<Frame>,
+    globals: Vec<Option<Object>>,
+    global_names: Vec<String>,
+    open_upvalues: Vec<Rc<RefCell<Upvalue>>>,
+    fuel: Option<u64>,
+    // the current frame of a program that ran out of fuel is the last one of `frames`
+    paused: bool,
+    // closures called back from builtins that are still running
+    callbacks: usize,
+    max_call_depth: usize,
+}
+
+impl Default for Vm {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Vm {
+    pub fn new() -> Self {
+        Vm {
+            machine: Machine {
+                max_call_depth: DEFAULT_MAX_CALL_DEPTH,
+                ..Machine::default()
+            },
+            builtins: make_builtin(),
+            host: Evaluator::new(Rc::new(RefCell::new(Env::new()))),
+        }
+    }
+
+    // builtins run on the host evaluator and draw their random numbers from it
+    pub fn set_seed(&mut self, seed: u64) {
+        self.host.set_seed(seed);
+    }
+
+    pub fn set_stdout(&mut self, stdout: impl std::io::Write + 'static) {
+        self.host.set_stdout(stdout);
+    }
+
+    pub fn set_stderr(&mut self, stderr: impl std::io::Write + 'static) {
+        self.host.set_stderr(stderr);
+    }
+
+    pub fn set_stdin(&mut self, stdin: impl std::io::BufRead + 'static) {
+        self.host.set_stdin(stdin);
+    }
+
+    // the vm does not nest calls on the native stack, it has the limit of the evaluator so both
+    // fail on the same runaway recursion, tail calls do not count
+    pub fn set_max_call_depth(&mut self, max_call_depth: usize) {
+        self.machine.max_call_depth = max_call_depth;
+    }
+
+    pub fn max_call_depth(&self) -> usize {
+        self.machine.max_call_depth
+    }
+
+    pub fn set_fuel(&mut self, fuel: Option<u64>) {
+        self.machine.fuel = fuel;
+    }
+
+    pub fn fuel(&self) -> Option<u64> {
+        self.machine.fuel
+    }
+
+    pub fn add_fuel(&mut self, fuel: u64) {
+        if let Some(remaining) = &mut self.machine.fuel {
+            *remaining = remaining.saturating_add(fuel);
+        }
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.machine.paused
+    }
+
+    // continue a program that ran out of fuel, `None` when there is none
+    pub fn resume(&mut self) -> Option<Object> {
+        if !self.machine.paused {
+            return None;
+        }
+
+        self.machine.paused = false;
+
+        let frame = self.machine.frames.pop()?;
+
+        self.finish(frame)
+    }
+
+    pub fn run(&mut self, bytecode: &Bytecode) -> Option<Object> {
+        // globals of earlier programs keep their values
+        for name in bytecode.global_names.iter().skip(self.machine.globals.len()) {
+            self.machine.globals.push(self.builtins.get(name).cloned());
+            self.machine.global_names.push(name.clone());
+        }
+
+        let closure = Rc::new(Closure {
+            function: Rc::clone(&bytecode.main),
+            upvalues: vec![],
+        });
+
+        // a paused program is dropped
+        self.machine.paused = false;
+        self.machine.stack.clear();
+        self.machine.frames.clear();
+        self.machine.open_upvalues.clear();
+
+        self.machine.stack.push(Object::Closure(Rc::clone(&closure)));
+        self.machine.stack.resize(1 + closure.function.num_slots, Object::Null);
+
+        let frame = Frame {
+            closure,
+            ip: 0,
+            base: 1,
+        };
+
+        self.finish(frame)
+    }
+
+    fn finish(&mut self, frame: Frame) -> Option<Object> {
+        let value = match self.machine.execute(&mut self.host, frame, 0) {
+            Ok(value) => value,
+            Err(message) => Some(Object::Error(message)),
+        };
+
+        if !self.machine.paused {
+            self.machine.stack.clear();
+            self.machine.frames.clear();
+            self.machine.open_upvalues.clear();
+        }
+
+        value
+    }
+}
+
+impl Machine {
+    // runs until the frame at `floor` returns, the frames below belong to the code that called
+    // back into a closure
+    fn execute(&mut self, host: &mut Evaluator, mut frame: Frame, floor: usize) -> Result<Option<Object>, String> {
+        loop {
+            match &mut self.fuel {
+                Some(0) if self.callbacks == 0 => {
+                    self.frames.push(frame);
+                    self.paused = true;
+
+                    return Err(String::from(OUT_OF_FUEL));
+                }
+                Some(0) => return Err(String::from(OUT_OF_FUEL)),
+                Some(remaining) => *remaining -= 1,
+                None => {}
+            }
+
+            let byte = frame.read_u8();
+            let op = Opcode::from_byte(byte).ok_or_else(|| format!("unknown opcode: {}", byte))?;
+
+            match op {
+                Opcode::Constant => {
+                    let index = frame.read_u16();
+                    self.stack.push(frame.closure.function.constants[index].clone());
+                }
+                Opcode::Null => self.stack.push(Object::Null),
+                Opcode::True => self.stack.push(Object::Boolean(true)),
+                Opcode::False => self.stack.push(Object::Boolean(false)),
+                Opcode::Pop => {
+                    self.pop();
+                }
+                Opcode::GetGlobal => {
+                    let index = frame.read_u16();
+
+                    match &self.globals[index] {
+                        Some(value) => self.stack.push(value.clone()),
+                        None => return Err(format!("no identifier found: {}", self.global_names[index])),
+                    }
+                }
+                Opcode::SetGlobal => {
+                    let index = frame.read_u16();
+
+                    if self.globals[index].is_none() {
+                        return Err(format!(
+                            "assignment to undefined variable: {}",
+                            self.global_names[index]
+                        ));
+                    }
+
+                    self.globals[index] = Some(self.peek().clone());
+                }
+                Opcode::DefineGlobal => {
+                    let index = frame.read_u16();
+                    self.globals[index] = Some(self.pop());
+                }
+                Opcode::GetLocal => {
+                    let slot = frame.base + frame.read_u16();
+                    self.stack.push(self.stack[slot].clone());
+                }
+                Opcode::SetLocal => {
+                    let slot = frame.base + frame.read_u16();
+                    self.stack[slot] = self.peek().clone();
+                }
+                Opcode::GetUpvalue => {
+                    let index = frame.read_u16();
+
+                    let value = match &*frame.closure.upvalues[index].borrow() {
+                        Upvalue::Open(slot) => self.stack[*slot].clone(),
+                        Upvalue::Closed(value) => value.clone(),
+                    };
+
+                    self.stack.push(value);
+                }
+                Opcode::SetUpvalue => {
+                    let index = frame.read_u16();
+                    let value = self.peek().clone();
+
+                    match &mut *frame.closure.upvalues[index].borrow_mut() {
+                        Upvalue::Open(slot) => self.stack[*slot] = value,
+                        Upvalue::Closed(closed) => *closed = value,
+                    }
+                }
+                Opcode::Add
+                | Opcode::Sub
+                | Opcode::Mul
+                | Opcode::Div
+                | Opcode::Equal
+                | Opcode::NotEqual
+                | Opcode::Less
+                | Opcode::LessEqual
+                | Opcode::Greater
+                | Opcode::GreaterEqual => {
+                    let right = self.pop();
+                    let left = self.pop();
+
+                    match eval_infix_objects(&op.operator().unwrap(), left, right) {
+                        Object::Error(message) => return Err(message),
+                        value => self.stack.push(value),
+                    }
+                }
+                Opcode::Not => {
+                    let value = self.pop();
+                    self.stack.push(Object::Boolean(!value.is_truthy()));
+                }
+                Opcode::Negate => match eval_prefix_object(&TokenType::MINUS, self.pop()) {
+                    Object::Error(message) => return Err(message),
+                    value => self.stack.push(value),
+                },
+                Opcode::Jump => {
+                    frame.ip = frame.read_u16();
+                }
+                Opcode::JumpIfFalse => {
+                    let target = frame.read_u16();
+
+                    if !self.pop().is_truthy() {
+                        frame.ip = target;
+                    }
+                }
+                Opcode::Array => {
+                    let count = frame.read_u16();
+                    let elements = self.stack.split_off(self.stack.len() - count);
+
+                    self.stack.push(Object::Array(elements));
+                }
+                Opcode::Hash => {
+                    let count = frame.read_u16();
+                    let mut hash = OrderedHash::new();
+                    let mut pairs = self.stack.split_off(self.stack.len() - count * 2).into_iter();
+
+                    while let (Some(key), Some(value)) = (pairs.next(), pairs.next()) {
+                        if !key.is_hashable() {
+                            return Err(key.unhashable_error());
+                        }
+
+                        hash.insert(key, value);
+                    }
+
+                    self.stack.push(Object::Hash(hash));
+                }
+                Opcode::Call | Opcode::TailCall => {
+                    let count = frame.read_u8() as usize;
+                    let callee_slot = self.stack.len() - count - 1;
+                    let callee = self.stack[callee_slot].clone();
+
+                    match callee {
+                        // the callee and its arguments take the place of the current frame
+                        Object::Closure(closure) if op == Opcode::TailCall => {
+                            self.close_upvalues(frame.base);
+                            self.stack.drain(frame.base - 1..callee_slot);
+                            frame = self.enter(closure, count);
+                        }
+                        Object::Closure(closure) => {
+                            self.check_call_depth(Some(&frame), &closure)?;
+
+                            let callee_frame = self.enter(closure, count);
+                            self.frames.push(std::mem::replace(&mut frame, callee_frame));
+                        }
+                        Object::Builtin(builtin) => {
+                            let arguments = self.stack.split_off(self.stack.len() - count);
+                            self.pop();
+
+                            let (caller, value) = self.lend(host, frame, |host| builtin.call(host, &arguments));
+                            frame = caller;
+
+                            match value {
+                                Object::Error(message) => return Err(message),
+                                value => self.stack.push(value),
+                            }
+                        }
+                        other => return Err(format!("not a function: {:?}", other)),
+                    }
+                }
+                Opcode::Invoke => {
+                    let index = frame.read_u16();
+                    let method = match &frame.closure.function.constants[index] {
+                        Object::String(method) => method.clone(),
+                        other => return Err(format!("not a method name: {:?}", other)),
+                    };
+                    let count = frame.read_u8() as usize;
+
+                    let arguments = self.stack.split_off(self.stack.len() - count);
+                    let receiver = self.pop();
+
+                    let (caller, value) =
+                        self.lend(host, frame, |host| host.call_method(receiver, &method, &arguments));
+                    frame = caller;
+
+                    match value {
+                        Object::Error(message) => return Err(message),
+                        value => self.stack.push(value),
+                    }
+                }
+                Opcode::GetProperty => {
+                    let index = frame.read_u16();
+                    let receiver = self.pop();
+
+                    match get_property(&receiver, &frame.closure.function.constants[index]) {
+                        Object::Error(message) => return Err(message),
+                        value => self.stack.push(value),
+                    }
+                }
+                Opcode::Closure => {
+                    let index = frame.read_u16();
+
+                    let function = match &frame.closure.function.constants[index] {
+                        Object::CompiledFunction(function) => Rc::clone(function),
+                        other => return Err(format!("not a function: {:?}", other)),
+                    };
+
+                    let mut upvalues = Vec::with_capacity(function.upvalue_count);
+
+                    for _ in 0..function.upvalue_count {
+                        let is_local = frame.read_u8() == 1;
+                        let index = frame.read_u16();
+
+                        if is_local {
+                            upvalues.push(self.capture_upvalue(frame.base + index));
+                        } else {
+                            upvalues.push(Rc::clone(&frame.closure.upvalues[index]));
+                        }
+                    }
+
+                    self.stack
+                        .push(Object::Closure(Rc::new(Closure { function, upvalues })));
+                }
+                Opcode::CloseUpvalues => {
+                    let slot = frame.read_u16();
+                    self.close_upvalues(frame.base + slot);
+                }
+                Opcode::Return => {
+                    let value = self.pop();
+
+                    self.close_upvalues(frame.base);
+                    self.stack.truncate(frame.base - 1);
+
+                    if self.frames.len() == floor {
+                        return Ok(Some(value));
+                    }
+
+                    if let Some(caller) = self.frames.pop() {
+                        frame = caller;
+                        self.stack.push(value);
+                    }
+                }
+                Opcode::ReturnNone => return Ok(None),
+            }
+        }
+    }
+
+    // a frame for a closure whose arguments are on top of the stack, missing arguments are null
+    // and extra ones are dropped
+    fn enter(&mut self, closure: Rc<Closure>, count: usize) -> Frame {
+        let base = self.stack.len() - count;
+        self.stack.truncate(base + closure.function.parameters.len().min(count));
+        self.stack.resize(base + closure.function.num_slots, Object::Null);
+
+        Frame { closure, ip: 0, base }
+    }
+
+    // the host can call closures back while it runs `call`, they run on top of `frame`
+    fn lend(
+        &mut self,
+        host: &mut Evaluator,
+        frame: Frame,
+        call: impl FnOnce(&mut Evaluator) -> Object,
+    ) -> (Frame, Object) {
+        self.frames.push(frame);
+        host.machine = Some(std::mem::take(self));
+
+        let value = call(host);
+
+        if let Some(machine) = host.machine.take() {
+            *self = machine;
+        }
+
+        (self.frames.pop().unwrap(), value)
+    }
+
+    // calls nest as deep as in the evaluator, the frame of the program at the bottom is no call
+    fn check_call_depth(&self, current: Option<&Frame>, callee: &Closure) -> Result<(), String> {
+        if self.frames.len() + current.is_some() as usize <= self.max_call_depth {
+            return Ok(());
+        }
+
+        let mut call_stack = self
+            .frames
+            .iter()
+            .chain(current)
+            .skip(1)
+            .map(|frame| frame.closure.function.display_name().to_string())
+            .collect::<Vec<_>>();
+        call_stack.push(callee.function.display_name().to_string());
+
+        Err(call_depth_error(self.max_call_depth, &call_stack))
+    }
+
+    // runs a closure for a builtin on top of the frames of the program
+    pub(crate) fn call(
+        &mut self,
+        host: &mut Evaluator,
+        closure: Rc<Closure>,
+        arguments: Vec<Object>,
+    ) -> Result<Object, String> {
+        self.check_call_depth(None, &closure)?;
+
+        let height = self.stack.len();
+        let floor = self.frames.len();
+
+        self.stack.push(Object::Closure(Rc::clone(&closure)));
+        let count = arguments.len();
+        self.stack.extend(arguments);
+        let frame = self.enter(closure, count);
+
+        self.callbacks += 1;
+        let value = self.execute(host, frame, floor);
+        self.callbacks -= 1;
+
+        // an error leaves the frames of the callback behind
+        if value.is_err() {
+            self.close_upvalues(height);
+            self.stack.truncate(height);
+            self.frames.truncate(floor);
+        }
+
+        value.map(|value| value.unwrap_or(Object::Null))
+    }
+
+    fn pop(&mut self) -> Object {
+        self.stack.pop().unwrap_or(Object::Null)
+    }
+
+    fn peek(&self) -> &Object {
+        self.stack.last().unwrap_or(&Object::Null)
+    }
+
+    // closures capturing the same variable share one upvalue
+    fn capture_upvalue(&mut self, slot: usize) -> Rc<RefCell<Upvalue>> {
+        for upvalue in &self.open_upvalues {
+            if matches!(*upvalue.borrow(), Upvalue::Open(open) if open == slot) {
+                return Rc::clone(upvalue);
+            }
+        }
+
+        let upvalue = Rc::new(RefCell::new(Upvalue::Open(slot)));
+        self.open_upvalues.push(Rc::clone(&upvalue));
+
+        upvalue
+    }
+
+    // move the values of the slots from `first_slot` on into their upvalues
+    fn close_upvalues(&mut self, first_slot: usize) {
+        let stack = &self.stack;
+
+        self.open_upvalues.retain(|upvalue| {
+            let slot = match *upvalue.borrow() {
+                Upvalue::Open(slot) => slot,
+                Upvalue::Closed(_) => return false,
+            };
+
+            if slot < first_slot {
+                return true;
+            }
+
+            *upvalue.borrow_mut() = Upvalue::Closed(stack[slot].clone());
+
+            false
+        });
+    }
+}
+
+#[cfg(test)]
+mod unit_test {
+    use crate::compiler::Compiler;
+    use crate::object::Object;
+    use crate::parser::Parser;
+    use crate::vm::Vm;
+
+    fn get_vm_val(input: &str) -> Option<Object> {
+        let program = Parser::get(input).parse_program();
+        let bytecode = Compiler::new().compile(program).unwrap();
+
+        Vm::new().run(&bytecode)
+    }
+
+    #[test]
+    fn vm_shared_upvalue() {
+        let value = get_vm_val(
+            r#"
+            fn pair() {
+                let count = 0;
+                let inc = fn() { count = count + 1; };
+                let get = fn() { count };
+                inc();
+                inc();
+                return get();
+            }
+            pair();
+        "#,
+        );
+
+        assert_eq!(value, Some(Object::Integer(2)));
+    }
+
+    #[test]
+    fn vm_closed_upvalue_per_iteration() {
+        let value = get_vm_val(
+            r#"
+            fn make() {
+                let fns = [];
+                let i = 0;
+                while (i < 3) {
+                    let j = i;
+                    fns = push(fns, fn() { j });
+                    i = i + 1;
+                }
+                return fns;
+            }
+            let fns = make();
+            [first(fns)(), last(fns)()];
+        "#,
+        );
+
+        assert_eq!(value, Some(Object::Array(vec![Object::Integer(0), Object::Integer(2)])));
+    }
+
+    #[test]
+    fn vm_break_pops_temporaries() {
+        let value = get_vm_val(
+            r#"
+            let i = 0;
+            let total = 0;
+            while (true) {
+                total = total + len([1, if (i == 3) { break; } else { i }]);
+                i = i + 1;
+            }
+            [i, total];
+        "#,
+        );
+
+        assert_eq!(value, Some(Object::Array(vec![Object::Integer(3), Object::Integer(6)])));
+    }
+
+    #[test]
+    fn vm_globals_between_runs() {
+        let mut compiler = Compiler::new();
+        let mut vm = Vm::new();
+
+        let program = Parser::get("let foo = 40;").parse_program();
+        assert_eq!(vm.run(&compiler.compile(program).unwrap()), None);
+
+        let program = Parser::get("foo + 2").parse_program();
+        assert_eq!(vm.run(&compiler.compile(program).unwrap()), Some(Object::Integer(42)));
+    }
+
+    #[test]
+    fn vm_fuel_pause_and_resume() {
+        let program = Parser::get(
+            r#"
+            fn add(x, y) { x + y }
+            let total = 0;
+            let i = 0;
+            while (i < 100) {
+                total = add(total, i);
+                i = i + 1;
+            }
+            total;
+        "#,
+        )
+        .parse_program();
+        let bytecode = Compiler::new().compile(program).unwrap();
+
+        let mut vm = Vm::new();
+        vm.set_fuel(Some(100));
+
+        assert_eq!(vm.run(&bytecode), Some(Object::Error(String::from("out of fuel"))));
+        assert!(vm.is_paused());
+        assert_eq!(vm.fuel(), Some(0));
+
+        let mut value = None;
+        while vm.is_paused() {
+            vm.add_fuel(100);
+            value = vm.resume();
+        }
+
+        assert_eq!(value, Some(Object::Integer(4950)));
+        assert_eq!(vm.resume(), None);
+    }
+
+    #[test]
+    fn vm_callback_out_of_fuel() {
+        let mut compiler = Compiler::new();
+        let mut vm = Vm::new();
+        vm.set_fuel(Some(50));
+
+        let program = Parser::get("let items = map(range(100), fn(x) { x * 2 });").parse_program();
+        assert_eq!(
+            vm.run(&compiler.compile(program).unwrap()),
+            Some(Object::Error(String::from("out of fuel")))
+        );
+        assert!(!vm.is_paused());
+
+        vm.set_fuel(None);
+        let program = Parser::get("reduce(map([1, 2], fn(x) { x + 1 }), fn(a, b) { a * b })").parse_program();
+        assert_eq!(vm.run(&compiler.compile(program).unwrap()), Some(Object::Integer(6)));
+    }
+
+    #[test]
+    fn vm_max_call_depth() {
+        let mut compiler = Compiler::new();
+        let mut vm = Vm::new();
+        vm.set_max_call_depth(5);
+        assert_eq!(vm.max_call_depth(), 5);
+
+        let program = Parser::get("fn f(n) { if (n == 0) { 0 } else { 1 + f(n - 1) } } f(4);").parse_program();
+        assert_eq!(vm.run(&compiler.compile(program).unwrap()), Some(Object::Integer(4)));
+
+        let program = Parser::get("f(5);").parse_program();
+        assert_eq!(
+            vm.run(&compiler.compile(program).unwrap()),
+            Some(Object::Error(String::from(
+                "maximum call depth of 5 exceeded, call stack: f (x6)"
+            )))
+        );
+
+        let program = Parser::get("let g = fn(n) { map([n], fn(x) { 1 + f(x) }) }; g(3);").parse_program();
+        assert_eq!(
+            vm.run(&compiler.compile(program).unwrap()),
+            Some(Object::Error(String::from(
+                "maximum call depth of 5 exceeded, call stack: g -> <anonymous> -> f (x4)"
+            )))
+        );
+
+        // tail calls take over the frame of their caller
+        let program = Parser::get("fn h(n) { if (n == 0) { \"done\" } else { h(n - 1) } } h(1000);").parse_program();
+        assert_eq!(
+            vm.run(&compiler.compile(program).unwrap()),
+            Some(Object::String(String::from("done")))
+        );
+    }
+}
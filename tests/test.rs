@@ -39,4 +39,29 @@ mod integration_test {
         //     assert_eq!(format!("{:?}", value), format!("{:?}", Some(Object::Integer(7))));
         assert_eq!(1, 1);
     }
+
+    #[test]
+    fn collect_closure_cycles_across_scripts() {
+        let mut evaluator = Evaluator::new(Rc::new(RefCell::new(Env::new())));
+
+        for _ in 0..2000 {
+            let program = Parser::get(
+                r#"
+                fn make_node() {
+                    let node = fn() { node };
+                    return node;
+                }
+                let node = make_node();
+            "#,
+            )
+            .parse_program();
+
+            evaluator.eval_program(program);
+        }
+
+        evaluator.collect_garbage();
+
+        // the global scope and the scope of the node bound last
+        assert_eq!(evaluator.heap_stats().live_envs, 2);
+    }
 }
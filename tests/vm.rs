@@ -0,0 +1,220 @@
+#[cfg(test)]
+mod integration_test {
+    use lynxlang::compiler::Compiler;
+    use lynxlang::env::Env;
+    use lynxlang::evaluator::Evaluator;
+    use lynxlang::object::Object;
+    use lynxlang::parser::Parser;
+    use lynxlang::vm::Vm;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    // programs of the evaluator test suite and some more, both engines have to agree on each
+    const PROGRAMS: [&str; 66] = [
+        "",
+        "12",
+        "true",
+        r#""foo_bar_123""#,
+        r#"{ "foo": "bar", 1: 2, 2: [1234, true, "Lynx programming language"], "abc": true };"#,
+        r#"[1234, true, "Lynx programming language", [1234, true, "Lynx programming language"]];"#,
+        r#"{ [1, 2]: "pair", { "a": 1 }: "record", [1, 2]: "again" };"#,
+        r#"{ "a": 1, len: 2 };"#,
+        "[1.5 + 2, math.sqrt(16), math.max(1, 2.5), math.PI > 3]",
+        "math.nope",
+        "foo;",
+        "let foo = 123 + 4; foo;",
+        "let foo = 123 + 4;",
+        "return !false;",
+        "let foo = 123; return foo;",
+        "!false",
+        "-5",
+        "1 + 2 + 3 + 4 / 2 * 3",
+        "(7 + 2) / 3",
+        r#"1 <= 2 == true != ("a" + "b" == "ab")"#,
+        "1 / 0",
+        "1 - 2",
+        r#"1 + "a""#,
+        "if (false) { false; } else { true; }",
+        "if (false) { 1 }",
+        "if (true) { let x = 1; }",
+        r#"let foo = 123; if (foo) { let bar = "stuff"; return bar; } else { return 5; }"#,
+        r#"
+            fn bar(foo, stuff, var) {
+                let another_bar = stuff;
+                return another_bar + foo + var;
+            }
+            let value = bar(1, 2, 3);
+            value;
+        "#,
+        "fn fibonacci(x) { if (x == 0) { return 0; } if (x == 1) { return 1; } fibonacci(x - 1) + fibonacci(x - 2) } fibonacci(15);",
+        "[first([1, 2, 3]), last([1, 2, 3]), rest([1, 2, 3]), len([1, 2, 3]), push([1], 2), unshift([1], 2)]",
+        r#"
+            fn counter() {
+                let count = 0;
+                return fn() {
+                    count = count + 1;
+                    return count;
+                };
+            }
+            let foo = counter();
+            let bar = counter();
+            foo();
+            foo();
+            bar();
+            [foo(), bar()];
+        "#,
+        r#"
+            fn make() {
+                let value = 1;
+                let get = fn() { value };
+                let value = 2;
+                return [get(), value];
+            }
+            make();
+        "#,
+        r#"
+            let getters = [];
+            let i = 0;
+            while (i < 3) {
+                let captured = i;
+                getters = push(getters, fn() { captured });
+                i = i + 1;
+            }
+            [first(getters)(), last(getters)()];
+        "#,
+        r#"
+            fn outer() {
+                let sum = fn(x) {
+                    if (x == 0) {
+                        return 0;
+                    }
+                    return x + sum(x - 1);
+                };
+                return sum;
+            }
+            outer()(10);
+        "#,
+        r#"
+            fn parity(n) {
+                fn is_even(x) { if (x == 0) { return true; } return is_odd(x - 1); }
+                fn is_odd(x) { if (x == 0) { return false; } return is_even(x - 1); }
+                return [is_even(n), is_odd(n)];
+            }
+            parity(7);
+        "#,
+        "foo = 1;",
+        "3(1)",
+        "let text = \"lynx\"; text.upper(1 + 1)",
+        r#"
+            let i = 0;
+            let found = while (true) {
+                if (i == 5) { break; }
+                i = i + 1;
+            };
+            [i, found, fn(a, b) { [a, b] }(1)];
+        "#,
+        r#"
+            let total = 0;
+            fn add(x) { total = total + x; }
+            add(1);
+            add(2);
+            if (total > 2) { total } else { 0 }
+        "#,
+        "[-5, -1.5, --3, -(2 - 7)]",
+        "let x = 2.5; let f = fn(n) { -n }; [f(x), f(4), -7 / 2]",
+        r#"[math.floor(-2.5), math.abs(1 - 5), math.pow(-2, 3), to_int("-5"), range(2, -3, -2)]"#,
+        "-(-9223372036854775807 - 1)",
+        "map([1, 2, 3], fn(x) { x * 2 })",
+        r#"
+            fn scaled(items, factor) {
+                let bigger = filter(items, fn(x) { x > factor });
+                return map(bigger, fn(x) { x * factor });
+            }
+            [scaled([1, 5, 2, 7], 2), reduce([1, 2, 3], fn(total, x) { total + x }, 10)]
+        "#,
+        r#"
+            let words = ["lynx", "a", "cat"];
+            [sort([3, 1, 2], fn(a, b) { a > b }), sort_by(words, len), find_index(words, fn(w) { len(w) == 1 })]
+        "#,
+        r#"
+            let seen = [];
+            let log = fn(x) { seen = push(seen, x); x };
+            [any(map([1, 2], log), fn(x) { all([x], fn(y) { y > 1 }) }), seen]
+        "#,
+        "map([1, 2], fn(x) { x / (x - 2) })",
+        "map(1, fn(x) { x })",
+        "break;",
+        "let i = 0; if (i == 0) { break; }",
+        "fn stop(x) { if (x) { break; } 1 } [stop(true), stop(false)]",
+        "str(fn(x) { x })",
+        "fn add(a, b) { a + b } str([add, fn() { add }, repr(fn(first, second) { first })])",
+        "fn f(n) { if (n == 0) { 0 } else { 1 + f(n - 1) } } f(100000);",
+        "let f = fn(n) { if (n == 0) { return 0; } return 1 + f(n - 1); }; f(300);",
+        "fn f(n, total) { if (n == 0) { total } else { f(n - 1, total + n) } } f(100000, 0);",
+        "fn f(n) { while (true) { return g(n); } } fn g(n) { if (n > 0) { f(n - 1) } else { \"done\" } } f(100000);",
+        "fn f(n) { if (n > 0) { map([n], fn(x) { f(x - 1) }) } else { 0 } } f(300);",
+        "fn f() { 1 } [f == f, f != f, len(unique([f, f]))]",
+        "fn counter() { let count = 0; fn() { count = count + 1 } } [counter() == counter()]",
+        "fn f() { let g = fn() { h() }; let h = fn() { 1 }; g() } f();",
+        "fn f() { let even = fn(n) { if (n == 0) { true } else { odd(n - 1) } }; let odd = fn(n) { if (n == 0) { false } else { even(n - 1) } }; [even(10), odd(7)] } f();",
+        "fn f() { let x = 1; let get = fn() { x }; let x = 2; [get(), x] } f();",
+        "fn f() { let gets = []; let i = 0; while (i < 3) { let get = fn() { later }; let later = i * 10; gets = gets.push(get); i = i + 1; } map(gets, fn(get) { get() }) } f();",
+    ];
+
+    fn get_eval_val(input: &str) -> Option<Object> {
+        let program = Parser::get(input).parse_program();
+
+        let mut evaluator = Evaluator::new(Rc::new(RefCell::new(Env::new())));
+
+        evaluator.builtin();
+
+        evaluator.eval_program(program)
+    }
+
+    fn get_vm_val(input: &str) -> Option<Object> {
+        let program = Parser::get(input).parse_program();
+        match Compiler::new().compile(program) {
+            Ok(bytecode) => Vm::new().run(&bytecode),
+            Err(message) => Some(Object::Error(message)),
+        }
+    }
+
+    #[test]
+    fn vm_matches_evaluator() {
+        for input in PROGRAMS {
+            assert_eq!(get_vm_val(input), get_eval_val(input), "{}", input);
+        }
+    }
+
+    #[test]
+    fn vm_fibonacci() {
+        assert_eq!(
+            get_vm_val(
+                r#"
+                fn fibonacci(x) {
+                    if (x < 2) {
+                        return x;
+                    }
+                    return fibonacci(x - 1) + fibonacci(x - 2);
+                }
+                fibonacci(20);
+            "#
+            ),
+            Some(Object::Integer(6765))
+        );
+    }
+
+    #[test]
+    fn deep_recursion_matches_evaluator() {
+        let input = r#"
+            fn count_down(n) {
+                if (n == 0) { return "done"; }
+                return count_down(n - 1);
+            }
+            count_down(100000);
+        "#;
+
+        assert_eq!(get_vm_val(input), Some(Object::String(String::from("done"))));
+        assert_eq!(get_eval_val(input), get_vm_val(input));
+    }
+}